@@ -82,12 +82,26 @@ pub struct ImportFunction {
     pub js_ret: Option<syn::Type>,
     pub catch: bool,
     pub variadic: bool,
+    pub slices: SliceMode,
     pub structural: bool,
     pub kind: ImportFunctionKind,
     pub shim: Ident,
     pub doc_comment: Option<String>,
 }
 
+/// How slices passed from Rust to an imported function are given to JS.
+#[cfg_attr(feature = "extra-traits", derive(Debug, PartialEq, Eq))]
+#[derive(Copy, Clone)]
+pub enum SliceMode {
+    /// A view of wasm memory, which is detached if memory grows.
+    View,
+    /// A copy of the slice.
+    Copy,
+    /// A copy of the slice, which is written back to mutable slices after
+    /// the call.
+    CopyInOut,
+}
+
 #[cfg_attr(feature = "extra-traits", derive(Debug, PartialEq, Eq))]
 #[derive(Clone)]
 pub enum ImportFunctionKind {
@@ -285,8 +299,32 @@ impl Function {
     fn shared(&self) -> shared::Function {
         shared::Function {
             name: self.name.to_string(),
+            location: self.location(),
         }
     }
+
+    /// Source locations of spans are only available from `proc-macro2` when
+    /// compiling with `--cfg procmacro2_semver_exempt` on nightly, so
+    /// otherwise the location is simply left out and `wasm-bindgen
+    /// --source-map` reports an error.
+    #[cfg(all(feature = "spans", procmacro2_semver_exempt))]
+    fn location(&self) -> Option<shared::Location> {
+        let file = self.name_span.source_file();
+        if !file.is_real() {
+            return None;
+        }
+        let start = self.name_span.start();
+        Some(shared::Location {
+            file: file.path().display().to_string(),
+            line: start.line as u32,
+            column: start.column as u32,
+        })
+    }
+
+    #[cfg(not(all(feature = "spans", procmacro2_semver_exempt)))]
+    fn location(&self) -> Option<shared::Location> {
+        None
+    }
 }
 
 impl Export {
@@ -461,6 +499,11 @@ impl ImportFunction {
             shim: self.shim.to_string(),
             catch: self.catch,
             variadic: self.variadic,
+            slices: match self.slices {
+                SliceMode::View => shared::SliceMode::View,
+                SliceMode::Copy => shared::SliceMode::Copy,
+                SliceMode::CopyInOut => shared::SliceMode::CopyInOut,
+            },
             method,
             structural: self.structural,
             function: self.function.shared(),
@@ -0,0 +1,500 @@
+//! Support for `--reference-types`, passing JS values to and from wasm as
+//! `externref` values rather than as indices into a slab in the JS glue.
+//!
+//! Rust code still refers to a `JsValue` by an index, but with this transform
+//! the index is a slot in a table of `externref` values owned by the wasm
+//! module itself. Slots are handed out by `__wbindgen_anyref_table_alloc` in
+//! the `wasm-bindgen` crate. Imports and exports whose signatures contain JS
+//! values are then wrapped in wasm shims translating between indices and
+//! `externref` values, so JS passes and receives the values directly:
+//!
+//! * Imports are changed to take and return `externref` values, and Rust calls
+//!   a shim with the original signature instead.
+//! * Exports are replaced with a shim taking and returning `externref` values.
+//! * `__wbindgen_object_clone_ref` and `__wbindgen_object_drop_ref` are
+//!   implemented in wasm, without calling out to JS at all.
+//!
+//! Any other place the JS glue needs an index, such as arguments to closures,
+//! goes through the same table from JS.
+//!
+//! The version of `parity-wasm` we use can't represent reference types, so
+//! this happens in two steps. `Transform::prepare` adds the shims to the module
+//! with placeholder bodies, and once the module has been gc'd and serialized
+//! `Transform::finish` patches the binary to fill in their real bodies, their
+//! types, and the table itself.
+
+use std::collections::HashMap;
+
+use failure::Error;
+use parity_wasm::elements::*;
+
+use binary::{leb, Reader, RawModule};
+
+/// Number of slots at the start of the table reserved for `undefined`, `null`,
+/// `true` and `false`, matching `JSIDX_RESERVED` in the `wasm-bindgen` crate.
+pub const RESERVED_SLOTS: u32 = 8;
+
+/// Name of the exported table of JS values.
+pub const TABLE: &str = "__wbindgen_anyref_table";
+
+const ALLOC: &str = "__wbindgen_anyref_table_alloc";
+const DEALLOC: &str = "__wbindgen_anyref_table_dealloc";
+const SHIM_PREFIX: &str = "__wbindgen_anyref_shim";
+
+const EXTERNREF: u8 = 0x6f;
+
+#[derive(Default)]
+pub struct Transform {
+    imports: HashMap<String, Signature>,
+    exports: HashMap<String, Signature>,
+    shims: Vec<Shim>,
+}
+
+/// Which parameters and return value of a wasm function are JS values.
+#[derive(Clone, Debug, Default)]
+pub struct Signature {
+    /// Indices of wasm parameters which are JS values, and whether ownership
+    /// of the value is transferred by the call.
+    pub args: Vec<(usize, Ownership)>,
+    /// Whether the function returns an owned JS value.
+    pub ret: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ownership {
+    Owned,
+    Borrowed,
+}
+
+struct Shim {
+    /// Name of the export pointing at the shim in the prepared module.
+    export: String,
+    kind: ShimKind,
+}
+
+enum ShimKind {
+    Import(Signature),
+    Export(Signature),
+    CloneRef,
+    DropRef,
+    TableGrow,
+    TableSetNull,
+}
+
+impl Signature {
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty() && !self.ret
+    }
+}
+
+impl Transform {
+    /// Records that the JS shim for the import `name` takes and returns JS
+    /// values as described by `sig`.
+    pub fn import(&mut self, name: &str, sig: Signature) {
+        if !sig.is_empty() {
+            self.imports.insert(name.to_string(), sig);
+        }
+    }
+
+    /// Records that the JS shim for the export `name` passes and expects JS
+    /// values as described by `sig`.
+    pub fn export(&mut self, name: &str, sig: Signature) {
+        if !sig.is_empty() {
+            self.exports.insert(name.to_string(), sig);
+        }
+    }
+
+    /// Adds shims with placeholder bodies for all recorded imports and exports
+    /// as well as for the intrinsics implemented in wasm.
+    ///
+    /// All calls to a wrapped import are redirected to its shim. Shims for
+    /// imports call the import in their placeholder body to keep it alive
+    /// through gc, and shims for exports similarly call the original export.
+    pub fn prepare(&mut self, module: &mut Module) -> Result<(), Error> {
+        let types = match module.type_section() {
+            Some(s) => s
+                .types()
+                .iter()
+                .map(|t| match t {
+                    Type::Function(f) => f.params().len(),
+                })
+                .collect::<Vec<_>>(),
+            None => return Ok(()),
+        };
+        let imported = module
+            .import_section()
+            .map(|s| s.functions())
+            .unwrap_or(0) as u32;
+        let defined = module
+            .function_section()
+            .map(|s| s.entries().len())
+            .unwrap_or(0) as u32;
+        let mut next = imported + defined;
+        let mut new_functions = Vec::new();
+        let mut redirect = HashMap::new();
+
+        let imports = module
+            .import_section()
+            .map(|s| s.entries())
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|i| match i.external() {
+                External::Function(ty) => Some((i, *ty)),
+                _ => None,
+            })
+            .enumerate();
+        for (idx, (import, ty)) in imports {
+            let intrinsic = if import.module() == "__wbindgen_placeholder__" {
+                match import.field() {
+                    "__wbindgen_object_clone_ref" => Some(ShimKind::CloneRef),
+                    "__wbindgen_object_drop_ref" => Some(ShimKind::DropRef),
+                    "__wbindgen_anyref_table_grow" => Some(ShimKind::TableGrow),
+                    "__wbindgen_anyref_table_set_null" => Some(ShimKind::TableSetNull),
+                    _ => None,
+                }
+            } else {
+                None
+            };
+            let (kind, body) = match intrinsic {
+                Some(kind) => (kind, vec![Instruction::Unreachable, Instruction::End]),
+                None => match self.imports.get(import.field()) {
+                    Some(sig) => (
+                        ShimKind::Import(sig.clone()),
+                        forward(types[ty as usize], idx as u32),
+                    ),
+                    None => continue,
+                },
+            };
+            redirect.insert(idx as u32, next);
+            self.shims.push(Shim {
+                export: format!("{}{}", SHIM_PREFIX, self.shims.len()),
+                kind,
+            });
+            new_functions.push((ty, body, next));
+            next += 1;
+        }
+
+        redirect_calls(module, &redirect);
+
+        let mut export_shims = Vec::new();
+        if let Some(exports) = module.export_section() {
+            for export in exports.entries() {
+                let sig = match self.exports.get(export.field()) {
+                    Some(sig) => sig,
+                    None => continue,
+                };
+                let func = match export.internal() {
+                    Internal::Function(f) if *f >= imported => *f,
+                    _ => bail!("export `{}` is not a function defined in wasm", export.field()),
+                };
+                let ty = module.function_section().unwrap().entries()[(func - imported) as usize]
+                    .type_ref();
+                export_shims.push((export.field().to_string(), next));
+                self.shims.push(Shim {
+                    export: export.field().to_string(),
+                    kind: ShimKind::Export(sig.clone()),
+                });
+                new_functions.push((ty, forward(types[ty as usize], func), next));
+                next += 1;
+            }
+        }
+
+        let exports = match module.export_section_mut() {
+            Some(s) => s,
+            None => bail!("the wasm module has no export section"),
+        };
+        for export in exports.entries_mut() {
+            if let Some((_, shim)) = export_shims.iter().find(|(n, _)| n == export.field()) {
+                *export.internal_mut() = Internal::Function(*shim);
+            }
+        }
+        for (shim, (_, _, idx)) in self.shims.iter().zip(&new_functions) {
+            if shim.export.starts_with(SHIM_PREFIX) {
+                exports
+                    .entries_mut()
+                    .push(ExportEntry::new(shim.export.clone(), Internal::Function(*idx)));
+            }
+        }
+
+        for (ty, body, _) in new_functions {
+            module
+                .function_section_mut()
+                .unwrap()
+                .entries_mut()
+                .push(Func::new(ty));
+            module
+                .code_section_mut()
+                .unwrap()
+                .bodies_mut()
+                .push(FuncBody::new(Vec::new(), Instructions::new(body)));
+        }
+        Ok(())
+    }
+
+    /// Fills in the shims added by `prepare` in the serialized `wasm` module,
+    /// changing the signatures of wrapped imports and exports to use
+    /// `externref` and adding the exported table of JS values.
+    pub fn finish(&self, wasm: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut module = RawModule::parse(wasm)?;
+        let table = module.add_table(EXTERNREF, RESERVED_SLOTS);
+        let alloc = module.exported_function(ALLOC)?;
+        let dealloc = module.exported_function(DEALLOC)?;
+
+        for shim in self.shims.iter() {
+            let func = module.exported_function(&shim.export)?;
+            if shim.export.starts_with(SHIM_PREFIX) {
+                module.exports.retain(|e| e.0 != shim.export);
+            }
+            let idx = (func - module.imported_functions()) as usize;
+            let mut body = Body::default();
+
+            match &shim.kind {
+                ShimKind::Import(sig) => {
+                    let import = forwarded_to(&module.code[idx])?;
+                    let ty = module.types[module.function_type(import) as usize].clone();
+                    let new_ty = module.add_type(anyref_type(&ty, sig));
+                    module.set_import_type(import, new_ty);
+
+                    let mut refs = HashMap::new();
+                    for &(arg, ownership) in sig.args.iter() {
+                        let local = body.local(EXTERNREF, ty.0.len());
+                        body.local_get(arg as u32);
+                        body.table_get(table);
+                        body.local_set(local);
+                        if ownership == Ownership::Owned {
+                            body.local_get(arg as u32);
+                            body.call(dealloc);
+                        }
+                        refs.insert(arg, local);
+                    }
+                    for arg in 0..ty.0.len() {
+                        body.local_get(*refs.get(&arg).unwrap_or(&(arg as u32)));
+                    }
+                    body.call(import);
+                    if sig.ret {
+                        let ret = body.local(EXTERNREF, ty.0.len());
+                        let slot = body.local(0x7f, ty.0.len());
+                        body.local_set(ret);
+                        body.call(alloc);
+                        body.local_tee(slot);
+                        body.local_get(ret);
+                        body.table_set(table);
+                        body.local_get(slot);
+                    }
+                }
+                ShimKind::Export(sig) => {
+                    let export = forwarded_to(&module.code[idx])?;
+                    let ty = module.types[module.functions[idx] as usize].clone();
+                    module.functions[idx] = module.add_type(anyref_type(&ty, sig));
+
+                    let mut slots = HashMap::new();
+                    for &(arg, _) in sig.args.iter() {
+                        let slot = body.local(0x7f, ty.0.len());
+                        body.call(alloc);
+                        body.local_tee(slot);
+                        body.local_get(arg as u32);
+                        body.table_set(table);
+                        slots.insert(arg, slot);
+                    }
+                    for arg in 0..ty.0.len() {
+                        body.local_get(*slots.get(&arg).unwrap_or(&(arg as u32)));
+                    }
+                    body.call(export);
+                    let ret = if sig.ret {
+                        let ret = body.local(0x7f, ty.0.len());
+                        body.local_set(ret);
+                        Some(ret)
+                    } else {
+                        None
+                    };
+                    for &(arg, ownership) in sig.args.iter() {
+                        if ownership == Ownership::Borrowed {
+                            body.local_get(slots[&arg]);
+                            body.call(dealloc);
+                        }
+                    }
+                    if let Some(ret) = ret {
+                        body.local_get(ret);
+                        body.table_get(table);
+                        body.local_get(ret);
+                        body.call(dealloc);
+                    }
+                }
+                ShimKind::CloneRef => {
+                    let slot = body.local(0x7f, 1);
+                    body.call(alloc);
+                    body.local_tee(slot);
+                    body.local_get(0);
+                    body.table_get(table);
+                    body.table_set(table);
+                    body.local_get(slot);
+                }
+                ShimKind::DropRef => {
+                    body.local_get(0);
+                    body.call(dealloc);
+                }
+                ShimKind::TableGrow => {
+                    body.ref_null();
+                    body.local_get(0);
+                    body.code.extend_from_slice(&[0xfc, 0x0f]);
+                    leb(&mut body.code, table);
+                }
+                ShimKind::TableSetNull => {
+                    body.local_get(0);
+                    body.ref_null();
+                    body.table_set(table);
+                }
+            }
+            module.code[idx] = body.finish();
+        }
+
+        module.exports.push((TABLE.to_string(), 1, table));
+        Ok(module.emit())
+    }
+}
+
+/// Returns the instructions of a function forwarding its `params` arguments
+/// to the function `target`.
+fn forward(params: usize, target: u32) -> Vec<Instruction> {
+    let mut body = (0..params as u32).map(Instruction::GetLocal).collect::<Vec<_>>();
+    body.push(Instruction::Call(target));
+    body.push(Instruction::End);
+    body
+}
+
+/// Returns the target of the `call` in the body of a shim generated by
+/// `forward`, after it's been through gc.
+fn forwarded_to(body: &[u8]) -> Result<u32, Error> {
+    let mut reader = Reader { data: body, pos: 0 };
+    if reader.u32()? != 0 {
+        bail!("placeholder shim unexpectedly has locals");
+    }
+    loop {
+        match reader.u8()? {
+            0x20 => {
+                reader.u32()?;
+            }
+            0x10 => return reader.u32(),
+            _ => bail!("malformed placeholder shim"),
+        }
+    }
+}
+
+/// Redirects all uses of functions in the keys of `redirect` to the function
+/// in the corresponding value.
+pub fn redirect_calls(module: &mut Module, redirect: &HashMap<u32, u32>) {
+    if redirect.is_empty() {
+        return;
+    }
+    for section in module.sections_mut() {
+        match section {
+            Section::Code(s) => {
+                for body in s.bodies_mut() {
+                    for instr in body.code_mut().elements_mut() {
+                        if let Instruction::Call(f) = instr {
+                            if let Some(new) = redirect.get(f) {
+                                *f = *new;
+                            }
+                        }
+                    }
+                }
+            }
+            Section::Element(s) => {
+                for segment in s.entries_mut() {
+                    for f in segment.members_mut() {
+                        if let Some(new) = redirect.get(f) {
+                            *f = *new;
+                        }
+                    }
+                }
+            }
+            Section::Export(s) => {
+                for export in s.entries_mut() {
+                    if let Internal::Function(f) = export.internal_mut() {
+                        if let Some(new) = redirect.get(f) {
+                            *f = *new;
+                        }
+                    }
+                }
+            }
+            Section::Start(f) => {
+                if let Some(new) = redirect.get(f) {
+                    *f = *new;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Replaces the JS value parameters and return value of `ty` with `externref`.
+fn anyref_type(ty: &(Vec<u8>, Vec<u8>), sig: &Signature) -> (Vec<u8>, Vec<u8>) {
+    let mut params = ty.0.clone();
+    for &(arg, _) in sig.args.iter() {
+        params[arg] = EXTERNREF;
+    }
+    let results = if sig.ret { vec![EXTERNREF] } else { ty.1.clone() };
+    (params, results)
+}
+
+/// A function body being generated, along with its locals.
+#[derive(Default)]
+struct Body {
+    locals: Vec<u8>,
+    code: Vec<u8>,
+}
+
+impl Body {
+    /// Declares a new local of type `ty`, returning its index given the number
+    /// of parameters of the function.
+    fn local(&mut self, ty: u8, params: usize) -> u32 {
+        self.locals.push(ty);
+        (params + self.locals.len() - 1) as u32
+    }
+
+    fn local_get(&mut self, idx: u32) {
+        self.code.push(0x20);
+        leb(&mut self.code, idx);
+    }
+
+    fn local_set(&mut self, idx: u32) {
+        self.code.push(0x21);
+        leb(&mut self.code, idx);
+    }
+
+    fn local_tee(&mut self, idx: u32) {
+        self.code.push(0x22);
+        leb(&mut self.code, idx);
+    }
+
+    fn call(&mut self, func: u32) {
+        self.code.push(0x10);
+        leb(&mut self.code, func);
+    }
+
+    fn table_get(&mut self, table: u32) {
+        self.code.push(0x25);
+        leb(&mut self.code, table);
+    }
+
+    fn table_set(&mut self, table: u32) {
+        self.code.push(0x26);
+        leb(&mut self.code, table);
+    }
+
+    fn ref_null(&mut self) {
+        self.code.extend_from_slice(&[0xd0, EXTERNREF]);
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut dst = Vec::new();
+        leb(&mut dst, self.locals.len() as u32);
+        for ty in self.locals {
+            dst.push(1);
+            dst.push(ty);
+        }
+        dst.extend(self.code);
+        dst.push(0x0b);
+        dst
+    }
+}
@@ -0,0 +1,478 @@
+//! Compares the exported API of two builds of a module, for
+//! `wasm-bindgen api-diff`.
+//!
+//! Only what JS code can call is compared: exported functions, classes with
+//! their methods and fields, and enums. Anything removed or changed is a
+//! breaking change, while additions aren't.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use failure::Error;
+
+use descriptor::{Descriptor, Function};
+use manifest::{self, Class, Manifest, MethodKind};
+
+pub struct Change {
+    pub breaking: bool,
+    pub message: String,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = if self.breaking { "breaking" } else { "added" };
+        write!(f, "{}: {}", kind, self.message)
+    }
+}
+
+/// Returns every change to the exported API between the wasm files `old` and
+/// `new`.
+pub fn diff(old: &[u8], new: &[u8]) -> Result<Vec<Change>, Error> {
+    let old = manifest::extract(old)?;
+    let new = manifest::extract(new)?;
+    let mut cx = Diff { changes: Vec::new() };
+    cx.manifests(&old, &new);
+    Ok(cx.changes)
+}
+
+struct Diff {
+    changes: Vec<Change>,
+}
+
+impl Diff {
+    fn breaking(&mut self, message: String) {
+        self.changes.push(Change { breaking: true, message });
+    }
+
+    fn added(&mut self, message: String) {
+        self.changes.push(Change { breaking: false, message });
+    }
+
+    fn manifests(&mut self, old: &Manifest, new: &Manifest) {
+        let new_functions = by_name(&new.functions, |f| &f.name);
+        for f in old.functions.iter() {
+            match new_functions.get(&f.name[..]) {
+                Some(new) => {
+                    let what = format!("function `{}`", f.name);
+                    self.signatures(&what, &f.signature, &new.signature)
+                }
+                None => self.breaking(format!("function `{}` was removed", f.name)),
+            }
+        }
+        let old_functions = by_name(&old.functions, |f| &f.name);
+        for f in new.functions.iter() {
+            if !old_functions.contains_key(&f.name[..]) {
+                self.added(format!("function `{}`", f.name));
+            }
+        }
+
+        let new_classes = by_name(&new.classes, |c| &c.name);
+        for c in old.classes.iter() {
+            match new_classes.get(&c.name[..]) {
+                Some(new) => self.classes(c, new),
+                None => self.breaking(format!("class `{}` was removed", c.name)),
+            }
+        }
+        let old_classes = by_name(&old.classes, |c| &c.name);
+        for c in new.classes.iter() {
+            if !old_classes.contains_key(&c.name[..]) {
+                self.added(format!("class `{}`", c.name));
+            }
+        }
+
+        let new_enums = by_name(&new.enums, |e| &e.name);
+        for e in old.enums.iter() {
+            let new = match new_enums.get(&e.name[..]) {
+                Some(new) => new,
+                None => {
+                    self.breaking(format!("enum `{}` was removed", e.name));
+                    continue;
+                }
+            };
+            let new_variants = by_name(&new.variants, |v| &v.name);
+            for v in e.variants.iter() {
+                match new_variants.get(&v.name[..]) {
+                    Some(n) if n.value == v.value => {}
+                    Some(n) => self.breaking(format!(
+                        "variant `{}::{}` changed value from {} to {}",
+                        e.name, v.name, v.value, n.value
+                    )),
+                    None => self.breaking(format!(
+                        "variant `{}::{}` was removed",
+                        e.name, v.name
+                    )),
+                }
+            }
+            let old_variants = by_name(&e.variants, |v| &v.name);
+            for v in new.variants.iter() {
+                if !old_variants.contains_key(&v.name[..]) {
+                    self.added(format!("variant `{}::{}`", e.name, v.name));
+                }
+            }
+        }
+        let old_enums = by_name(&old.enums, |e| &e.name);
+        for e in new.enums.iter() {
+            if !old_enums.contains_key(&e.name[..]) {
+                self.added(format!("enum `{}`", e.name));
+            }
+        }
+    }
+
+    fn classes(&mut self, old: &Class, new: &Class) {
+        let new_methods = by_name(&new.methods, |m| &m.name);
+        for m in old.methods.iter() {
+            let what = format!("method `{}::{}`", old.name, m.name);
+            let n = match new_methods.get(&m.name[..]) {
+                Some(n) => n,
+                None => {
+                    self.breaking(format!("{} was removed", what));
+                    continue;
+                }
+            };
+            if n.kind != m.kind {
+                self.breaking(format!(
+                    "{} changed from {} to {}",
+                    what,
+                    method_kind(m.kind),
+                    method_kind(n.kind)
+                ));
+            }
+            self.signatures(&what, &m.signature, &n.signature);
+        }
+        let old_methods = by_name(&old.methods, |m| &m.name);
+        for m in new.methods.iter() {
+            if !old_methods.contains_key(&m.name[..]) {
+                self.added(format!("method `{}::{}`", new.name, m.name));
+            }
+        }
+
+        let new_fields = by_name(&new.fields, |f| &f.name);
+        for f in old.fields.iter() {
+            let what = format!("field `{}::{}`", old.name, f.name);
+            let n = match new_fields.get(&f.name[..]) {
+                Some(n) => n,
+                None => {
+                    self.breaking(format!("{} was removed", what));
+                    continue;
+                }
+            };
+            if n.ty != f.ty {
+                self.breaking(format!(
+                    "{} changed type from `{}` to `{}`",
+                    what,
+                    rust_type(&f.ty),
+                    rust_type(&n.ty)
+                ));
+            }
+            if n.readonly && !f.readonly {
+                self.breaking(format!("{} is now read-only", what));
+            }
+        }
+        let old_fields = by_name(&old.fields, |f| &f.name);
+        for f in new.fields.iter() {
+            if !old_fields.contains_key(&f.name[..]) {
+                self.added(format!("field `{}::{}`", new.name, f.name));
+            }
+        }
+    }
+
+    fn signatures(&mut self, what: &str, old: &Function, new: &Function) {
+        if old.arguments.len() != new.arguments.len() {
+            self.breaking(format!(
+                "{} changed from {} to {} arguments",
+                what,
+                old.arguments.len(),
+                new.arguments.len()
+            ));
+        } else {
+            for (i, (a, b)) in old.arguments.iter().zip(&new.arguments).enumerate() {
+                if a != b {
+                    self.breaking(format!(
+                        "{} changed argument {} from `{}` to `{}`",
+                        what,
+                        i,
+                        rust_type(a),
+                        rust_type(b)
+                    ));
+                }
+            }
+        }
+        if old.ret != new.ret {
+            self.breaking(format!(
+                "{} changed its return type from `{}` to `{}`",
+                what,
+                rust_type(&old.ret),
+                rust_type(&new.ret)
+            ));
+        }
+    }
+}
+
+fn by_name<'a, T>(items: &'a [T], name: fn(&T) -> &String) -> HashMap<&'a str, &'a T> {
+    items.iter().map(|i| (&name(i)[..], i)).collect()
+}
+
+fn method_kind(kind: MethodKind) -> &'static str {
+    match kind {
+        MethodKind::Constructor => "a constructor",
+        MethodKind::Method => "a method",
+        MethodKind::Consuming => "a method taking `self`",
+        MethodKind::Static => "a static method",
+    }
+}
+
+/// Formats `ty` roughly the way it's written in Rust, for messages.
+fn rust_type(ty: &Descriptor) -> String {
+    match ty {
+        Descriptor::I8 => "i8".to_string(),
+        Descriptor::U8 => "u8".to_string(),
+        Descriptor::I16 => "i16".to_string(),
+        Descriptor::U16 => "u16".to_string(),
+        Descriptor::I32 => "i32".to_string(),
+        Descriptor::U32 => "u32".to_string(),
+        Descriptor::I64 => "i64".to_string(),
+        Descriptor::U64 => "u64".to_string(),
+        Descriptor::I128 => "i128".to_string(),
+        Descriptor::U128 => "u128".to_string(),
+        Descriptor::F32 => "f32".to_string(),
+        Descriptor::F64 => "f64".to_string(),
+        Descriptor::Boolean => "bool".to_string(),
+        Descriptor::Char => "char".to_string(),
+        Descriptor::Unit => "()".to_string(),
+        Descriptor::String => "String".to_string(),
+        Descriptor::Anyref => "JsValue".to_string(),
+        Descriptor::Enum => "enum".to_string(),
+        Descriptor::RustStruct(name) => name.clone(),
+        Descriptor::Ref(t) => match **t {
+            Descriptor::String => "&str".to_string(),
+            ref t => format!("&{}", rust_type(t)),
+        },
+        Descriptor::RefMut(t) => format!("&mut {}", rust_type(t)),
+        Descriptor::Slice(t) => format!("[{}]", rust_type(t)),
+        Descriptor::Vector(t) => format!("Vec<{}>", rust_type(t)),
+        Descriptor::Option(t) => format!("Option<{}>", rust_type(t)),
+        Descriptor::Clamped(t) => format!("Clamped<{}>", rust_type(t)),
+        Descriptor::Function(f) => format!("fn{}", rust_signature(f)),
+        Descriptor::Closure(c) => {
+            let kind = if c.mutable { "FnMut" } else { "Fn" };
+            format!("&dyn {}{}", kind, rust_signature(&c.function))
+        }
+    }
+}
+
+fn rust_signature(f: &Function) -> String {
+    let args = f.arguments.iter().map(rust_type).collect::<Vec<_>>();
+    match f.ret {
+        Descriptor::Unit => format!("({})", args.join(", ")),
+        ref ret => format!("({}) -> {}", args.join(", "), rust_type(ret)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use manifest::{EnumVariant, Field, Method};
+
+    fn manifest() -> Manifest {
+        Manifest {
+            version: String::new(),
+            functions: Vec::new(),
+            classes: Vec::new(),
+            enums: Vec::new(),
+            imports: Vec::new(),
+        }
+    }
+
+    fn signature(arguments: Vec<Descriptor>, ret: Descriptor) -> Function {
+        Function { arguments, ret }
+    }
+
+    fn function(name: &str, signature: Function) -> manifest::Function {
+        manifest::Function {
+            name: name.to_string(),
+            signature,
+            comments: Vec::new(),
+        }
+    }
+
+    fn method(name: &str, kind: MethodKind, signature: Function) -> Method {
+        Method {
+            name: name.to_string(),
+            kind,
+            signature,
+            comments: Vec::new(),
+        }
+    }
+
+    fn field(name: &str, readonly: bool, ty: Descriptor) -> Field {
+        Field {
+            name: name.to_string(),
+            readonly,
+            ty,
+            comments: Vec::new(),
+        }
+    }
+
+    fn class(name: &str, fields: Vec<Field>, methods: Vec<Method>) -> Class {
+        Class {
+            name: name.to_string(),
+            fields,
+            methods,
+            comments: Vec::new(),
+        }
+    }
+
+    fn enum_(name: &str, variants: &[(&str, u32)]) -> manifest::Enum {
+        manifest::Enum {
+            name: name.to_string(),
+            variants: variants
+                .iter()
+                .map(|&(name, value)| EnumVariant {
+                    name: name.to_string(),
+                    value,
+                })
+                .collect(),
+            comments: Vec::new(),
+        }
+    }
+
+    fn changes(old: &Manifest, new: &Manifest) -> Vec<String> {
+        let mut cx = Diff { changes: Vec::new() };
+        cx.manifests(old, new);
+        cx.changes.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn no_changes() {
+        let mut m = manifest();
+        m.functions.push(function("f", signature(vec![Descriptor::U32], Descriptor::Unit)));
+        m.classes.push(class("Foo", vec![field("x", false, Descriptor::U32)], Vec::new()));
+        m.enums.push(enum_("Color", &[("Red", 0)]));
+        assert_eq!(changes(&m, &m), Vec::<String>::new());
+    }
+
+    #[test]
+    fn removed_and_added_functions() {
+        let mut old = manifest();
+        old.functions.push(function("gone", signature(Vec::new(), Descriptor::Unit)));
+        let mut new = manifest();
+        new.functions.push(function("fresh", signature(Vec::new(), Descriptor::Unit)));
+        assert_eq!(
+            changes(&old, &new),
+            [
+                "breaking: function `gone` was removed",
+                "added: function `fresh`",
+            ]
+        );
+    }
+
+    #[test]
+    fn retyped_functions() {
+        let mut old = manifest();
+        old.functions.push(function(
+            "f",
+            signature(vec![Descriptor::U32, Descriptor::String], Descriptor::Unit),
+        ));
+        let mut new = manifest();
+        new.functions.push(function(
+            "f",
+            signature(
+                vec![Descriptor::U32, Descriptor::Ref(Box::new(Descriptor::String))],
+                Descriptor::Option(Box::new(Descriptor::F64)),
+            ),
+        ));
+        assert_eq!(
+            changes(&old, &new),
+            [
+                "breaking: function `f` changed argument 1 from `String` to `&str`",
+                "breaking: function `f` changed its return type from `()` to `Option<f64>`",
+            ]
+        );
+    }
+
+    #[test]
+    fn added_arguments() {
+        let mut old = manifest();
+        old.functions.push(function("f", signature(vec![Descriptor::U32], Descriptor::Unit)));
+        let mut new = manifest();
+        new.functions.push(function(
+            "f",
+            signature(vec![Descriptor::U32, Descriptor::Boolean], Descriptor::Unit),
+        ));
+        assert_eq!(
+            changes(&old, &new),
+            ["breaking: function `f` changed from 1 to 2 arguments"]
+        );
+    }
+
+    #[test]
+    fn classes() {
+        let get = || signature(Vec::new(), Descriptor::U32);
+        let mut old = manifest();
+        old.classes.push(class(
+            "Foo",
+            vec![
+                field("x", false, Descriptor::U32),
+                field("y", false, Descriptor::U32),
+                field("z", true, Descriptor::U32),
+                field("gone", false, Descriptor::U32),
+            ],
+            vec![
+                method("get", MethodKind::Method, get()),
+                method("take", MethodKind::Method, get()),
+                method("gone", MethodKind::Static, get()),
+            ],
+        ));
+        old.classes.push(class("Gone", Vec::new(), Vec::new()));
+        let mut new = manifest();
+        new.classes.push(class(
+            "Foo",
+            vec![
+                field("x", true, Descriptor::U32),
+                field("y", false, Descriptor::I32),
+                field("z", false, Descriptor::U32),
+                field("fresh", false, Descriptor::U32),
+            ],
+            vec![
+                method("get", MethodKind::Method, get()),
+                method("take", MethodKind::Consuming, get()),
+                method("fresh", MethodKind::Static, get()),
+            ],
+        ));
+        new.classes.push(class("Fresh", Vec::new(), Vec::new()));
+        assert_eq!(
+            changes(&old, &new),
+            [
+                "breaking: method `Foo::take` changed from a method to a method taking `self`",
+                "breaking: method `Foo::gone` was removed",
+                "added: method `Foo::fresh`",
+                "breaking: field `Foo::x` is now read-only",
+                "breaking: field `Foo::y` changed type from `u32` to `i32`",
+                "breaking: field `Foo::gone` was removed",
+                "added: field `Foo::fresh`",
+                "breaking: class `Gone` was removed",
+                "added: class `Fresh`",
+            ]
+        );
+    }
+
+    #[test]
+    fn enums() {
+        let mut old = manifest();
+        old.enums.push(enum_("Color", &[("Red", 0), ("Green", 1), ("Blue", 2)]));
+        old.enums.push(enum_("Gone", &[]));
+        let mut new = manifest();
+        new.enums.push(enum_("Color", &[("Red", 0), ("Green", 5), ("Cyan", 3)]));
+        new.enums.push(enum_("Fresh", &[]));
+        assert_eq!(
+            changes(&old, &new),
+            [
+                "breaking: variant `Color::Green` changed value from 1 to 5",
+                "breaking: variant `Color::Blue` was removed",
+                "added: variant `Color::Cyan`",
+                "breaking: enum `Gone` was removed",
+                "added: enum `Fresh`",
+            ]
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Support for `--bigint`, passing 64-bit integers to and from wasm as `i64`
+//! values, which engines with the JS BigInt integration convert to and from
+//! BigInts themselves.
+//!
+//! The Rust ABI passes a 64-bit integer as two `i32` halves and returns one by
+//! writing it through a return pointer, which the JS glue otherwise has to
+//! split and reassemble through typed arrays. With this transform imports and
+//! exports with 64-bit integers in their signature are wrapped in shims
+//! translating between the two, similarly to `anyref`:
+//!
+//! * Imports are changed to take and return `i64` values, and Rust calls a
+//!   shim with the original signature instead.
+//! * Exports are replaced with a shim taking and returning `i64` values.
+//!
+//! 128-bit integers are passed as two `i64` halves, but are still returned
+//! through a return pointer.
+
+use std::collections::{HashMap, HashSet};
+
+use failure::Error;
+use parity_wasm::elements::*;
+
+use anyref::redirect_calls;
+use multi_value::{add_type, check_stack_pointer, RET_AREA};
+
+#[derive(Default)]
+pub struct Transform {
+    imports: HashMap<String, Signature>,
+    exports: HashMap<String, Signature>,
+}
+
+/// Which parameters and return value of a wasm function, as seen from JS, are
+/// `i64` values.
+#[derive(Clone, Debug, Default)]
+pub struct Signature {
+    /// Indices of parameters which are `i64` values, each of which is passed
+    /// to Rust as two `i32` halves.
+    pub args: Vec<usize>,
+    /// Whether the function returns an `i64` value rather than taking a
+    /// return pointer as its first parameter.
+    pub ret: bool,
+}
+
+impl Signature {
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty() && !self.ret
+    }
+
+    /// Returns the parameters and result of the function as seen from JS,
+    /// given the parameters and result of the function in the Rust ABI.
+    fn js_type(&self, rust: &FunctionType) -> Result<FunctionType, Error> {
+        let args = self.args.iter().cloned().collect::<HashSet<_>>();
+        let mut params = Vec::new();
+        let mut rust_params = rust.params().iter();
+        if self.ret && rust_params.next() != Some(&ValueType::I32) {
+            bail!("function doesn't take a return pointer");
+        }
+        while let Some(param) = rust_params.next() {
+            if args.contains(&params.len()) {
+                if *param != ValueType::I32 || rust_params.next() != Some(&ValueType::I32) {
+                    bail!("64-bit integer isn't passed as two `i32` halves");
+                }
+                params.push(ValueType::I64);
+            } else {
+                params.push(*param);
+            }
+        }
+        let ret = if self.ret {
+            if rust.return_type().is_some() {
+                bail!("function takes a return pointer but also returns a value");
+            }
+            Some(ValueType::I64)
+        } else {
+            rust.return_type()
+        };
+        Ok(FunctionType::new(params, ret))
+    }
+}
+
+impl Transform {
+    /// Records that the JS shim for the import `name` takes and returns `i64`
+    /// values as described by `sig`.
+    pub fn import(&mut self, name: &str, sig: Signature) {
+        if !sig.is_empty() {
+            self.imports.insert(name.to_string(), sig);
+        }
+    }
+
+    /// Records that the JS shim for the export `name` passes and expects
+    /// `i64` values as described by `sig`.
+    pub fn export(&mut self, name: &str, sig: Signature) {
+        if !sig.is_empty() {
+            self.exports.insert(name.to_string(), sig);
+        }
+    }
+
+    /// Retypes all recorded imports, redirecting calls to them to shims with
+    /// their original type, and replaces all recorded exports with shims.
+    pub fn prepare(&mut self, module: &mut Module) -> Result<(), Error> {
+        if self.imports.is_empty() && self.exports.is_empty() {
+            return Ok(());
+        }
+        if self.exports.values().any(|s| s.ret) {
+            check_stack_pointer(module, "--bigint")?;
+        }
+        let imported = module
+            .import_section()
+            .map(|s| s.functions())
+            .unwrap_or(0) as u32;
+        let mut next = imported + module
+            .function_section()
+            .map(|s| s.entries().len())
+            .unwrap_or(0) as u32;
+        let mut new_functions = Vec::new();
+        let mut redirect = HashMap::new();
+
+        let mut import_types = Vec::new();
+        if let Some(imports) = module.import_section() {
+            let functions = imports.entries().iter().filter_map(|i| match i.external() {
+                External::Function(ty) => Some((i.field(), *ty)),
+                _ => None,
+            });
+            for (idx, (field, ty)) in functions.enumerate() {
+                let sig = match self.imports.get(field) {
+                    Some(sig) => sig,
+                    None => continue,
+                };
+                let rust = function_type(module, ty);
+                let js = sig
+                    .js_type(&rust)
+                    .map_err(|e| format_err!("import `{}`: {}", field, e))?;
+                let body = import_shim(sig, &rust, &js, idx as u32);
+                import_types.push((idx, js));
+                redirect.insert(idx as u32, next);
+                new_functions.push((ty, body, next));
+                next += 1;
+            }
+        }
+        redirect_calls(module, &redirect);
+        for (idx, js) in import_types {
+            let ty = add_type(module, js);
+            let entry = module
+                .import_section_mut()
+                .unwrap()
+                .entries_mut()
+                .iter_mut()
+                .filter(|i| match i.external() {
+                    External::Function(_) => true,
+                    _ => false,
+                })
+                .nth(idx)
+                .unwrap();
+            *entry.external_mut() = External::Function(ty);
+        }
+
+        let mut export_shims = Vec::new();
+        if let Some(exports) = module.export_section() {
+            for export in exports.entries() {
+                let sig = match self.exports.get(export.field()) {
+                    Some(sig) => sig.clone(),
+                    None => continue,
+                };
+                let func = match export.internal() {
+                    Internal::Function(f) if *f >= imported => *f,
+                    _ => bail!("export `{}` is not a function defined in wasm", export.field()),
+                };
+                let ty = module.function_section().unwrap().entries()[(func - imported) as usize]
+                    .type_ref();
+                let rust = function_type(module, ty);
+                let js = sig
+                    .js_type(&rust)
+                    .map_err(|e| format_err!("export `{}`: {}", export.field(), e))?;
+                export_shims.push((export.field().to_string(), sig, js, func, next));
+                next += 1;
+            }
+        }
+        for (name, sig, js, func, idx) in export_shims {
+            let body = export_shim(&sig, &js, func);
+            let ty = add_type(module, js);
+            new_functions.push((ty, body, idx));
+            for export in module.export_section_mut().unwrap().entries_mut() {
+                if export.field() == name {
+                    *export.internal_mut() = Internal::Function(idx);
+                }
+            }
+        }
+
+        for (ty, body, _) in new_functions {
+            module
+                .function_section_mut()
+                .unwrap()
+                .entries_mut()
+                .push(Func::new(ty));
+            module.code_section_mut().unwrap().bodies_mut().push(body);
+        }
+        Ok(())
+    }
+}
+
+fn function_type(module: &Module, ty: u32) -> FunctionType {
+    match &module.type_section().unwrap().types()[ty as usize] {
+        Type::Function(f) => f.clone(),
+    }
+}
+
+/// Generates the body of a shim with the Rust signature `rust` calling the
+/// import `import`, which has the JS signature `js`.
+fn import_shim(sig: &Signature, rust: &FunctionType, js: &FunctionType, import: u32) -> FuncBody {
+    let mut body = Vec::new();
+    let mut local = 0;
+    if sig.ret {
+        body.push(Instruction::GetLocal(local));
+        local += 1;
+    }
+    for param in js.params() {
+        if *param == ValueType::I64 {
+            // (high << 32) | low
+            body.push(Instruction::GetLocal(local + 1));
+            body.push(Instruction::I64ExtendUI32);
+            body.push(Instruction::I64Const(32));
+            body.push(Instruction::I64Shl);
+            body.push(Instruction::GetLocal(local));
+            body.push(Instruction::I64ExtendUI32);
+            body.push(Instruction::I64Or);
+            local += 2;
+        } else {
+            body.push(Instruction::GetLocal(local));
+            local += 1;
+        }
+    }
+    assert_eq!(local as usize, rust.params().len());
+    body.push(Instruction::Call(import));
+    if sig.ret {
+        body.push(Instruction::I64Store(3, 0));
+    }
+    body.push(Instruction::End);
+    FuncBody::new(Vec::new(), Instructions::new(body))
+}
+
+/// Generates the body of a shim with the JS signature `js` calling the export
+/// `func`, reserving space on the stack for its return value if necessary.
+fn export_shim(sig: &Signature, js: &FunctionType, func: u32) -> FuncBody {
+    let sp = js.params().len() as u32;
+    let mut body = Vec::new();
+    let mut locals = Vec::new();
+    if sig.ret {
+        locals.push(Local::new(1, ValueType::I32));
+        body.extend(vec![
+            Instruction::GetGlobal(0),
+            Instruction::I32Const(RET_AREA),
+            Instruction::I32Sub,
+            Instruction::TeeLocal(sp),
+            Instruction::SetGlobal(0),
+            Instruction::GetLocal(sp),
+        ]);
+    }
+    for (i, param) in js.params().iter().enumerate() {
+        let i = i as u32;
+        if *param == ValueType::I64 {
+            body.extend(vec![
+                Instruction::GetLocal(i),
+                Instruction::I32WrapI64,
+                Instruction::GetLocal(i),
+                Instruction::I64Const(32),
+                Instruction::I64ShrU,
+                Instruction::I32WrapI64,
+            ]);
+        } else {
+            body.push(Instruction::GetLocal(i));
+        }
+    }
+    body.push(Instruction::Call(func));
+    if sig.ret {
+        body.extend(vec![
+            Instruction::GetLocal(sp),
+            Instruction::I64Load(3, 0),
+            Instruction::GetLocal(sp),
+            Instruction::I32Const(RET_AREA),
+            Instruction::I32Add,
+            Instruction::SetGlobal(0),
+        ]);
+    }
+    body.push(Instruction::End);
+    FuncBody::new(locals, Instructions::new(body))
+}
@@ -0,0 +1,327 @@
+//! Minimal reading and writing of the wasm binary format.
+//!
+//! The version of `parity-wasm` we use predates the reference types and
+//! multi-value proposals, so transforms relying on them first add what they
+//! can with `parity-wasm`, and then patch the serialized module with the
+//! helpers here.
+
+use failure::Error;
+
+/// Just enough of a wasm module's binary encoding to patch in the parts of a
+/// transform `parity-wasm` can't represent. Sections which aren't decoded are
+/// kept as raw bytes.
+pub struct RawModule {
+    /// All sections keyed by section id and kept in order. The payloads of
+    /// the sections decoded below are regenerated when emitting the module.
+    sections: Vec<(u8, Vec<u8>)>,
+    /// Function types as (params, results) value type bytes.
+    pub types: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Imports as (module, field, kind, descriptor bytes).
+    pub imports: Vec<(String, String, u8, Vec<u8>)>,
+    /// Type indices of the functions defined in the module.
+    pub functions: Vec<u32>,
+    pub tables: Vec<Vec<u8>>,
+    /// Exports as (field, kind, index).
+    pub exports: Vec<(String, u8, u32)>,
+    /// Bodies of the functions defined in the module, including locals.
+    pub code: Vec<Vec<u8>>,
+}
+
+const TYPE: u8 = 1;
+const IMPORT: u8 = 2;
+const FUNCTION: u8 = 3;
+const TABLE_SECTION: u8 = 4;
+const EXPORT: u8 = 7;
+const CODE: u8 = 10;
+
+impl RawModule {
+    pub fn parse(wasm: &[u8]) -> Result<RawModule, Error> {
+        if wasm.len() < 8 || &wasm[..4] != b"\0asm" {
+            bail!("not a wasm module");
+        }
+        let mut module = RawModule {
+            sections: Vec::new(),
+            types: Vec::new(),
+            imports: Vec::new(),
+            functions: Vec::new(),
+            tables: Vec::new(),
+            exports: Vec::new(),
+            code: Vec::new(),
+        };
+        let mut reader = Reader { data: wasm, pos: 8 };
+        while reader.pos < wasm.len() {
+            let id = reader.u8()?;
+            let len = reader.u32()? as usize;
+            let payload = reader.bytes(len)?;
+            let mut r = Reader { data: payload, pos: 0 };
+            match id {
+                TYPE => {
+                    for _ in 0..r.u32()? {
+                        if r.u8()? != 0x60 {
+                            bail!("unknown type form");
+                        }
+                        let params = r.vec()?.to_vec();
+                        let results = r.vec()?.to_vec();
+                        module.types.push((params, results));
+                    }
+                }
+                IMPORT => {
+                    for _ in 0..r.u32()? {
+                        let name = r.name()?;
+                        let field = r.name()?;
+                        let kind = r.u8()?;
+                        let start = r.pos;
+                        match kind {
+                            0 => {
+                                r.u32()?;
+                            }
+                            1 => {
+                                r.u8()?;
+                                r.limits()?;
+                            }
+                            2 => r.limits()?,
+                            3 => {
+                                r.bytes(2)?;
+                            }
+                            _ => bail!("unknown import kind"),
+                        }
+                        module.imports.push((name, field, kind, payload[start..r.pos].to_vec()));
+                    }
+                }
+                FUNCTION => {
+                    for _ in 0..r.u32()? {
+                        module.functions.push(r.u32()?);
+                    }
+                }
+                TABLE_SECTION => {
+                    for _ in 0..r.u32()? {
+                        let start = r.pos;
+                        r.u8()?;
+                        r.limits()?;
+                        module.tables.push(payload[start..r.pos].to_vec());
+                    }
+                }
+                EXPORT => {
+                    for _ in 0..r.u32()? {
+                        let name = r.name()?;
+                        let kind = r.u8()?;
+                        let idx = r.u32()?;
+                        module.exports.push((name, kind, idx));
+                    }
+                }
+                CODE => {
+                    for _ in 0..r.u32()? {
+                        let len = r.u32()? as usize;
+                        module.code.push(r.bytes(len)?.to_vec());
+                    }
+                }
+                _ => {}
+            }
+            module.sections.push((id, payload.to_vec()));
+        }
+        // Make sure there's a table section to append to, right before the
+        // first section which has to come after it.
+        if !module.sections.iter().any(|s| s.0 == TABLE_SECTION) {
+            let pos = module
+                .sections
+                .iter()
+                .position(|s| s.0 != 0 && s.0 > TABLE_SECTION)
+                .unwrap_or(module.sections.len());
+            module.sections.insert(pos, (TABLE_SECTION, Vec::new()));
+        }
+        Ok(module)
+    }
+
+    pub fn imported_functions(&self) -> u32 {
+        self.imports.iter().filter(|i| i.2 == 0).count() as u32
+    }
+
+    pub fn function_type(&self, func: u32) -> u32 {
+        let imported = self.imported_functions();
+        if func < imported {
+            let import = self.imports.iter().filter(|i| i.2 == 0).nth(func as usize).unwrap();
+            Reader { data: &import.3, pos: 0 }.u32().unwrap()
+        } else {
+            self.functions[(func - imported) as usize]
+        }
+    }
+
+    pub fn set_import_type(&mut self, func: u32, ty: u32) {
+        let import = self
+            .imports
+            .iter_mut()
+            .filter(|i| i.2 == 0)
+            .nth(func as usize)
+            .unwrap();
+        import.3.truncate(0);
+        leb(&mut import.3, ty);
+    }
+
+    pub fn exported_function(&self, name: &str) -> Result<u32, Error> {
+        match self.exports.iter().find(|e| e.0 == name && e.1 == 0) {
+            Some(e) => Ok(e.2),
+            None => bail!("failed to find the exported function `{}`", name),
+        }
+    }
+
+    pub fn add_type(&mut self, ty: (Vec<u8>, Vec<u8>)) -> u32 {
+        if let Some(i) = self.types.iter().position(|t| *t == ty) {
+            return i as u32;
+        }
+        self.types.push(ty);
+        self.types.len() as u32 - 1
+    }
+
+    /// Appends a growable table of `elem` values with `initial` slots,
+    /// returning its index.
+    pub fn add_table(&mut self, elem: u8, initial: u32) -> u32 {
+        let idx = self.imports.iter().filter(|i| i.2 == 1).count() + self.tables.len();
+        let mut table = vec![elem, 0];
+        leb(&mut table, initial);
+        self.tables.push(table);
+        idx as u32
+    }
+
+    pub fn emit(self) -> Vec<u8> {
+        let mut dst = b"\0asm\x01\0\0\0".to_vec();
+        for (id, payload) in self.sections.iter() {
+            let mut new_payload = Vec::new();
+            let payload = match *id {
+                TYPE => {
+                    leb(&mut new_payload, self.types.len() as u32);
+                    for (params, results) in self.types.iter() {
+                        new_payload.push(0x60);
+                        leb(&mut new_payload, params.len() as u32);
+                        new_payload.extend_from_slice(params);
+                        leb(&mut new_payload, results.len() as u32);
+                        new_payload.extend_from_slice(results);
+                    }
+                    &new_payload
+                }
+                IMPORT => {
+                    leb(&mut new_payload, self.imports.len() as u32);
+                    for (module, field, kind, desc) in self.imports.iter() {
+                        name(&mut new_payload, module);
+                        name(&mut new_payload, field);
+                        new_payload.push(*kind);
+                        new_payload.extend_from_slice(desc);
+                    }
+                    &new_payload
+                }
+                FUNCTION => {
+                    leb(&mut new_payload, self.functions.len() as u32);
+                    for ty in self.functions.iter() {
+                        leb(&mut new_payload, *ty);
+                    }
+                    &new_payload
+                }
+                TABLE_SECTION => {
+                    leb(&mut new_payload, self.tables.len() as u32);
+                    for table in self.tables.iter() {
+                        new_payload.extend_from_slice(table);
+                    }
+                    &new_payload
+                }
+                EXPORT => {
+                    leb(&mut new_payload, self.exports.len() as u32);
+                    for (field, kind, idx) in self.exports.iter() {
+                        name(&mut new_payload, field);
+                        new_payload.push(*kind);
+                        leb(&mut new_payload, *idx);
+                    }
+                    &new_payload
+                }
+                CODE => {
+                    leb(&mut new_payload, self.code.len() as u32);
+                    for body in self.code.iter() {
+                        leb(&mut new_payload, body.len() as u32);
+                        new_payload.extend_from_slice(body);
+                    }
+                    &new_payload
+                }
+                _ => payload,
+            };
+            dst.push(*id);
+            leb(&mut dst, payload.len() as u32);
+            dst.extend_from_slice(payload);
+        }
+        dst
+    }
+}
+
+pub struct Reader<'a> {
+    pub data: &'a [u8],
+    pub pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        match self.data.get(self.pos) {
+            Some(b) => {
+                self.pos += 1;
+                Ok(*b)
+            }
+            None => bail!("unexpected end of wasm module"),
+        }
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        let mut ret = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            ret |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(ret);
+            }
+            shift += 7;
+            if shift > 28 {
+                bail!("invalid LEB128 integer in wasm module");
+            }
+        }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() - self.pos < len {
+            bail!("unexpected end of wasm module");
+        }
+        let ret = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(ret)
+    }
+
+    pub fn vec(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    pub fn name(&mut self) -> Result<String, Error> {
+        Ok(String::from_utf8(self.vec()?.to_vec())?)
+    }
+
+    pub fn limits(&mut self) -> Result<(), Error> {
+        let flags = self.u8()?;
+        self.u32()?;
+        if flags & 1 != 0 {
+            self.u32()?;
+        }
+        Ok(())
+    }
+}
+
+pub fn leb(dst: &mut Vec<u8>, mut n: u32) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            dst.push(byte);
+            return;
+        }
+        dst.push(byte | 0x80);
+    }
+}
+
+pub fn name(dst: &mut Vec<u8>, s: &str) {
+    leb(dst, s.len() as u32);
+    dst.extend_from_slice(s.as_bytes());
+}
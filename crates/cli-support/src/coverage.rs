@@ -0,0 +1,731 @@
+//! Code coverage for wasm modules, for `wasm-bindgen-test-runner`.
+//!
+//! The module is instrumented with a `u32` counter for the entry of every
+//! function and for every block of code within it, that is the arms of an
+//! `if`, the body of a `loop` and the code following the `end` of any block
+//! or a conditional branch. The counters live in memory just past the module's initial memory,
+//! which is grown to make room for them, and each is incremented with a few
+//! instructions at the start of its code.
+//!
+//! After running the module the counters are read back out of memory and
+//! mapped onto the Rust sources they were compiled from, using the DWARF line
+//! tables and the name section, to make an lcov report. This means the wasm
+//! file needs to be compiled with debug info.
+//!
+//! Functions which are only run by the interpreter to describe types aren't
+//! instrumented, since the interpreter can't run the counters and they're not
+//! interesting to cover anyway. Functions which are also called by other
+//! code are instrumented, but are copied first and the copies are what
+//! describes types.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use std::mem;
+
+use addr2line::gimli::{self, EndianSlice, LittleEndian};
+use addr2line::Context;
+use failure::{Error, ResultExt};
+use parity_wasm::elements::*;
+use rustc_demangle::demangle;
+
+/// Where the counters of an instrumented module are and which source they
+/// count.
+pub struct Coverage {
+    base: u32,
+    counters: usize,
+    functions: Vec<Function>,
+    lines: Vec<Line>,
+}
+
+struct Function {
+    name: String,
+    file: String,
+    line: u32,
+    counter: usize,
+}
+
+/// A line of source which some of the instructions counted by `counter` were
+/// compiled from.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Line {
+    file: String,
+    line: u32,
+    counter: usize,
+}
+
+/// Instruments the wasm module `wasm` with coverage counters, returning the
+/// instrumented module.
+pub fn instrument(wasm: &[u8]) -> Result<(Module, Coverage), Error> {
+    let offsets = instruction_offsets(wasm).context("failed to parse the code section")?;
+    let module = Module::deserialize(&mut &wasm[..]).context("failed to deserialize wasm module")?;
+    let module = module.parse_names().unwrap_or_else(|(_, m)| m);
+
+    // Copy out the debug info so it's not borrowed from the module while the
+    // module is being changed.
+    let mut sections = HashMap::new();
+    for section in module.sections() {
+        if let Section::Custom(s) = section {
+            if s.name().starts_with(".debug_") {
+                sections.insert(s.name().to_string(), s.payload().to_vec());
+            }
+        }
+    }
+    if !sections.contains_key(".debug_line") {
+        bail!(
+            "the wasm file has no DWARF debug info, which is needed to map \
+             coverage back to the source; make sure it's compiled with `debug = true`"
+        );
+    }
+    let dwarf = gimli::Dwarf::load(|id| -> Result<_, gimli::Error> {
+        let data = sections.get(id.name()).map(|s| &s[..]).unwrap_or(&[]);
+        Ok(EndianSlice::new(data, LittleEndian))
+    })?;
+    let debug_info = Context::from_dwarf(dwarf).context("failed to parse DWARF debug info")?;
+
+    instrument_module(module, &offsets, |offset| {
+        let location = debug_info
+            .find_location(offset as u64)?
+            .and_then(|l| match (l.file, l.line) {
+                (Some(file), Some(line)) if line > 0 => Some((file.to_string(), line)),
+                _ => None,
+            });
+        Ok(location)
+    })
+}
+
+/// Instruments `module`, whose instructions are at `offsets`, finding the
+/// source each instruction was compiled from with `locate`.
+fn instrument_module<F>(
+    mut module: Module,
+    offsets: &[Vec<u32>],
+    locate: F,
+) -> Result<(Module, Coverage), Error>
+where
+    F: Fn(u32) -> Result<Option<(String, u32)>, Error>,
+{
+    let names = function_names(&module);
+    let (skip, shared) = descriptors(&module);
+    let imported = module.import_count(ImportCountType::Function) as u32;
+    let defined = module.code_section().map(|s| s.bodies().len()).unwrap_or(0) as u32;
+    let copies = shared
+        .iter()
+        .enumerate()
+        .map(|(i, idx)| (*idx, imported + defined + i as u32))
+        .collect::<HashMap<_, _>>();
+    let mut copied = Vec::new();
+
+    let base = match module.memory_section() {
+        Some(s) if s.entries().len() == 1 => s.entries()[0].limits().initial() * 65536,
+        _ => bail!("coverage requires the wasm module to define exactly one memory"),
+    };
+
+    let mut coverage = Coverage {
+        base,
+        counters: 0,
+        functions: Vec::new(),
+        lines: Vec::new(),
+    };
+    let bodies = match module.code_section_mut() {
+        Some(s) => s.bodies_mut(),
+        None => bail!("the wasm module has no code section"),
+    };
+    if bodies.len() != offsets.len() {
+        bail!("found a different number of functions than the wasm module has");
+    }
+    for (i, (body, offsets)) in bodies.iter_mut().zip(offsets).enumerate() {
+        let idx = imported + i as u32;
+        if body.code().elements().len() != offsets.len() {
+            bail!("found a different number of instructions in function {}", idx);
+        }
+        if copies.contains_key(&idx) {
+            let mut copy = body.clone();
+            redirect_calls(copy.code_mut().elements_mut(), &copies);
+            copied.push((idx, copy));
+        } else if skip.contains(&idx) {
+            redirect_calls(body.code_mut().elements_mut(), &copies);
+            continue;
+        }
+        let code = body.code_mut().elements_mut();
+
+        let mut locations = Vec::with_capacity(offsets.len());
+        for offset in offsets {
+            locations.push(locate(*offset)?);
+        }
+        let (file, line) = match locations.iter().filter_map(|l| l.as_ref()).next() {
+            Some(l) => l.clone(),
+            // Nothing to report for functions without any source.
+            None => continue,
+        };
+        let name = match names.get(&idx) {
+            Some(name) => format!("{:#}", demangle(name)),
+            None => format!("func{}", idx),
+        };
+
+        let mut counter = coverage.counters;
+        coverage.functions.push(Function { name, file, line, counter });
+        let mut instrumented = increment(base, &mut coverage.counters);
+        let mut depth = 0;
+        for (instr, location) in mem::replace(code, Vec::new()).into_iter().zip(locations) {
+            if let Some((file, line)) = location {
+                coverage.lines.push(Line { file, line, counter });
+            }
+            let starts_block = match instr {
+                Instruction::Block(_) => {
+                    depth += 1;
+                    false
+                }
+                Instruction::Loop(_) | Instruction::If(_) => {
+                    depth += 1;
+                    true
+                }
+                Instruction::Else => true,
+                // What follows a branch may run fewer times than what came
+                // before it.
+                Instruction::BrIf(_) | Instruction::BrTable(..) => true,
+                // The last `end` is the end of the function itself.
+                Instruction::End if depth > 0 => {
+                    depth -= 1;
+                    true
+                }
+                _ => false,
+            };
+            instrumented.push(instr);
+            if starts_block {
+                counter = coverage.counters;
+                instrumented.extend(increment(base, &mut coverage.counters));
+            }
+        }
+        *code = instrumented;
+    }
+    coverage.lines.sort();
+    coverage.lines.dedup();
+
+    for (idx, body) in copied {
+        let functions = module.function_section_mut().unwrap().entries_mut();
+        let ty = functions[(idx - imported) as usize].type_ref();
+        functions.push(Func::new(ty));
+        module.code_section_mut().unwrap().bodies_mut().push(body);
+    }
+
+    // Make room for the counters in memory. Everything past the initial
+    // memory is only ever handed out by growing memory, so they won't be
+    // clobbered.
+    let pages = (coverage.counters as u32 * 4 + 65535) / 65536;
+    let memory = &mut module.memory_section_mut().unwrap().entries_mut()[0];
+    let limits = *memory.limits();
+    let initial = limits.initial() + pages;
+    if let Some(maximum) = limits.maximum() {
+        if maximum < initial {
+            bail!("the wasm module's maximum memory is too small to fit coverage counters");
+        }
+    }
+    *memory = MemoryType::new(initial, limits.maximum());
+
+    Ok((module, coverage))
+}
+
+impl Coverage {
+    /// The address in memory of the first counter.
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    /// The number of counters, each of which is a little-endian `u32`.
+    pub fn counters(&self) -> usize {
+        self.counters
+    }
+
+    /// Renders an lcov report of the counters, given the value of each one.
+    pub fn lcov(&self, counts: &[u64]) -> String {
+        #[derive(Default)]
+        struct File<'a> {
+            functions: BTreeMap<&'a str, (u32, u64)>,
+            lines: BTreeMap<u32, u64>,
+        }
+
+        // Generic functions are instantiated once per set of type
+        // parameters, which are all merged here. A line's count is that of
+        // the block run most often among those compiled from it.
+        let mut files = BTreeMap::new();
+        for f in self.functions.iter() {
+            let file = files.entry(&f.file[..]).or_insert_with(File::default);
+            let entry = file.functions.entry(&f.name[..]).or_insert((f.line, 0));
+            entry.0 = entry.0.min(f.line);
+            entry.1 += counts[f.counter];
+        }
+        for l in self.lines.iter() {
+            let file = files.entry(&l.file[..]).or_insert_with(File::default);
+            let count = file.lines.entry(l.line).or_insert(0);
+            *count = (*count).max(counts[l.counter]);
+        }
+
+        let mut lcov = String::new();
+        for (path, file) in files {
+            lcov.push_str("TN:\n");
+            drop(writeln!(lcov, "SF:{}", path));
+            for (name, (line, _)) in file.functions.iter() {
+                drop(writeln!(lcov, "FN:{},{}", line, name));
+            }
+            for (name, (_, count)) in file.functions.iter() {
+                drop(writeln!(lcov, "FNDA:{},{}", count, name));
+            }
+            drop(writeln!(lcov, "FNF:{}", file.functions.len()));
+            drop(writeln!(
+                lcov,
+                "FNH:{}",
+                file.functions.values().filter(|f| f.1 > 0).count()
+            ));
+            for (line, count) in file.lines.iter() {
+                drop(writeln!(lcov, "DA:{},{}", line, count));
+            }
+            drop(writeln!(lcov, "LF:{}", file.lines.len()));
+            drop(writeln!(
+                lcov,
+                "LH:{}",
+                file.lines.values().filter(|c| **c > 0).count()
+            ));
+            lcov.push_str("end_of_record\n");
+        }
+        lcov
+    }
+}
+
+/// Instructions incrementing the next counter.
+fn increment(base: u32, counters: &mut usize) -> Vec<Instruction> {
+    let addr = (base + *counters as u32 * 4) as i32;
+    *counters += 1;
+    vec![
+        Instruction::I32Const(addr),
+        Instruction::I32Const(addr),
+        Instruction::I32Load(2, 0),
+        Instruction::I32Const(1),
+        Instruction::I32Add,
+        Instruction::I32Store(2, 0),
+    ]
+}
+
+/// Returns the offset of every instruction of every function body, relative
+/// to the start of the code section's payload as DWARF addresses are.
+///
+/// These are read from the original bytes rather than from a serialized
+/// `Module` because LLVM pads LEB128 immediates, so the encoding of an
+/// instruction isn't always the one `parity-wasm` would pick.
+fn instruction_offsets(wasm: &[u8]) -> Result<Vec<Vec<u32>>, Error> {
+    if wasm.len() < 8 {
+        bail!("not a wasm file");
+    }
+    let mut rdr = &wasm[8..];
+    let code = loop {
+        if rdr.is_empty() {
+            return Ok(Vec::new());
+        }
+        let id = rdr[0];
+        rdr = &rdr[1..];
+        let size = u32::from(VarUint32::deserialize(&mut rdr)?) as usize;
+        if rdr.len() < size {
+            bail!("section extends past the end of the file");
+        }
+        let (payload, rest) = rdr.split_at(size);
+        if id == 10 {
+            break payload;
+        }
+        rdr = rest;
+    };
+
+    let offset = |rdr: &[u8]| (code.len() - rdr.len()) as u32;
+    let mut rdr = code;
+    let mut bodies = Vec::new();
+    for _ in 0..u32::from(VarUint32::deserialize(&mut rdr)?) {
+        let size = u32::from(VarUint32::deserialize(&mut rdr)?);
+        let end = offset(rdr) + size;
+        for _ in 0..u32::from(VarUint32::deserialize(&mut rdr)?) {
+            Local::deserialize(&mut rdr)?;
+        }
+        let mut offsets = Vec::new();
+        while offset(rdr) < end {
+            offsets.push(offset(rdr));
+            Instruction::deserialize(&mut rdr)?;
+        }
+        bodies.push(offsets);
+    }
+    Ok(bodies)
+}
+
+fn function_names(module: &Module) -> HashMap<u32, String> {
+    match module.names_section() {
+        Some(NameSection::Function(s)) => s
+            .names()
+            .iter()
+            .map(|(idx, name)| (idx, name.clone()))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// Returns every function which describes types for the interpreter: the
+/// `__wbindgen_describe_*` exports, functions calling the imports they
+/// describe with, and everything they call. Also returns those of them
+/// which are called by other code too, in order.
+fn descriptors(module: &Module) -> (HashSet<u32>, Vec<u32>) {
+    let mut describe_imports = HashSet::new();
+    if let Some(imports) = module.import_section() {
+        let functions = imports.entries().iter().filter(|e| match e.external() {
+            External::Function(_) => true,
+            _ => false,
+        });
+        for (idx, entry) in functions.enumerate() {
+            if entry.field().starts_with("__wbindgen_describe") {
+                describe_imports.insert(idx as u32);
+            }
+        }
+    }
+
+    let imported = module.import_count(ImportCountType::Function) as u32;
+    let bodies = module.code_section().map(|s| s.bodies()).unwrap_or(&[]);
+    let calls = |idx: u32| {
+        let body = match idx.checked_sub(imported) {
+            Some(i) => &bodies[i as usize],
+            None => return Vec::new(),
+        };
+        body.code()
+            .elements()
+            .iter()
+            .filter_map(|i| match i {
+                Instruction::Call(f) => Some(*f),
+                _ => None,
+            })
+            .collect()
+    };
+    let reachable = |mut next: Vec<u32>| {
+        let mut found = HashSet::new();
+        while let Some(idx) = next.pop() {
+            if found.insert(idx) {
+                next.extend(calls(idx));
+            }
+        }
+        found
+    };
+
+    // Other code is run from the other exports, the table and the start
+    // function.
+    let (mut describe, mut other) = (Vec::new(), Vec::new());
+    if let Some(exports) = module.export_section() {
+        for entry in exports.entries() {
+            if let Internal::Function(idx) = entry.internal() {
+                if entry.field().starts_with("__wbindgen_describe") {
+                    describe.push(*idx);
+                } else {
+                    other.push(*idx);
+                }
+            }
+        }
+    }
+    for i in 0..bodies.len() as u32 {
+        if calls(imported + i).iter().any(|f| describe_imports.contains(f)) {
+            describe.push(imported + i);
+        }
+    }
+    if let Some(elements) = module.elements_section() {
+        for segment in elements.entries() {
+            other.extend(segment.members());
+        }
+    }
+    if let Some(start) = module.start_section() {
+        other.push(start);
+    }
+
+    let descriptors = reachable(describe);
+    let other = reachable(other);
+    let mut shared = descriptors
+        .iter()
+        .cloned()
+        .filter(|idx| *idx >= imported && other.contains(idx))
+        .collect::<Vec<_>>();
+    shared.sort();
+    (descriptors, shared)
+}
+
+/// Points calls to functions which have been copied at their copies.
+fn redirect_calls(code: &mut [Instruction], copies: &HashMap<u32, u32>) {
+    for instr in code {
+        if let Instruction::Call(f) = instr {
+            if let Some(copy) = copies.get(f) {
+                *f = *copy;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u32 = 65536;
+
+    /// A module with one memory page importing functions named `imports`,
+    /// defining functions with `bodies` and exporting `exports`.
+    fn module(imports: &[&str], bodies: Vec<Vec<Instruction>>, exports: &[(&str, u32)]) -> Module {
+        let ty = Type::Function(FunctionType::new(Vec::new(), None));
+        let imports = imports
+            .iter()
+            .map(|name| {
+                ImportEntry::new("env".to_string(), name.to_string(), External::Function(0))
+            })
+            .collect();
+        let exports = exports
+            .iter()
+            .map(|(name, idx)| ExportEntry::new(name.to_string(), Internal::Function(*idx)))
+            .collect();
+        let functions = bodies.iter().map(|_| Func::new(0)).collect();
+        let bodies = bodies
+            .into_iter()
+            .map(|code| FuncBody::new(Vec::new(), Instructions::new(code)))
+            .collect();
+        Module::new(vec![
+            Section::Type(TypeSection::with_types(vec![ty])),
+            Section::Import(ImportSection::with_entries(imports)),
+            Section::Function(FunctionSection::with_entries(functions)),
+            Section::Memory(MemorySection::with_entries(vec![MemoryType::new(1, None)])),
+            Section::Export(ExportSection::with_entries(exports)),
+            Section::Code(CodeSection::with_bodies(bodies)),
+        ])
+    }
+
+    /// Instruments `module`, pretending that the instructions of the `n`th
+    /// function are at offsets `100 * (n + 1)` onwards, each on the line of
+    /// its offset.
+    fn instrument(module: Module) -> (Module, Coverage) {
+        let offsets = module
+            .code_section()
+            .unwrap()
+            .bodies()
+            .iter()
+            .enumerate()
+            .map(|(n, body)| {
+                let start = 100 * (n as u32 + 1);
+                (start..start + body.code().elements().len() as u32).collect()
+            })
+            .collect::<Vec<Vec<u32>>>();
+        let locate = |offset| Ok(Some(("src/lib.rs".to_string(), offset)));
+        instrument_module(module, &offsets, locate).unwrap()
+    }
+
+    fn code(module: &Module, idx: usize) -> &[Instruction] {
+        module.code_section().unwrap().bodies()[idx].code().elements()
+    }
+
+    fn inc(counter: usize) -> Vec<Instruction> {
+        increment(BASE, &mut counter.clone())
+    }
+
+    fn line(line: u32, counter: usize) -> Line {
+        Line {
+            file: "src/lib.rs".to_string(),
+            line,
+            counter,
+        }
+    }
+
+    #[test]
+    fn instruction_offsets_of_original_encoding() {
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            // A type section with `fn()`.
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            // A function section with two functions.
+            0x03, 0x03, 0x02, 0x00, 0x00,
+            // A custom section named `x`.
+            0x00, 0x02, 0x01, b'x',
+            // The code section.
+            0x0a, 0x0d, 0x02,
+            // `i32.const 1` with a padded immediate, `drop` and `end`.
+            0x06, 0x00, 0x41, 0x81, 0x00, 0x1a, 0x0b,
+            // An `i32` local and `end`.
+            0x04, 0x01, 0x01, 0x7f, 0x0b,
+        ];
+        assert_eq!(instruction_offsets(&wasm).unwrap(), vec![vec![3, 6, 7], vec![12]]);
+        assert_eq!(instruction_offsets(&wasm[..23]).unwrap(), Vec::<Vec<u32>>::new());
+        assert!(instruction_offsets(&wasm[..4]).is_err());
+        assert!(instruction_offsets(&wasm[..30]).is_err());
+    }
+
+    #[test]
+    fn counts_blocks() {
+        let body = vec![
+            Instruction::I32Const(0),
+            Instruction::If(BlockType::NoResult),
+            Instruction::Nop,
+            Instruction::Else,
+            Instruction::Nop,
+            Instruction::End,
+            Instruction::Block(BlockType::NoResult),
+            Instruction::I32Const(1),
+            Instruction::BrIf(0),
+            Instruction::Nop,
+            Instruction::End,
+            Instruction::Loop(BlockType::NoResult),
+            Instruction::I32Const(0),
+            Instruction::BrTable(Box::new([0]), 0),
+            Instruction::End,
+            Instruction::End,
+        ];
+        let (module, coverage) = instrument(module(&[], vec![body], &[("run", 0)]));
+
+        let expected = vec![
+            inc(0),
+            vec![Instruction::I32Const(0), Instruction::If(BlockType::NoResult)],
+            inc(1),
+            vec![Instruction::Nop, Instruction::Else],
+            inc(2),
+            vec![Instruction::Nop, Instruction::End],
+            inc(3),
+            vec![
+                Instruction::Block(BlockType::NoResult),
+                Instruction::I32Const(1),
+                Instruction::BrIf(0),
+            ],
+            inc(4),
+            vec![Instruction::Nop, Instruction::End],
+            inc(5),
+            vec![Instruction::Loop(BlockType::NoResult)],
+            inc(6),
+            vec![Instruction::I32Const(0), Instruction::BrTable(Box::new([0]), 0)],
+            inc(7),
+            vec![Instruction::End],
+            inc(8),
+            vec![Instruction::End],
+        ];
+        assert_eq!(code(&module, 0), &expected.concat()[..]);
+        assert_eq!(coverage.base(), BASE);
+        assert_eq!(coverage.counters(), 9);
+        let memory = &module.memory_section().unwrap().entries()[0];
+        assert_eq!(memory.limits().initial(), 2);
+
+        // Every instruction's line is counted by the block it's in.
+        let lines = [0, 0, 1, 1, 2, 2, 3, 3, 3, 4, 4, 5, 6, 6, 7, 8];
+        let lines = lines
+            .iter()
+            .enumerate()
+            .map(|(i, counter)| line(100 + i as u32, *counter))
+            .collect::<Vec<_>>();
+        assert!(coverage.lines == lines);
+        assert_eq!(coverage.functions.len(), 1);
+        assert_eq!(coverage.functions[0].name, "func0");
+        assert_eq!(coverage.functions[0].line, 100);
+    }
+
+    #[test]
+    fn copies_functions_shared_with_descriptors() {
+        let describe = 0;
+        let (run, describe_foo, shared, helper) = (1, 2, 3, 4);
+        let bodies = vec![
+            vec![Instruction::Call(shared), Instruction::End],
+            vec![
+                Instruction::I32Const(7),
+                Instruction::Call(describe),
+                Instruction::Call(shared),
+                Instruction::Call(helper),
+                Instruction::End,
+            ],
+            vec![Instruction::Nop, Instruction::End],
+            vec![Instruction::End],
+        ];
+        let exports = [("run", run), ("__wbindgen_describe_foo", describe_foo)];
+        let (module, coverage) = instrument(module(&["__wbindgen_describe"], bodies, &exports));
+
+        // The copy of `shared` is appended, and called by the descriptor.
+        let copy = 5;
+        assert_eq!(module.function_section().unwrap().entries().len(), 5);
+        let run_code = [inc(0), vec![Instruction::Call(shared), Instruction::End]].concat();
+        assert_eq!(code(&module, 0), &run_code[..]);
+        assert_eq!(
+            code(&module, 1),
+            &[
+                Instruction::I32Const(7),
+                Instruction::Call(describe),
+                Instruction::Call(copy),
+                Instruction::Call(helper),
+                Instruction::End,
+            ]
+        );
+        let shared_code = [inc(1), vec![Instruction::Nop, Instruction::End]].concat();
+        assert_eq!(code(&module, 2), &shared_code[..]);
+        assert_eq!(code(&module, 3), &[Instruction::End]);
+        assert_eq!(code(&module, 4), &[Instruction::Nop, Instruction::End]);
+
+        // `helper` is only called by the descriptor, so it's left alone.
+        assert_eq!(coverage.counters(), 2);
+        let functions = coverage
+            .functions
+            .iter()
+            .map(|f| (&f.name[..], f.counter))
+            .collect::<Vec<_>>();
+        assert_eq!(functions, [("func1", 0), ("func3", 1)]);
+    }
+
+    #[test]
+    fn requires_debug_info() {
+        let wasm = parity_wasm::serialize(module(&[], vec![vec![Instruction::End]], &[])).unwrap();
+        let err = super::instrument(&wasm).err().unwrap();
+        assert!(err.to_string().contains("has no DWARF debug info"));
+    }
+
+    #[test]
+    fn lcov_report() {
+        let function = |name: &str, file: &str, line, counter| Function {
+            name: name.to_string(),
+            file: file.to_string(),
+            line,
+            counter,
+        };
+        let line = |file: &str, line, counter| Line {
+            file: file.to_string(),
+            line,
+            counter,
+        };
+        let coverage = Coverage {
+            base: BASE,
+            counters: 4,
+            // `a::f` is generic with two instantiations.
+            functions: vec![
+                function("a::f", "src/a.rs", 5, 2),
+                function("a::f", "src/a.rs", 3, 0),
+                function("b::g", "src/b.rs", 1, 3),
+            ],
+            lines: vec![
+                line("src/a.rs", 3, 0),
+                line("src/a.rs", 4, 1),
+                line("src/a.rs", 4, 2),
+                line("src/b.rs", 1, 3),
+                line("src/b.rs", 2, 3),
+            ],
+        };
+        assert_eq!(
+            coverage.lcov(&[2, 0, 5, 0]),
+            "\
+TN:
+SF:src/a.rs
+FN:3,a::f
+FNDA:7,a::f
+FNF:1
+FNH:1
+DA:3,2
+DA:4,5
+LF:2
+LH:2
+end_of_record
+TN:
+SF:src/b.rs
+FN:1,b::g
+FNDA:0,b::g
+FNF:1
+FNH:0
+DA:1,0
+DA:2,0
+LF:2
+LH:0
+end_of_record
+"
+        );
+    }
+}
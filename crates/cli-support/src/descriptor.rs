@@ -36,9 +36,11 @@ tys! {
     OPTIONAL
     UNIT
     CLAMPED
+    I128
+    U128
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, PartialEq)]
 pub enum Descriptor {
     I8,
     U8,
@@ -48,6 +50,8 @@ pub enum Descriptor {
     U32,
     I64,
     U64,
+    I128,
+    U128,
     F32,
     F64,
     Boolean,
@@ -67,13 +71,13 @@ pub enum Descriptor {
     Clamped(Box<Descriptor>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, PartialEq)]
 pub struct Function {
     pub arguments: Vec<Descriptor>,
     pub ret: Descriptor,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, PartialEq)]
 pub struct Closure {
     pub function: Function,
     pub mutable: bool,
@@ -113,6 +117,8 @@ impl Descriptor {
             U16 => Descriptor::U16,
             U32 => Descriptor::U32,
             U64 => Descriptor::U64,
+            I128 => Descriptor::I128,
+            U128 => Descriptor::U128,
             F32 => Descriptor::F32,
             F64 => Descriptor::F64,
             BOOLEAN => Descriptor::Boolean,
@@ -189,6 +195,14 @@ impl Descriptor {
         }
     }
 
+    pub fn get_128(&self) -> Option<bool> {
+        match *self {
+            Descriptor::I128 => Some(true),
+            Descriptor::U128 => Some(false),
+            _ => None,
+        }
+    }
+
     pub fn is_ref_anyref(&self) -> bool {
         match *self {
             Descriptor::Ref(ref s) => s.is_anyref(),
@@ -1,6 +1,8 @@
 use failure::Error;
 
 use super::Context;
+use anyref::{self, Ownership};
+use bigint;
 use descriptor::{Descriptor, Function};
 
 /// Helper struct for manufacturing a shim in JS used to translate JS types to
@@ -45,6 +47,33 @@ pub struct Js2Rust<'a, 'b: 'a> {
     /// The string value here is the class that this should be a constructor
     /// for.
     constructor: Option<String>,
+
+    /// Name of the wasm export this shim invokes, if it invokes one directly.
+    ///
+    /// With `--reference-types` such exports are wrapped to take and return
+    /// JS values directly, so the shim doesn't need to put them in the table.
+    wasm_export: Option<String>,
+
+    /// Rust arguments which are JS values passed directly to `wasm_export`.
+    anyref_args: Vec<(String, Ownership)>,
+
+    /// Whether `wasm_export` returns a JS value directly.
+    anyref_ret: bool,
+
+    /// Whether `wasm_export` returns a `(ptr, len)` pair rather than taking a
+    /// return pointer, with `--multi-value`.
+    multi_value_ret: bool,
+
+    /// Rust arguments which are BigInts passed directly to `wasm_export` as
+    /// `i64` values, with `--bigint`.
+    bigint_args: Vec<String>,
+
+    /// Whether `wasm_export` returns an `i64` value directly.
+    bigint_ret: bool,
+
+    /// Name this shim's calls are recorded under with `--profile-boundary`,
+    /// and the creation site of objects it allocates with `--debug`.
+    instrument: Option<String>,
 }
 
 impl<'a, 'b> Js2Rust<'a, 'b> {
@@ -60,6 +89,13 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
             ret_ty: String::new(),
             ret_expr: String::new(),
             constructor: None,
+            wasm_export: None,
+            anyref_args: Vec::new(),
+            anyref_ret: false,
+            multi_value_ret: false,
+            bigint_args: Vec::new(),
+            bigint_ret: false,
+            instrument: None,
         }
     }
 
@@ -78,6 +114,30 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
         self
     }
 
+    /// Flag this shim as invoking the wasm export `name` directly, which must
+    /// come before any arguments are processed.
+    pub fn wasm_export(&mut self, name: &str) -> &mut Self {
+        self.wasm_export = Some(name.to_string());
+        self
+    }
+
+    /// Records calls to this shim as calls to the export `name` with
+    /// `--profile-boundary` and `--debug`.
+    pub fn instrument(&mut self, name: &str) -> &mut Self {
+        if self.cx.config.profile_boundary || self.cx.config.debug {
+            self.instrument = Some(name.to_string());
+        }
+        self
+    }
+
+    fn passes_anyref(&self) -> bool {
+        self.cx.config.reference_types && self.wasm_export.is_some()
+    }
+
+    fn passes_bigint(&self) -> bool {
+        self.cx.config.bigint && self.wasm_export.is_some()
+    }
+
     /// Flag this shim as a method call into Rust, so the first Rust argument
     /// passed should be `this.ptr`.
     pub fn method(&mut self, method: bool, consumed: bool) -> &mut Self {
@@ -96,6 +156,8 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
                      this.ptr = 0;\n\
                      ",
                 );
+                let untrack = self.cx.untrack_live_object("ptr");
+                self.prelude(&untrack);
                 self.rust_arguments.insert(0, "ptr".to_string());
             } else {
                 self.rust_arguments.insert(0, "this.ptr".to_string());
@@ -194,6 +256,11 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
 
         if arg.is_anyref() {
             self.js_arguments.push((name.clone(), "any".to_string()));
+            if self.passes_anyref() && !optional {
+                self.anyref_args.push((name.clone(), Ownership::Owned));
+                self.rust_arguments.push(name);
+                return Ok(self);
+            }
             self.cx.expose_add_heap_object();
             if optional {
                 self.cx.expose_is_like_none();
@@ -332,6 +399,8 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
                     i = i,
                     arg = name
                 ));
+                let untrack = self.cx.untrack_live_object(&format!("ptr{}", i));
+                self.prelude(&untrack);
                 self.rust_arguments.push(format!("ptr{}", i));
             }
             return Ok(self);
@@ -350,6 +419,12 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
         }
 
         if let Some(signed) = arg.get_64() {
+            self.js_arguments.push((name.clone(), "BigInt".to_string()));
+            if self.passes_bigint() {
+                self.bigint_args.push(name.clone());
+                self.rust_arguments.push(name);
+                return Ok(self);
+            }
             let f = if signed {
                 self.cx.expose_int64_cvt_shim()
             } else {
@@ -357,7 +432,6 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
             };
             self.cx.expose_uint32_memory();
             self.cx.expose_global_argument_ptr()?;
-            self.js_arguments.push((name.clone(), "BigInt".to_string()));
             self.prelude(&format!(
                 "
                  {f}[0] = {name};
@@ -373,10 +447,52 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
             return Ok(self);
         }
 
+        if arg.get_128().is_some() {
+            self.js_arguments.push((name.clone(), "BigInt".to_string()));
+            let high = format!("{} >> BigInt(64)", name);
+            if self.passes_bigint() {
+                self.bigint_args.push(name.clone());
+                self.bigint_args.push(high.clone());
+                self.rust_arguments.push(name);
+                self.rust_arguments.push(high);
+                return Ok(self);
+            }
+            let words = self.cx.expose_u32x4_cvt_shim();
+            let f = self.cx.expose_uint128_cvt_shim();
+            self.prelude(&format!(
+                "
+                 {f}[0] = {name};
+                 {f}[1] = {high};
+                 const w0_{i} = {words}[0];
+                 const w1_{i} = {words}[1];
+                 const w2_{i} = {words}[2];
+                 const w3_{i} = {words}[3];
+                 ",
+                i = i,
+                f = f,
+                words = words,
+                name = name,
+                high = high,
+            ));
+            for word in 0..4 {
+                self.rust_arguments.push(format!("w{}_{}", word, i));
+            }
+            return Ok(self);
+        }
+
         if arg.is_ref_anyref() {
             self.js_arguments.push((name.clone(), "any".to_string()));
+            if self.passes_anyref() {
+                self.anyref_args.push((name.clone(), Ownership::Borrowed));
+                self.rust_arguments.push(name);
+                return Ok(self);
+            }
             self.cx.expose_borrowed_objects();
-            self.finally("stack.pop();");
+            if self.cx.config.reference_types {
+                self.finally("dropBorrowedObject();");
+            } else {
+                self.finally("stack.pop();");
+            }
             self.rust_arguments
                 .push(format!("addBorrowedObject({})", name));
             return Ok(self);
@@ -411,6 +527,8 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
             match &self.constructor {
                 Some(class) if class == name => {
                     self.ret_expr = format!("this.ptr = RET;");
+                    let track = self.cx.track_live_object("this.ptr", name);
+                    self.ret_expr.push_str(&track);
                     if self.cx.config.weak_refs {
                         self.ret_expr.push_str(&format!("\
                             addCleanup(this, this.ptr, free{});
@@ -447,9 +565,26 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
         if let Some(ty) = ty.vector_kind() {
             self.ret_ty = ty.js_ty().to_string();
             let f = self.cx.expose_get_vector_from_wasm(ty);
+            self.cx.require_internal_export("__wbindgen_free")?;
+            let guard = if optional { "if (rustptr === 0) return;" } else { "" };
+            if self.cx.config.multi_value && self.wasm_export.is_some() {
+                self.multi_value_ret = true;
+                self.ret_expr = format!(
+                    "\
+                     const [rustptr, rustlen] = RET;\n\
+                     {guard}
+                     const realRet = {}(rustptr, rustlen).slice();\n\
+                     wasm.__wbindgen_free(rustptr, rustlen * {});\n\
+                     return realRet;\n\
+                     ",
+                    f,
+                    ty.size(),
+                    guard = guard,
+                );
+                return Ok(self);
+            }
             self.cx.expose_global_argument_ptr()?;
             self.cx.expose_uint32_memory();
-            self.cx.require_internal_export("__wbindgen_free")?;
             self.prelude("const retptr = globalArgumentPtr();");
             self.rust_arguments.insert(0, "retptr".to_string());
             self.ret_expr = format!(
@@ -465,7 +600,7 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
                  ",
                 f,
                 ty.size(),
-                guard = if optional { "if (rustptr === 0) return;" } else { "" },
+                guard = guard,
             );
             return Ok(self);
         }
@@ -474,6 +609,11 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
         // that `takeObject` will naturally pluck out `undefined`.
         if ty.is_anyref() {
             self.ret_ty = "any".to_string();
+            if self.passes_anyref() {
+                self.anyref_ret = true;
+                self.ret_expr = format!("return RET;");
+                return Ok(self);
+            }
             self.cx.expose_take_object();
             self.ret_expr = format!("return takeObject(RET);");
             return Ok(self);
@@ -604,6 +744,16 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
 
         if let Some(signed) = ty.get_64() {
             self.ret_ty = "BigInt".to_string();
+            if self.passes_bigint() {
+                self.bigint_ret = true;
+                // `i64` values always arrive as signed BigInts.
+                self.ret_expr = if signed {
+                    "return RET;".to_string()
+                } else {
+                    "return BigInt.asUintN(64, RET);".to_string()
+                };
+                return Ok(self);
+            }
             self.cx.expose_global_argument_ptr()?;
             let f = if signed {
                 self.cx.expose_int64_memory();
@@ -624,6 +774,23 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
             return Ok(self);
         }
 
+        if let Some(signed) = ty.get_128() {
+            self.ret_ty = "BigInt".to_string();
+            self.cx.expose_global_argument_ptr()?;
+            self.cx.expose_uint64_memory();
+            self.prelude("const retptr = globalArgumentPtr();");
+            self.rust_arguments.insert(0, "retptr".to_string());
+            self.ret_expr = format!(
+                "\
+                 RET;\n\
+                 const mem = getUint64Memory();\n\
+                 return BigInt.as{}N(128, mem[retptr / 8] | (mem[retptr / 8 + 1] << BigInt(64)));\n\
+                 ",
+                if signed { "Int" } else { "Uint" },
+            );
+            return Ok(self);
+        }
+
         match *ty {
             Descriptor::Boolean => {
                 self.ret_ty = "boolean".to_string();
@@ -655,7 +822,27 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
     /// Returns two strings, the first of which is the JS expression for the
     /// generated function shim and the second is a TypeScript signature of the
     /// JS expression.
-    pub fn finish(&self, prefix: &str, invoc: &str) -> (String, String, String) {
+    pub fn finish(&mut self, prefix: &str, invoc: &str) -> (String, String, String) {
+        if let Some(export) = &self.wasm_export {
+            let args = self.anyref_args.iter().map(|(name, ownership)| {
+                let pos = self.rust_arguments.iter().position(|a| a == name).unwrap();
+                (pos, *ownership)
+            });
+            self.cx.anyref.export(export, anyref::Signature {
+                args: args.collect(),
+                ret: self.anyref_ret,
+            });
+            if self.multi_value_ret {
+                self.cx.multi_value.export(export);
+            }
+            let args = self.bigint_args.iter().map(|name| {
+                self.rust_arguments.iter().position(|a| a == name).unwrap()
+            });
+            self.cx.bigint.export(export, bigint::Signature {
+                args: args.collect(),
+                ret: self.bigint_ret,
+            });
+        }
         let js_args = self
             .js_arguments
             .iter()
@@ -663,7 +850,7 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
             .collect::<Vec<_>>()
             .join(", ");
         let mut js = format!("{}({}) {{\n", prefix, js_args);
-        js.push_str(&self.prelude);
+        let mut body = self.prelude.clone();
         let rust_args = self.rust_arguments.join(", ");
 
         let invoc = self
@@ -683,7 +870,11 @@ impl<'a, 'b> Js2Rust<'a, 'b> {
                 &invoc, &self.finally,
             )
         };
-        js.push_str(&invoc);
+        body.push_str(&invoc);
+        match &self.instrument {
+            Some(name) => js.push_str(&self.cx.instrument("export", name, &body)),
+            None => js.push_str(&body),
+        }
         js.push_str("\n}");
         let ts_args = self
             .js_arguments
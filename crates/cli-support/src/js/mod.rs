@@ -5,10 +5,16 @@ use std::mem;
 use failure::{Error, ResultExt};
 use parity_wasm;
 use parity_wasm::elements::*;
+use serde_json;
 use shared;
 use wasm_gc;
 
-use super::Bindgen;
+use super::{Bindgen, OutputMode};
+use anyref;
+use bigint;
+use multi_value;
+use source_map;
+use threads;
 use descriptor::{Descriptor, VectorKind};
 use wasm_interpreter::Interpreter;
 
@@ -28,6 +34,8 @@ pub struct Context<'a> {
     pub imported_functions: HashSet<String>,
     pub imported_statics: HashSet<String>,
     pub config: &'a Bindgen,
+    /// The kind of JS to generate, worked out from `config`'s target flags.
+    pub mode: OutputMode,
     pub module: &'a mut Module,
 
     /// A map which maintains a list of what identifiers we've imported and what
@@ -47,6 +55,22 @@ pub struct Context<'a> {
     pub function_table_needed: bool,
     pub interpreter: &'a mut Interpreter,
     pub memory_init: Option<ResizableLimits>,
+
+    /// Rust source locations referenced by the markers emitted from
+    /// `source_begin`, used to generate a source map if one was requested.
+    pub source_locations: Vec<shared::Location>,
+
+    /// Imports and exports passing JS values, which with `--reference-types`
+    /// are rewritten to pass them as `externref` values.
+    pub anyref: anyref::Transform,
+
+    /// Exports returning strings and vectors, which with `--multi-value` are
+    /// rewritten to return them as a `(ptr, len)` pair.
+    pub multi_value: multi_value::Transform,
+
+    /// Imports and exports passing 64-bit integers, which with `--bigint` are
+    /// rewritten to pass them as `i64` values.
+    pub bigint: bigint::Transform,
 }
 
 #[derive(Default)]
@@ -72,6 +96,22 @@ pub struct SubContext<'a, 'b: 'a> {
 
 const INITIAL_SLAB_VALUES: &[&str] = &["undefined", "null", "true", "false"];
 
+/// Intrinsics implementing the operators on `&JsValue`, and the JS operator
+/// each of them applies.
+const OPERATORS: &[(&str, &str)] = &[
+    ("__wbindgen_add", "+"),
+    ("__wbindgen_sub", "-"),
+    ("__wbindgen_mul", "*"),
+    ("__wbindgen_div", "/"),
+    ("__wbindgen_rem", "%"),
+    ("__wbindgen_pow", "**"),
+    ("__wbindgen_bit_and", "&"),
+    ("__wbindgen_bit_or", "|"),
+    ("__wbindgen_bit_xor", "^"),
+    ("__wbindgen_shl", "<<"),
+    ("__wbindgen_shr", ">>"),
+];
+
 impl<'a> Context<'a> {
     fn export(&mut self, name: &str, contents: &str, comments: Option<String>) {
         let contents = contents.trim();
@@ -84,12 +124,30 @@ impl<'a> Context<'a> {
             } else {
                 format!("module.exports.{} = {};\n", name, contents)
             }
-        } else if self.config.no_modules {
+        } else if self.mode.no_modules() {
             if contents.starts_with("class") {
                 format!("{1}\n__exports.{0} = {0};\n", name, contents)
             } else {
                 format!("__exports.{} = {};\n", name, contents)
             }
+        } else if self.mode.web() {
+            // Native ES modules can't be handed to `WebAssembly.instantiate`
+            // as an import object, so in addition to exporting everything we
+            // also record it on `__exports` which is what the wasm imports.
+            if contents.starts_with("function") {
+                format!(
+                    "export function {0}{1}\n__exports.{0} = {0};\n",
+                    name,
+                    &contents[8..]
+                )
+            } else if contents.starts_with("class") {
+                format!("export {1}\n__exports.{0} = {0};\n", name, contents)
+            } else {
+                format!(
+                    "export const {0} = {1};\n__exports.{0} = {0};\n",
+                    name, contents
+                )
+            }
         } else {
             if contents.starts_with("function") {
                 format!("export function {}{}\n", name, &contents[8..])
@@ -122,47 +180,23 @@ impl<'a> Context<'a> {
 
     pub fn finalize(&mut self, module_name: &str) -> Result<(String, String), Error> {
         self.write_classes()?;
-
-        self.bind("__wbindgen_object_clone_ref", &|me| {
-            me.expose_add_heap_object();
-            me.expose_get_object();
-            let bump_cnt = if me.config.debug {
-                String::from(
-                    "
-                    if (typeof(val) === 'number') throw new Error('corrupt slab');
-                    val.cnt += 1;
-                    ",
-                )
-            } else {
-                String::from("val.cnt += 1;")
-            };
-            Ok(format!(
-                "
-                function(idx) {{
-                    // If this object is on the stack promote it to the heap.
-                    if ((idx & 1) === 1) return addHeapObject(getObject(idx));
-
-                    // Otherwise if the object is on the heap just bump the
-                    // refcount and move on
-                    const val = slab[idx >> 1];
-                    {}
-                    return idx;
-                }}
-                ",
-                bump_cnt
-            ))
-        })?;
-
-        self.bind("__wbindgen_object_drop_ref", &|me| {
-            me.expose_drop_ref();
-            Ok(String::from(
-                "
-                function(i) {
-                    dropRef(i);
-                }
-                ",
-            ))
-        })?;
+        if self.config.threads {
+            self.expose_threads()?;
+        }
+        if self.config.profile_boundary {
+            self.expose_profile_report();
+        }
+        if self.config.debug {
+            self.expose_live_objects_report();
+        }
+        if self.config.reference_types {
+            self.require_internal_export("__wbindgen_anyref_table_alloc")?;
+            self.require_internal_export("__wbindgen_anyref_table_dealloc")?;
+            self.expose_init_anyref_table();
+        } else {
+            // With `--reference-types` these are implemented in wasm instead.
+            self.bind_object_ref_intrinsics()?;
+        }
 
         self.bind("__wbindgen_string_new", &|me| {
             me.expose_add_heap_object();
@@ -303,6 +337,89 @@ impl<'a> Context<'a> {
             ))
         })?;
 
+        self.bind("__wbindgen_is_bigint", &|me| {
+            me.expose_get_object();
+            Ok(String::from(
+                "
+                function(i) {
+                    return typeof(getObject(i)) === 'bigint' ? 1 : 0;
+                }
+                ",
+            ))
+        })?;
+
+        self.bind("__wbindgen_bigint_new", &|me| {
+            me.expose_add_heap_object();
+            let words = me.expose_u32x4_cvt_shim();
+            let f = me.expose_uint128_cvt_shim();
+            Ok(format!(
+                "
+                function(w0, w1, w2, w3, signed) {{
+                    {words}[0] = w0;
+                    {words}[1] = w1;
+                    {words}[2] = w2;
+                    {words}[3] = w3;
+                    const n = {f}[0] | ({f}[1] << BigInt(64));
+                    return addHeapObject(signed ? BigInt.asIntN(128, n) : n);
+                }}
+                ",
+                words = words,
+                f = f,
+            ))
+        })?;
+
+        self.bind("__wbindgen_bigint_get", &|me| {
+            me.expose_get_object();
+            me.expose_uint64_memory();
+            Ok(String::from(
+                "
+                function(i, signed, ret) {
+                    const n = getObject(i);
+                    if (typeof(n) !== 'bigint') return 0;
+                    if ((signed ? BigInt.asIntN(128, n) : BigInt.asUintN(128, n)) !== n) return 0;
+                    const mem = getUint64Memory();
+                    mem[ret / 8] = n;
+                    mem[ret / 8 + 1] = n >> BigInt(64);
+                    return 1;
+                }
+                ",
+            ))
+        })?;
+
+        self.bind("__wbindgen_string_new_utf16", &|me| {
+            me.expose_add_heap_object();
+            me.expose_get_utf16_string_from_wasm();
+            Ok(String::from(
+                "
+                function(p, l) {
+                    return addHeapObject(getUtf16StringFromWasm(p, l));
+                }
+                ",
+            ))
+        })?;
+
+        self.bind("__wbindgen_string_get_utf16", &|me| {
+            me.require_internal_export("__wbindgen_malloc")?;
+            me.expose_get_object();
+            me.expose_uint16_memory();
+            me.expose_uint32_memory();
+            Ok(String::from(
+                "
+                function(i, len_ptr) {
+                    let obj = getObject(i);
+                    if (typeof(obj) !== 'string') return 0;
+                    const ptr = wasm.__wbindgen_malloc(obj.length * 2);
+                    const mem = getUint16Memory();
+                    for (let j = 0; j < obj.length; j++) {
+                        mem[ptr / 2 + j] = obj.charCodeAt(j);
+                    }
+                    getUint32Memory()[len_ptr / 4] = obj.length;
+                    return ptr;
+                }
+                ",
+            ))
+        })?;
+
         self.bind("__wbindgen_string_get", &|me| {
             me.expose_pass_string_to_wasm()?;
             me.expose_get_object();
@@ -382,6 +499,49 @@ impl<'a> Context<'a> {
             ))
         })?;
 
+        // Operators can throw, so like imports with `catch` the exception is
+        // written through the last argument for `JsValue::try_add` and friends.
+        for (name, op) in OPERATORS.iter() {
+            self.bind(name, &|me| {
+                me.expose_get_object();
+                me.expose_add_heap_object();
+                me.expose_uint32_memory();
+                Ok(format!(
+                    "
+                    function(a, b, exnptr) {{
+                        try {{
+                            return addHeapObject(getObject(a) {} getObject(b));
+                        }} catch (e) {{
+                            const view = getUint32Memory();
+                            view[exnptr / 4] = 1;
+                            view[exnptr / 4 + 1] = addHeapObject(e);
+                        }}
+                    }}
+                    ",
+                    op
+                ))
+            })?;
+        }
+
+        self.bind("__wbindgen_neg", &|me| {
+            me.expose_get_object();
+            me.expose_add_heap_object();
+            me.expose_uint32_memory();
+            Ok(String::from(
+                "
+                function(a, exnptr) {
+                    try {
+                        return addHeapObject(-getObject(a));
+                    } catch (e) {
+                        const view = getUint32Memory();
+                        view[exnptr / 4] = 1;
+                        view[exnptr / 4 + 1] = addHeapObject(e);
+                    }
+                }
+                ",
+            ))
+        })?;
+
         self.bind("__wbindgen_memory", &|me| {
             me.expose_add_heap_object();
             let mem = me.memory();
@@ -399,9 +559,49 @@ impl<'a> Context<'a> {
             Ok(String::from("function(idx) { throw takeObject(idx); }"))
         })?;
 
+        self.bind("__wbindgen_current_id", &|me| {
+            Ok(String::from(if me.config.threads {
+                "function() { return wasmThreadId; }"
+            } else {
+                "function() { return 0; }"
+            }))
+        })?;
+
+        self.bind("__wbindgen_tcb_get", &|me| {
+            me.expose_tcb();
+            Ok(String::from("function() { return wasmTcb; }"))
+        })?;
+
+        self.bind("__wbindgen_tcb_set", &|me| {
+            me.expose_tcb();
+            Ok(String::from(if me.config.threads {
+                "
+                function(ptr) {
+                    wasmTcb = ptr;
+                    if (wasmThreadId !== 0) {
+                        Atomics.store(new Int32Array(wasm.memory.buffer), wasmThreadId * 16384, ptr);
+                    }
+                }
+                "
+            } else {
+                "function(ptr) { wasmTcb = ptr; }"
+            }))
+        })?;
+
         self.create_memory_export();
         self.unexport_unused_internal_exports();
         closures::rewrite(self)?;
+        if self.config.multi_value {
+            self.multi_value.prepare(self.module)?;
+        }
+        // This must come after `multi_value`, which removes return pointers
+        // the JS glue doesn't pass from the exports it wraps.
+        if self.config.bigint {
+            self.bigint.prepare(self.module)?;
+        }
+        if self.config.reference_types {
+            self.anyref.prepare(self.module)?;
+        }
         self.gc()?;
 
         // Note that it's important `throw` comes last *after* we gc. The
@@ -419,41 +619,71 @@ impl<'a> Context<'a> {
             ))
         })?;
 
+        // Like `throw` above this must come after we gc, both so it's only
+        // generated if a panic hook is actually installed and because in debug
+        // mode we embed a table of function names indexed by the final
+        // function indices of the module.
+        self.bind("__wbindgen_panic", &|me| {
+            me.expose_get_string_from_wasm();
+            let rust_stack = if me.config.debug {
+                me.expose_get_rust_stack();
+                "message += getRustStack();"
+            } else {
+                ""
+            };
+            Ok(format!(
+                "
+                function(msg, msg_len, file, file_len, line, column) {{
+                    let message = `panicked at '${{getStringFromWasm(msg, msg_len)}}', \
+                                   ${{getStringFromWasm(file, file_len)}}:${{line}}:${{column}}`;
+                    {}
+                    throw new Error(message);
+                }}
+                ",
+                rust_stack,
+            ))
+        })?;
+
         self.rewrite_imports(module_name);
 
-        let mut js = if self.config.no_modules {
+        if self.mode.web() || self.mode.no_modules() {
+            self.typescript.push_str(&self.ts_for_init());
+        }
+
+        let mut js = if let OutputMode::NoModules { global } = &self.mode {
             format!(
-                    "\
-                    (function() {{
-                        var wasm;
-                        const __exports = {{}};
-                        {globals}
-                        function init(wasm_path) {{
-                            const fetchPromise = fetch(wasm_path);
-                            let resultPromise;
-                            if (typeof WebAssembly.instantiateStreaming === 'function') {{
-                                resultPromise = WebAssembly.instantiateStreaming(fetchPromise, {{ './{module}': __exports }});
-                            }} else {{
-                                resultPromise = fetchPromise
-                                    .then(response => response.arrayBuffer())
-                                    .then(buffer => WebAssembly.instantiate(buffer, {{ './{module}': __exports }}));
-                            }}
-                            return resultPromise.then(({{instance}}) => {{
-                                wasm = init.wasm = instance.exports;
-                                return;
-                            }});
-                        }};
-                        self.{global_name} = Object.assign(init, __exports);
-                    }})();",
-                    globals = self.globals,
-                    module = module_name,
-                    global_name = self.config.no_modules_global
-                        .as_ref()
-                        .map(|s| &**s)
-                        .unwrap_or("wasm_bindgen"),
+                "\
+                (function() {{
+                    var wasm;
+                    const __exports = {{}};
+                    {globals}
+                    {init}
+                    self.{global_name} = Object.assign(init, __exports);
+                }})();",
+                globals = self.globals,
+                init = self.gen_init(module_name),
+                global_name = global,
+            )
+        } else if self.mode.web() {
+            format!(
+                "\
+                /* tslint:disable */\n\
+                {imports}\n\
+                let wasm;
+                const __exports = {{}};
+                {globals}\n\
+                {init}
+                export default init;
+                ",
+                imports = self.imports,
+                globals = self.globals,
+                init = self.gen_init(module_name),
             )
         } else {
-            let import_wasm = if self.globals.len() == 0 {
+            let import_wasm = if self.config.threads {
+                self.footer.push_str(&self.gen_node_thread_start(module_name));
+                format!("var wasm;")
+            } else if self.globals.len() == 0 {
                 String::new()
             } else if self.use_node_require() {
                 self.footer
@@ -462,6 +692,9 @@ impl<'a> Context<'a> {
             } else {
                 format!("import * as wasm from './{}_bg';", module_name)
             };
+            if self.config.reference_types {
+                self.footer.push_str("\ninitAnyrefTable();\n");
+            }
 
             format!(
                 "\
@@ -488,6 +721,217 @@ impl<'a> Context<'a> {
         Ok((js, self.typescript.clone()))
     }
 
+    /// Binds the intrinsics which manage references to objects in the slab.
+    fn bind_object_ref_intrinsics(&mut self) -> Result<(), Error> {
+        self.bind("__wbindgen_object_clone_ref", &|me| {
+            me.expose_add_heap_object();
+            me.expose_get_object();
+            let bump_cnt = if me.config.debug {
+                String::from(
+                    "
+                    if (typeof(val) === 'number') throw new Error('corrupt slab');
+                    val.cnt += 1;
+                    ",
+                )
+            } else {
+                String::from("val.cnt += 1;")
+            };
+            Ok(format!(
+                "
+                function(idx) {{
+                    // If this object is on the stack promote it to the heap.
+                    if ((idx & 1) === 1) return addHeapObject(getObject(idx));
+
+                    // Otherwise if the object is on the heap just bump the
+                    // refcount and move on
+                    const val = slab[idx >> 1];
+                    {}
+                    return idx;
+                }}
+                ",
+                bump_cnt
+            ))
+        })?;
+
+        self.bind("__wbindgen_object_drop_ref", &|me| {
+            me.expose_drop_ref();
+            Ok(String::from(
+                "
+                function(i) {
+                    dropRef(i);
+                }
+                ",
+            ))
+        })?;
+        Ok(())
+    }
+
+    /// The TypeScript declaration of the function generated by `gen_init`.
+    fn ts_for_init(&self) -> String {
+        let thread_arg = if self.config.threads { ", thread?: any" } else { "" };
+        format!(
+            "\n\
+             export type InitInput = RequestInfo | URL | BufferSource | WebAssembly.Module;\n\
+             \n\
+             export default function init \
+             (module_or_path?: InitInput{}): Promise<any>;\n",
+            thread_arg,
+        )
+    }
+
+    /// Generates the `init` function used by `--target web` and
+    /// `--target no-modules` to fetch, compile and instantiate the wasm
+    /// module, since in those modes there's no bundler or node module loader
+    /// to do it for us.
+    ///
+    /// The argument can either be a URL (string, `URL` or `Request`) which is
+    /// fetched, or the raw bytes / compiled `WebAssembly.Module`. The returned
+    /// promise resolves to the wasm module's exports.
+    fn gen_init(&self, module_name: &str) -> String {
+        let default_module_path = if self.mode.web() {
+            "\
+            if (typeof module_or_path === 'undefined') {
+                module_or_path = import.meta.url.replace(/\\.js$/, '_bg.wasm');
+            }
+            "
+        } else {
+            ""
+        };
+
+        // With threads `init` is also used to start the module on other
+        // threads, in which case the second argument is the object returned
+        // from `prepareThread` on the spawning thread.
+        let init_anyref_table = if self.config.reference_types {
+            "initAnyrefTable();"
+        } else {
+            ""
+        };
+        let (thread_arg, thread_setup, thread_start) = if self.config.threads {
+            (
+                ", thread",
+                format!(
+                    "\
+                    if (typeof module_or_path === 'undefined' && thread !== undefined) {{
+                        module_or_path = thread.module;
+                    }}
+                    __exports.memory = thread === undefined ? {} : thread.memory;
+                    ",
+                    self.shared_memory_init(),
+                ),
+                "startThread(module, thread);",
+            )
+        } else {
+            ("", String::new(), "")
+        };
+
+        format!(
+            "\
+            function init(module_or_path{thread_arg}) {{
+                {thread_setup}
+                {default_module_path}
+                const imports = {{ './{module}': __exports }};
+                let result;
+                if (typeof module_or_path === 'string' ||
+                    (typeof URL === 'function' && module_or_path instanceof URL) ||
+                    (typeof Request === 'function' && module_or_path instanceof Request)) {{
+                    const response = fetch(module_or_path);
+                    if (typeof WebAssembly.instantiateStreaming === 'function') {{
+                        result = WebAssembly.instantiateStreaming(response, imports)
+                            .catch(e => {{
+                                return response
+                                    .then(r => {{
+                                        if (r.headers.get('Content-Type') != 'application/wasm') {{
+                                            console.warn(\"`WebAssembly.instantiateStreaming` failed \
+                                                          because your server does not serve wasm with \
+                                                          `application/wasm` MIME type. Falling back to \
+                                                          `WebAssembly.instantiate` which is slower. \
+                                                          Original error:\\n\", e);
+                                            return r.arrayBuffer();
+                                        }} else {{
+                                            throw e;
+                                        }}
+                                    }})
+                                    .then(bytes => WebAssembly.instantiate(bytes, imports));
+                            }});
+                    }} else {{
+                        result = response
+                            .then(r => r.arrayBuffer())
+                            .then(bytes => WebAssembly.instantiate(bytes, imports));
+                    }}
+                }} else {{
+                    result = WebAssembly.instantiate(module_or_path, imports)
+                        .then(result => {{
+                            if (result instanceof WebAssembly.Instance) {{
+                                return {{ instance: result, module: module_or_path }};
+                            }} else {{
+                                return result;
+                            }}
+                        }});
+                }}
+                return result.then(({{instance, module}}) => {{
+                    wasm = init.wasm = instance.exports;
+                    {init_anyref_table}
+                    {thread_start}
+                    return wasm;
+                }});
+            }}
+            ",
+            thread_arg = thread_arg,
+            thread_setup = thread_setup,
+            init_anyref_table = init_anyref_table,
+            thread_start = thread_start,
+            default_module_path = default_module_path,
+            module = module_name,
+        )
+    }
+
+    /// Generates the footer of the node.js glue with threads enabled, which
+    /// instantiates the wasm module directly instead of requiring the usual
+    /// `_bg.js` shim, as worker threads need to use the module and memory of
+    /// the thread that spawned them.
+    fn gen_node_thread_start(&self, module_name: &str) -> String {
+        format!(
+            "
+            let wasmThread;
+            try {{
+                const {{ workerData }} = require('worker_threads');
+                if (workerData && workerData.__wbindgen_thread === true) {{
+                    wasmThread = workerData;
+                }}
+            }} catch (e) {{}}
+
+            module.exports.memory = wasmThread === undefined
+                ? {memory}
+                : wasmThread.memory;
+            const wasmModuleToStart = wasmThread === undefined
+                ? new WebAssembly.Module(require('fs').readFileSync(require('path').join(__dirname, '{module}_bg.wasm')))
+                : wasmThread.module;
+            wasm = new WebAssembly.Instance(wasmModuleToStart, {{ './{module}': module.exports }}).exports;
+            startThread(wasmModuleToStart, wasmThread);
+            ",
+            memory = self.shared_memory_init(),
+            module = module_name,
+        )
+    }
+
+    /// Returns a JS expression creating the shared memory imported by the wasm
+    /// module when threads are enabled.
+    fn shared_memory_init(&self) -> String {
+        let limits = self.module.import_section()
+            .and_then(|s| {
+                s.entries().iter().filter_map(|i| match i.external() {
+                    External::Memory(m) => Some(*m.limits()),
+                    _ => None,
+                }).next()
+            })
+            .expect("must import memory");
+        format!(
+            "new WebAssembly.Memory({{ initial: {}, maximum: {}, shared: true }})",
+            limits.initial(),
+            limits.maximum().expect("shared memory must have a maximum"),
+        )
+    }
+
     fn bind(
         &mut self,
         name: &str,
@@ -574,17 +1018,20 @@ impl<'a> Context<'a> {
         }
 
         if wrap_needed {
+            let track = self.track_live_object("obj.ptr", name);
             dst.push_str(&format!(
                 "
                 static __wrap(ptr) {{
                     const obj = Object.create({}.prototype);
                     obj.ptr = ptr;
                     {}
+                    {}
                     return obj;
                 }}
                 ",
                 name,
                 mkweakref.replace("this", "obj"),
+                track,
             ));
         }
 
@@ -598,7 +1045,9 @@ impl<'a> Context<'a> {
 
             let set = {
                 let mut cx = Js2Rust::new(&field.name, self);
-                cx.method(true, false)
+                cx.wasm_export(&wasm_setter)
+                    .instrument(&format!("set {}.{}", name, field.name))
+                    .method(true, false)
                     .argument(&descriptor)?
                     .ret(&Descriptor::Unit)?;
                 ts_dst.push_str(&format!(
@@ -610,6 +1059,8 @@ impl<'a> Context<'a> {
                 cx.finish("", &format!("wasm.{}", wasm_setter)).0
             };
             let (get, _ts, js_doc) = Js2Rust::new(&field.name, self)
+                .wasm_export(&wasm_getter)
+                .instrument(&format!("get {}.{}", name, field.name))
                 .method(true, false)
                 .ret(&descriptor)?
                 .finish("", &format!("wasm.{}", wasm_getter));
@@ -639,15 +1090,17 @@ impl<'a> Context<'a> {
             freeref,
             shared::free_function(&name)
         ));
+        let untrack = self.untrack_live_object("ptr");
         dst.push_str(&format!(
             "
             free() {{
                 const ptr = this.ptr;
                 this.ptr = 0;
+                {}
                 free{}(ptr);
             }}
             ",
-            name,
+            untrack, name,
         ));
         ts_dst.push_str("free(): void;\n");
         dst.push_str(&class.contents);
@@ -777,6 +1230,14 @@ impl<'a> Context<'a> {
         if !self.exposed_globals.insert("drop_ref") {
             return;
         }
+        if self.config.reference_types {
+            self.global(
+                "
+                function dropRef(idx) { wasm.__wbindgen_anyref_table_dealloc(idx); }
+                ",
+            );
+            return;
+        }
         self.expose_global_slab();
         self.expose_global_slab_next();
         let validate_owned = if self.config.debug {
@@ -887,6 +1348,15 @@ impl<'a> Context<'a> {
         if !self.exposed_globals.insert("get_object") {
             return;
         }
+        if self.config.reference_types {
+            self.global(&format!(
+                "
+                function getObject(idx) {{ return wasm.{}.get(idx); }}
+                ",
+                anyref::TABLE,
+            ));
+            return;
+        }
         self.expose_global_stack();
         self.expose_global_slab();
 
@@ -952,7 +1422,8 @@ impl<'a> Context<'a> {
             return Ok(());
         }
         self.require_internal_export("__wbindgen_malloc")?;
-        self.expose_text_encoder();
+        self.require_internal_export("__wbindgen_realloc")?;
+        self.expose_encode_string();
         self.expose_uint8_memory();
         let debug = if self.config.debug {
             "
@@ -961,14 +1432,37 @@ impl<'a> Context<'a> {
         } else {
             ""
         };
+
+        // Strings are optimistically assumed to be ASCII, which is copied into
+        // memory one character at a time without calling into the
+        // `TextEncoder`. Once a non-ASCII character is found the allocation is
+        // grown to the worst case of three bytes per UTF-16 code unit for the
+        // rest of the string, which is encoded directly into memory and then
+        // shrunk to fit. This way Rust frees strings with the same size as it
+        // was given.
         self.global(&format!(
             "
             function passStringToWasm(arg) {{
                 {}
-                const buf = cachedEncoder.encode(arg);
-                const ptr = wasm.__wbindgen_malloc(buf.length);
-                getUint8Memory().set(buf, ptr);
-                return [ptr, buf.length];
+                let len = arg.length;
+                let ptr = wasm.__wbindgen_malloc(len);
+                const mem = getUint8Memory();
+                let offset = 0;
+                for (; offset < len; offset++) {{
+                    const code = arg.charCodeAt(offset);
+                    if (code > 0x7F) break;
+                    mem[ptr + offset] = code;
+                }}
+                if (offset !== len) {{
+                    if (offset !== 0) {{
+                        arg = arg.slice(offset);
+                    }}
+                    ptr = wasm.__wbindgen_realloc(ptr, len, len = offset + arg.length * 3);
+                    const view = getUint8Memory().subarray(ptr + offset, ptr + len);
+                    offset += encodeString(arg, view);
+                    ptr = wasm.__wbindgen_realloc(ptr, len, offset);
+                }}
+                return [ptr, offset];
             }}
             ",
             debug
@@ -976,6 +1470,43 @@ impl<'a> Context<'a> {
         Ok(())
     }
 
+    /// Exposes `encodeString(arg, view)`, which writes the UTF-8 encoding of
+    /// `arg` into `view` and returns the number of bytes written.
+    fn expose_encode_string(&mut self) {
+        if !self.exposed_globals.insert("encode_string") {
+            return;
+        }
+        self.expose_text_encoder();
+
+        // `encodeInto` avoids allocating an intermediate buffer, but isn't
+        // available everywhere and rejects views of shared memory.
+        if self.memory_is_shared() {
+            self.global(
+                "
+                function encodeString(arg, view) {
+                    const buf = cachedEncoder.encode(arg);
+                    view.set(buf);
+                    return buf.length;
+                }
+                ",
+            );
+        } else {
+            self.global(
+                "
+                const encodeString = (typeof cachedEncoder.encodeInto === 'function'
+                    ? function (arg, view) {
+                        return cachedEncoder.encodeInto(arg, view).written;
+                    }
+                    : function (arg, view) {
+                        const buf = cachedEncoder.encode(arg);
+                        view.set(buf);
+                        return buf.length;
+                    });
+                ",
+            );
+        }
+    }
+
     fn expose_pass_array8_to_wasm(&mut self) -> Result<(), Error> {
         self.expose_uint8_memory();
         self.pass_array_to_wasm("passArray8ToWasm", "getUint8Memory", 1)
@@ -1052,20 +1583,413 @@ impl<'a> Context<'a> {
         Ok(())
     }
 
+    fn expose_threads(&mut self) -> Result<(), Error> {
+        if !self.exposed_globals.insert("threads") {
+            return Ok(());
+        }
+        self.require_internal_export("__wbindgen_thread_init")?;
+        self.global(&format!(
+            "
+            let wasmModule;
+            let wasmThreadId = 0;
+
+            function startThread(module, thread) {{
+                wasmModule = module;
+                if (thread === undefined) {{
+                    // Only the main thread initializes memory, from the data
+                    // segments that were moved into a custom section.
+                    const mem = new Uint8Array(wasm.memory.buffer);
+                    for (const section of WebAssembly.Module.customSections(module, '{data}')) {{
+                        const data = new Uint8Array(section);
+                        const view = new DataView(section);
+                        let i = 0;
+                        while (i < data.length) {{
+                            const offset = view.getUint32(i, true);
+                            const len = view.getUint32(i + 4, true);
+                            i += 8;
+                            mem.set(data.subarray(i, i + len), offset);
+                            i += len;
+                        }}
+                    }}
+                }} else {{
+                    // The first word of the thread's region of memory holds
+                    // its thread control block pointer, see `__wbindgen_tcb_set`,
+                    // which is inherited from the last thread to use it.
+                    wasmThreadId = thread.id;
+                    wasmTcb = Atomics.load(new Int32Array(wasm.memory.buffer), thread.id * 16384);
+                    wasm.__wbindgen_thread_init(thread.stack);
+                }}
+            }}
+
+            const wasmFreeThreads = [];
+            ",
+            data = threads::DATA_SECTION,
+        ));
+
+        // Growing the memory is atomic, so each thread's stack gets its own
+        // region of memory without any coordination between threads. The page
+        // index of that region also makes for a unique thread id. The regions
+        // of released threads are reused, but only by the thread which
+        // released them.
+        self.export(
+            "prepareThread",
+            &format!(
+                "
+                function() {{
+                    const memory = wasm.memory;
+                    const base = wasmFreeThreads.length > 0
+                        ? wasmFreeThreads.pop()
+                        : memory.grow({pages});
+                    return {{
+                        __wbindgen_thread: true,
+                        module: wasmModule,
+                        memory,
+                        id: base,
+                        stack: (base + {pages}) * 65536,
+                    }};
+                }}
+                ",
+                pages = threads::STACK_PAGES,
+            ),
+            Some(String::from(
+                "\n/**\n\
+                 * Allocates a stack for a new thread, returning the object to pass to\n\
+                 * the new thread to start this module there.\n\
+                 */\n",
+            )),
+        );
+        self.export(
+            "releaseThread",
+            "
+            function(thread) {
+                wasmFreeThreads.push(thread.id);
+            }
+            ",
+            Some(String::from(
+                "\n/**\n\
+                 * Makes the stack of a thread returned from `prepareThread` available\n\
+                 * to the next new thread, once that thread has exited.\n\
+                 */\n",
+            )),
+        );
+        self.typescript.push_str("export function prepareThread(): any;\n");
+        self.typescript.push_str("export function releaseThread(thread: any): void;\n");
+        Ok(())
+    }
+
+    /// Wraps `body`, the statements of the `kind` ("export" or "import") shim
+    /// `name`, with whatever bookkeeping `--profile-boundary` and `--debug`
+    /// ask for.
+    pub fn instrument(&mut self, kind: &str, name: &str, body: &str) -> String {
+        let mut body = body.to_string();
+        if self.config.profile_boundary {
+            body = self.profile(&format!("{}s", kind), name, &body);
+        }
+        if self.config.debug {
+            body = self.debug_site(&format!("{} {}", kind, name), &body);
+        }
+        body
+    }
+
+    /// Wraps `body` to record its calls in `profileStats[table]` under `name`.
+    fn profile(&mut self, table: &str, name: &str, body: &str) -> String {
+        self.expose_profile();
+        format!(
+            "\
+             const profileStart = profileNow();
+             try {{
+                 {}
+             }} finally {{
+                 profileRecord(profileStats.{}, {:?}, profileStart);
+             }}\
+             ",
+            body, table, name,
+        )
+    }
+
+    /// Wraps `body` so objects allocated while it runs, including by Rust
+    /// through intrinsics, are attributed to `site`.
+    fn debug_site(&mut self, site: &str, body: &str) -> String {
+        self.expose_debug_site();
+        format!(
+            "\
+             const prevSite = debugSite;
+             debugSite = {:?};
+             try {{
+                 {}
+             }} finally {{
+                 debugSite = prevSite;
+             }}\
+             ",
+            site, body,
+        )
+    }
+
+    fn expose_debug_site(&mut self) {
+        if !self.exposed_globals.insert("debug_site") {
+            return;
+        }
+        self.global(
+            "
+            let debugSite = null;
+            ",
+        );
+    }
+
+    fn expose_live_objects(&mut self) {
+        if !self.exposed_globals.insert("live_objects") {
+            return;
+        }
+        self.expose_debug_site();
+        // Instances are tracked by their pointer rather than by the JS object,
+        // so tracking them doesn't keep the JS objects alive. Instances of
+        // zero-sized types can share a pointer, hence the lists.
+        self.global(
+            "
+            const liveObjects = new Map();
+
+            function trackLiveObject(ptr, className) {
+                const entry = { class: className, site: debugSite };
+                const entries = liveObjects.get(ptr);
+                if (entries === undefined) {
+                    liveObjects.set(ptr, [entry]);
+                } else {
+                    entries.push(entry);
+                }
+            }
+
+            function untrackLiveObject(ptr) {
+                const entries = liveObjects.get(ptr);
+                if (entries === undefined) return;
+                entries.pop();
+                if (entries.length === 0) liveObjects.delete(ptr);
+            }
+            ",
+        );
+    }
+
+    /// Returns JS to record the pointer `ptr` as a live instance of the
+    /// exported class `class`, with `--debug`.
+    pub fn track_live_object(&mut self, ptr: &str, class: &str) -> String {
+        if !self.config.debug {
+            return String::new();
+        }
+        self.expose_live_objects();
+        format!("trackLiveObject({}, {:?});\n", ptr, class)
+    }
+
+    /// Returns JS to forget the exported class instance at `ptr` once it's
+    /// freed or moved into Rust, with `--debug`.
+    pub fn untrack_live_object(&mut self, ptr: &str) -> String {
+        if !self.config.debug {
+            return String::new();
+        }
+        self.expose_live_objects();
+        format!("untrackLiveObject({});\n", ptr)
+    }
+
+    /// Exports `__wbindgen_live_objects`, listing every slab entry and exported
+    /// class instance which hasn't been freed along with the shim it was
+    /// created under.
+    fn expose_live_objects_report(&mut self) {
+        self.expose_live_objects();
+        let heap = if self.config.reference_types {
+            // Slots are freed from wasm, which clears them, so every slot
+            // which isn't empty is alive. Only values added from JS have a
+            // recorded site, and only if the slot wasn't reused since.
+            self.expose_heap_sites();
+            format!(
+                "
+                const table = wasm.{};
+                for (let i = {}; i < table.length; i++) {{
+                    const value = table.get(i);
+                    if (value === null) continue;
+                    const site = heapSites.get(i);
+                    const current = site !== undefined && heapSiteValue(site) === value;
+                    heap.push({{ index: i, site: current ? site.site : null, value }});
+                }}
+                ",
+                anyref::TABLE,
+                anyref::RESERVED_SLOTS,
+            )
+        } else {
+            self.expose_global_slab();
+            format!(
+                "
+                for (let i = {}; i < slab.length; i++) {{
+                    const val = slab[i];
+                    if (typeof(val) === 'number') continue;
+                    heap.push({{ index: i << 1, site: val.site, value: val.obj }});
+                }}
+                ",
+                INITIAL_SLAB_VALUES.len(),
+            )
+        };
+        self.export(
+            "__wbindgen_live_objects",
+            &format!(
+                "
+                function() {{
+                    const heap = [];
+                    {}
+                    const classes = [];
+                    for (const [ptr, entries] of liveObjects) {{
+                        for (const {{ class: className, site }} of entries) {{
+                            classes.push({{ class: className, ptr, site }});
+                        }}
+                    }}
+                    return {{ heap, classes }};
+                }}
+                ",
+                heap,
+            ),
+            Some(String::from(
+                "\n/**\n\
+                 * Returns the JS heap handles and exported class instances which are\n\
+                 * currently alive, each with the export or import it was created under.\n\
+                 */\n",
+            )),
+        );
+        self.typescript
+            .push_str("export function __wbindgen_live_objects(): any;\n");
+    }
+
+    fn expose_profile(&mut self) {
+        if !self.exposed_globals.insert("profile") {
+            return;
+        }
+        if self.mode.nodejs_experimental_modules() {
+            self.imports
+                .push_str("import { performance } from 'perf_hooks';\n");
+        } else if self.mode.nodejs() {
+            self.global(
+                "
+                const { performance } = require('perf_hooks');
+                ",
+            );
+        } else if !self.mode.always_run_in_browser() {
+            self.global(
+                "
+                const performance = typeof self === 'object' && self.performance
+                    ? self.performance
+                    : require('perf_hooks').performance;
+                ",
+            );
+        }
+        self.global(
+            "
+            const profileStats = { exports: {}, imports: {} };
+
+            function profileNow() {
+                return performance.now();
+            }
+
+            function profileRecord(table, name, start) {
+                const elapsed = profileNow() - start;
+                const stats = table[name] || (table[name] = { calls: 0, totalMs: 0 });
+                stats.calls += 1;
+                stats.totalMs += elapsed;
+            }
+            ",
+        );
+    }
+
+    fn expose_profile_report(&mut self) {
+        self.expose_profile();
+        self.export(
+            "__wbindgen_profile_report",
+            "
+            function() {
+                const report = table => {
+                    const ret = {};
+                    for (const name in table) {
+                        const { calls, totalMs } = table[name];
+                        ret[name] = { calls, totalMs, meanMs: totalMs / calls };
+                    }
+                    return ret;
+                };
+                return {
+                    exports: report(profileStats.exports),
+                    imports: report(profileStats.imports),
+                };
+            }
+            ",
+            Some(String::from(
+                "\n/**\n\
+                 * Returns the number of calls to, and milliseconds spent in, each export\n\
+                 * and import shim since the module was loaded.\n\
+                 */\n",
+            )),
+        );
+        self.typescript
+            .push_str("export function __wbindgen_profile_report(): any;\n");
+    }
+
+    fn expose_tcb(&mut self) {
+        if !self.exposed_globals.insert("tcb") {
+            return;
+        }
+        self.global("let wasmTcb = 0;");
+    }
+
+    fn expose_get_rust_stack(&mut self) {
+        if !self.exposed_globals.insert("get_rust_stack") {
+            return;
+        }
+        self.parse_wasm_names();
+        let mut names = serde_json::Map::new();
+        if let Some(NameSection::Function(section)) = self.module.names_section() {
+            for (idx, name) in section.names().iter() {
+                names.insert(idx.to_string(), name.clone().into());
+            }
+        }
+        self.global(&format!(
+            "const wasmFunctionNames = {};",
+            serde_json::Value::Object(names),
+        ));
+
+        // Engines render wasm frames differently (and only some of them use
+        // the name section), but they all mention `wasm-function[N]`, so
+        // recover the Rust function from that index.
+        self.global(
+            "
+            function getRustStack() {
+                const stack = new Error().stack;
+                if (typeof stack !== 'string') {
+                    return '';
+                }
+                const frames = [];
+                for (const line of stack.split('\\n')) {
+                    const m = /wasm-function\\[(\\d+)\\]/.exec(line);
+                    if (m === null) {
+                        continue;
+                    }
+                    const name = wasmFunctionNames[m[1]];
+                    frames.push(`    at ${name === undefined ? m[0] : name}`);
+                }
+                if (frames.length === 0) {
+                    return '';
+                }
+                return `\\n\\nRust stack:\\n${frames.join('\\n')}`;
+            }
+            ",
+        );
+    }
+
     fn expose_text_encoder(&mut self) {
         if !self.exposed_globals.insert("text_encoder") {
             return;
         }
-        if self.config.nodejs_experimental_modules {
+        if self.mode.nodejs_experimental_modules() {
             self.imports
                 .push_str("import { TextEncoder } from 'util';\n");
-        } else if self.config.nodejs {
+        } else if self.mode.nodejs() {
             self.global(
                 "
                 const TextEncoder = require('util').TextEncoder;
                 ",
             );
-        } else if !(self.config.browser || self.config.no_modules) {
+        } else if !self.mode.always_run_in_browser() {
             self.global(
                 "
                 const TextEncoder = typeof self === 'object' && self.TextEncoder
@@ -1085,16 +2009,16 @@ impl<'a> Context<'a> {
         if !self.exposed_globals.insert("text_decoder") {
             return;
         }
-        if self.config.nodejs_experimental_modules {
+        if self.mode.nodejs_experimental_modules() {
             self.imports
                 .push_str("import { TextDecoder } from 'util';\n");
-        } else if self.config.nodejs {
+        } else if self.mode.nodejs() {
             self.global(
                 "
                 const TextDecoder = require('util').TextDecoder;
                 ",
             );
-        } else if !(self.config.browser || self.config.no_modules) {
+        } else if !self.mode.always_run_in_browser() {
             self.global(
                 "
                 const TextDecoder = typeof self === 'object' && self.TextDecoder
@@ -1125,15 +2049,7 @@ impl<'a> Context<'a> {
         // creates just a view. That way in shared mode we copy more data but in
         // non-shared mode there's no need to copy the data except for the
         // string itself.
-        self.memory(); // set self.memory_init
-        let is_shared = self.module
-            .memory_section()
-            .map(|s| s.entries()[0].limits().shared())
-            .unwrap_or(match &self.memory_init {
-                Some(limits) => limits.shared(),
-                None => false,
-            });
-        let method = if is_shared { "slice" } else { "subarray" };
+        let method = if self.memory_is_shared() { "slice" } else { "subarray" };
 
         self.global(&format!("
             function getStringFromWasm(ptr, len) {{
@@ -1142,6 +2058,40 @@ impl<'a> Context<'a> {
         ", method));
     }
 
+    fn expose_get_utf16_string_from_wasm(&mut self) {
+        if !self.exposed_globals.insert("get_utf16_string_from_wasm") {
+            return;
+        }
+        self.expose_uint16_memory();
+
+        // `String.fromCharCode` is called on chunks of the code units to stay
+        // well within engines' limits on the number of arguments.
+        self.global(
+            "
+            function getUtf16StringFromWasm(ptr, len) {
+                const mem = getUint16Memory();
+                const end = ptr / 2 + len;
+                let ret = '';
+                for (let i = ptr / 2; i < end; i += 4096) {
+                    ret += String.fromCharCode.apply(null, mem.subarray(i, Math.min(i + 4096, end)));
+                }
+                return ret;
+            }
+            ",
+        );
+    }
+
+    fn memory_is_shared(&mut self) -> bool {
+        self.memory(); // set self.memory_init
+        self.config.threads || self.module
+            .memory_section()
+            .map(|s| s.entries()[0].limits().shared())
+            .unwrap_or(match &self.memory_init {
+                Some(limits) => limits.shared(),
+                None => false,
+            })
+    }
+
     fn expose_get_array_js_value_from_wasm(&mut self) {
         if !self.exposed_globals.insert("get_array_js_value_from_wasm") {
             return;
@@ -1377,6 +2327,26 @@ impl<'a> Context<'a> {
             return;
         }
         self.expose_global_stack();
+        if self.config.reference_types {
+            // Borrowed objects get a slot in the table like any other, which
+            // is freed again by `dropBorrowedObject` once the call is done.
+            self.expose_add_heap_object();
+            self.expose_drop_ref();
+            self.global(
+                "
+                function addBorrowedObject(obj) {
+                    const idx = addHeapObject(obj);
+                    stack.push(idx);
+                    return idx;
+                }
+
+                function dropBorrowedObject() {
+                    dropRef(stack.pop());
+                }
+                ",
+            );
+            return;
+        }
         self.global(
             "
             function addBorrowedObject(obj) {
@@ -1387,6 +2357,31 @@ impl<'a> Context<'a> {
         );
     }
 
+    /// Fills in the reserved slots of the table of JS values with the values
+    /// of `JsValue::UNDEFINED` and friends, which is done right after the
+    /// module is instantiated.
+    fn expose_init_anyref_table(&mut self) {
+        if !self.exposed_globals.insert("init_anyref_table") {
+            return;
+        }
+        let init = INITIAL_SLAB_VALUES
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("table.set({}, {});", i << 1, value))
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.global(&format!(
+            "
+            function initAnyrefTable() {{
+                const table = wasm.{};
+                {}
+            }}
+            ",
+            anyref::TABLE,
+            init,
+        ));
+    }
+
     fn expose_take_object(&mut self) {
         if !self.exposed_globals.insert("take_object") {
             return;
@@ -1408,6 +2403,27 @@ impl<'a> Context<'a> {
         if !self.exposed_globals.insert("add_heap_object") {
             return;
         }
+        if self.config.reference_types {
+            let record = if self.config.debug {
+                self.expose_heap_sites();
+                "recordHeapSite(idx, obj);"
+            } else {
+                ""
+            };
+            self.global(&format!(
+                "
+                function addHeapObject(obj) {{
+                    const idx = wasm.__wbindgen_anyref_table_alloc();
+                    wasm.{}.set(idx, obj);
+                    {}
+                    return idx;
+                }}
+                ",
+                anyref::TABLE,
+                record,
+            ));
+            return;
+        }
         self.expose_global_slab();
         self.expose_global_slab_next();
         let set_slab_next = if self.config.debug {
@@ -1424,6 +2440,12 @@ impl<'a> Context<'a> {
                 ",
             )
         };
+        let entry = if self.config.debug {
+            self.expose_debug_site();
+            "{ obj, cnt: 1, site: debugSite }"
+        } else {
+            "{ obj, cnt: 1 }"
+        };
         self.global(&format!(
             "
             function addHeapObject(obj) {{
@@ -1431,14 +2453,39 @@ impl<'a> Context<'a> {
                 const idx = slab_next;
                 const next = slab[idx];
                 {}
-                slab[idx] = {{ obj, cnt: 1 }};
+                slab[idx] = {};
                 return idx << 1;
             }}
             ",
-            set_slab_next
+            set_slab_next, entry
         ));
     }
 
+    /// With `--reference-types` and `--debug`, remembers the site values were
+    /// added to the table from JS under. Objects are only held weakly, which
+    /// is enough to tell whether a slot still holds the same value.
+    fn expose_heap_sites(&mut self) {
+        if !self.exposed_globals.insert("heap_sites") {
+            return;
+        }
+        self.expose_debug_site();
+        self.global(
+            "
+            const heapSites = new Map();
+
+            function recordHeapSite(idx, obj) {
+                const weak = (typeof obj === 'object' && obj !== null) || typeof obj === 'function';
+                const value = weak ? new WeakRef(obj) : obj;
+                heapSites.set(idx, { site: debugSite, weak, value });
+            }
+
+            function heapSiteValue(entry) {
+                return entry.weak ? entry.value.deref() : entry.value;
+            }
+            ",
+        );
+    }
+
     fn wasm_import_needed(&self, name: &str) -> bool {
         let imports = match self.module.import_section() {
             Some(s) => s,
@@ -1644,6 +2691,30 @@ impl<'a> Context<'a> {
         name
     }
 
+    fn expose_u32x4_cvt_shim(&mut self) -> &'static str {
+        let name = "u32x4CvtShim";
+        if !self.exposed_globals.insert(name) {
+            return name;
+        }
+        self.global(&format!("const {} = new Uint32Array(4);", name));
+        name
+    }
+
+    /// Exposes a pair of `u64` halves aliasing `u32x4CvtShim`, used to convert
+    /// between 128-bit integers and their four `u32` words.
+    fn expose_uint128_cvt_shim(&mut self) -> &'static str {
+        let name = "uint128CvtShim";
+        if !self.exposed_globals.insert(name) {
+            return name;
+        }
+        let n = self.expose_u32x4_cvt_shim();
+        self.global(&format!(
+            "const {} = new BigUint64Array({}.buffer);",
+            name, n
+        ));
+        name
+    }
+
     fn expose_is_like_none(&mut self) {
         if !self.exposed_globals.insert("is_like_none") {
             return
@@ -1655,21 +2726,76 @@ impl<'a> Context<'a> {
         ");
     }
 
+    /// Used in debug mode to notice when memory grew and detached a view of a
+    /// slice while it was passed to JS. Indexing the view can't be watched
+    /// without replacing it, so any view found detached once the import
+    /// returns is reported. Its methods throw right away, as most of them
+    /// would anyway.
+    fn expose_watch_view(&mut self) {
+        if !self.exposed_globals.insert("watch_view") {
+            return;
+        }
+        self.global("
+            const typedArrayPrototype = Object.getPrototypeOf(Uint8Array.prototype);
+            const typedArrayByteLength =
+                Object.getOwnPropertyDescriptor(typedArrayPrototype, 'byteLength').get;
+
+            function detachedViewError() {
+                return new Error('wasm memory grew while a slice was passed to JS as a view of it, ' +
+                    'which detached the view: any reads through it since returned nothing and writes to ' +
+                    'it were lost. Use `#[wasm_bindgen(slices = copy)]` or ' +
+                    '`#[wasm_bindgen(slices = copy_in_out)]` on the import to pass a copy instead');
+            }
+
+            function watchView(view, len) {
+                // Empty views have no memory to detach.
+                const watched = view !== undefined && len !== 0;
+                const watch = {
+                    detached: () => watched && typedArrayByteLength.call(view) === 0,
+                };
+                if (!watched) {
+                    return watch;
+                }
+                for (const key of Reflect.ownKeys(typedArrayPrototype)) {
+                    const desc = Object.getOwnPropertyDescriptor(typedArrayPrototype, key);
+                    if (typeof desc.value === 'function' && key !== 'constructor') {
+                        Object.defineProperty(view, key, {
+                            configurable: true,
+                            writable: true,
+                            value: function(...args) {
+                                if (watch.detached()) {
+                                    throw detachedViewError();
+                                }
+                                return desc.value.apply(this, args);
+                            },
+                        });
+                    }
+                }
+                return watch;
+            }
+        ");
+    }
+
     fn expose_cleanup_groups(&mut self) {
         if !self.exposed_globals.insert("cleanup_groups") {
             return
         }
-        self.global(
+        let untrack = self.untrack_live_object("ptr");
+        self.global(&format!(
             "
                 const CLEANUPS = new WeakRefGroup(x => x.holdings());
                 const CLEANUPS_MAP = new Map();
 
-                function addCleanup(obj, ptr, free) {
-                    const ref = CLEANUPS.makeRef(obj, () => free(ptr));
+                function addCleanup(obj, ptr, free) {{
+                    const ref = CLEANUPS.makeRef(obj, () => {{
+                        {}
+                        free(ptr);
+                    }});
                     CLEANUPS_MAP.set(ptr, ref);
-                }
-            "
-        );
+                }}
+            ",
+            untrack,
+        ));
     }
 
     fn gc(&mut self) -> Result<(), Error> {
@@ -1710,12 +2836,41 @@ impl<'a> Context<'a> {
         self.globals.push_str("\n");
     }
 
+    /// Returns a marker to be placed before JS generated on behalf of the Rust
+    /// function `f`, so it can later be mapped back to its source location.
+    ///
+    /// This is empty if source maps are disabled or the location is unknown,
+    /// in which case `source_end` need not be called either.
+    fn source_begin(&mut self, f: &shared::Function) -> String {
+        let location = match &f.location {
+            Some(location) if self.config.source_map => location,
+            _ => return String::new(),
+        };
+        self.source_locations.push(location.clone());
+        format!(
+            "{}{} */\n",
+            source_map::BEGIN_MARKER,
+            self.source_locations.len() - 1
+        )
+    }
+
+    /// Returns a marker ending the JS started by the last `source_begin`.
+    fn source_end(&self, f: &shared::Function) -> String {
+        if self.config.source_map && f.location.is_some() {
+            format!("{}\n", source_map::END_MARKER)
+        } else {
+            String::new()
+        }
+    }
+
     fn use_node_require(&self) -> bool {
-        self.config.nodejs && !self.config.nodejs_experimental_modules
+        self.mode.nodejs() && !self.mode.nodejs_experimental_modules()
     }
 
     fn memory(&mut self) -> &'static str {
-        if self.module.memory_section().is_some() {
+        // With threads the memory is imported, but `threads::run` also makes
+        // sure it's exported for the glue to use.
+        if self.module.memory_section().is_some() || self.config.threads {
             return "wasm.memory";
         }
 
@@ -1789,13 +2944,18 @@ impl<'a, 'b> SubContext<'a, 'b> {
         };
 
         let (js, ts, js_doc) = Js2Rust::new(&export.function.name, self.cx)
+            .wasm_export(&export.function.name)
+            .instrument(&export.function.name)
             .process(descriptor.unwrap_function())?
             .finish("function", &format!("wasm.{}", export.function.name));
+        let source_begin = self.cx.source_begin(&export.function);
         self.cx.export(
             &export.function.name,
             &js,
-            Some(format_doc_comments(&export.comments, Some(js_doc))),
+            Some(format_doc_comments(&export.comments, Some(js_doc)) + &source_begin),
         );
+        let source_end = self.cx.source_end(&export.function);
+        self.cx.globals.push_str(&source_end);
         self.cx.globals.push_str("\n");
         self.cx.typescript.push_str("export ");
         self.cx.typescript.push_str(&ts);
@@ -1821,10 +2981,14 @@ impl<'a, 'b> SubContext<'a, 'b> {
             &export.function.name
         };
         let (js, ts, js_doc) = Js2Rust::new(function_name, self.cx)
+            .wasm_export(&wasm_name)
+            .instrument(&format!("{}.{}", class_name, function_name))
             .method(export.method, export.consumed)
             .constructor(if export.is_constructor { Some(class_name) } else { None })
             .process(descriptor.unwrap_function())?
             .finish("", &format!("wasm.{}", wasm_name));
+        let source_begin = self.cx.source_begin(&export.function);
+        let source_end = self.cx.source_end(&export.function);
 
         let class = self
             .cx
@@ -1834,6 +2998,7 @@ impl<'a, 'b> SubContext<'a, 'b> {
         class
             .contents
             .push_str(&format_doc_comments(&export.comments, Some(js_doc)));
+        class.contents.push_str(&source_begin);
 
         if export.is_constructor {
             if class.has_constructor {
@@ -1849,6 +3014,7 @@ impl<'a, 'b> SubContext<'a, 'b> {
         class.contents.push_str(function_name);
         class.contents.push_str(&js);
         class.contents.push_str("\n");
+        class.contents.push_str(&source_end);
         class.typescript.push_str(&ts);
         class.typescript.push_str("\n");
         Ok(())
@@ -1932,13 +3098,23 @@ impl<'a, 'b> SubContext<'a, 'b> {
         };
 
         let target = self.generated_import_target(info, import, &descriptor)?;
+        let instrument = match &import.method {
+            Some(data) => format!("{}.{}", data.class, import.function.name),
+            None => import.function.name.clone(),
+        };
 
         let js = Rust2Js::new(self.cx)
+            .wasm_import(&import.shim)
+            .instrument(&instrument)
             .catch(import.catch)
             .variadic(import.variadic)
+            .slices(import.slices)
             .process(descriptor.unwrap_function())?
             .finish(&target)?;
-        self.cx.export(&import.shim, &js, None);
+        let source_begin = self.cx.source_begin(&import.function);
+        self.cx.export(&import.shim, &js, Some(source_begin));
+        let source_end = self.cx.source_end(&import.function);
+        self.cx.globals.push_str(&source_end);
         Ok(())
     }
 
@@ -2127,11 +3303,11 @@ impl<'a, 'b> SubContext<'a, 'b> {
     fn import_name(&mut self, import: &shared::Import, item: &str) -> Result<String, Error> {
         // First up, imports don't work at all in `--no-modules` mode as we're
         // not sure how to import them.
-        if self.cx.config.no_modules {
+        if self.cx.mode.no_modules() {
             if let Some(module) = &import.module {
                 bail!(
-                    "import from `{}` module not allowed with `--no-modules`; \
-                     use `--nodejs` or `--browser` instead",
+                    "import from `{}` module not allowed with `--target no-modules`; \
+                     use `--target web`, `--target nodejs` or `--target bundler` instead",
                     module
                 );
             }
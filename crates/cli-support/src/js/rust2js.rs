@@ -1,7 +1,10 @@
 use failure::{self, Error};
 
 use super::{Context, Js2Rust};
-use descriptor::{Descriptor, Function};
+use anyref::{self, Ownership};
+use bigint;
+use descriptor::{Descriptor, Function, VectorKind};
+use shared::SliceMode;
 
 /// Helper struct for manufacturing a shim in JS used to translate Rust types to
 /// JS, then invoking an imported JS function.
@@ -39,6 +42,36 @@ pub struct Rust2Js<'a, 'b: 'a> {
 
     /// Whether or not the last argument is a slice representing variadic arguments.
     variadic: bool,
+
+    /// How slices borrowed from Rust are passed to JS.
+    slices: SliceMode,
+
+    /// Variables watching views of slices passed to JS in debug mode, see
+    /// `borrowed_slice`.
+    watched_views: Vec<String>,
+
+    /// Name of the wasm import this shim is for.
+    ///
+    /// With `--reference-types` the import is changed to take and return JS
+    /// values directly, so the shim doesn't need to look them up in the table.
+    wasm_import: Option<String>,
+
+    /// Shim arguments which are JS values passed directly by `wasm_import`.
+    anyref_args: Vec<(String, Ownership)>,
+
+    /// Whether `wasm_import` returns a JS value directly.
+    anyref_ret: bool,
+
+    /// Shim arguments which are `i64` values passed directly as BigInts by
+    /// `wasm_import`, with `--bigint`.
+    bigint_args: Vec<String>,
+
+    /// Whether `wasm_import` returns an `i64` value directly.
+    bigint_ret: bool,
+
+    /// Name this shim's calls are recorded under with `--profile-boundary`,
+    /// and the creation site of objects it allocates with `--debug`.
+    instrument: Option<String>,
 }
 
 impl<'a, 'b> Rust2Js<'a, 'b> {
@@ -54,9 +87,32 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
             ret_expr: String::new(),
             catch: false,
             variadic: false,
+            slices: SliceMode::View,
+            watched_views: Vec::new(),
+            wasm_import: None,
+            anyref_args: Vec::new(),
+            anyref_ret: false,
+            bigint_args: Vec::new(),
+            bigint_ret: false,
+            instrument: None,
         }
     }
 
+    /// Flag this shim as the implementation of the wasm import `name`, which
+    /// must come before any arguments are processed.
+    pub fn wasm_import(&mut self, name: &str) -> &mut Self {
+        self.wasm_import = Some(name.to_string());
+        self
+    }
+
+    fn passes_anyref(&self) -> bool {
+        self.cx.config.reference_types && self.wasm_import.is_some()
+    }
+
+    fn passes_bigint(&self) -> bool {
+        self.cx.config.bigint && self.wasm_import.is_some()
+    }
+
     pub fn catch(&mut self, catch: bool) -> &mut Self {
         if catch {
             self.cx.expose_uint32_memory();
@@ -75,6 +131,20 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
         self
     }
 
+    pub fn slices(&mut self, slices: SliceMode) -> &mut Self {
+        self.slices = slices;
+        self
+    }
+
+    /// Records calls to this shim as calls to the import `name` with
+    /// `--profile-boundary` and `--debug`.
+    pub fn instrument(&mut self, name: &str) -> &mut Self {
+        if self.cx.config.profile_boundary || self.cx.config.debug {
+            self.instrument = Some(name.to_string());
+        }
+        self
+    }
+
     /// Generates all bindings necessary for the signature in `Function`,
     /// creating necessary argument conversions and return value processing.
     pub fn process(&mut self, function: &Function) -> Result<&mut Self, Error> {
@@ -128,6 +198,14 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
 
                 ));
                 self.cx.require_internal_export("__wbindgen_free")?;
+            } else if let VectorKind::String = ty {
+                // Borrowed strings are always decoded into a JS string.
+            } else {
+                let mutable = match arg {
+                    Descriptor::Clamped(d) => d.is_mut_ref(),
+                    _ => arg.is_mut_ref(),
+                };
+                self.borrowed_slice(&abi, &abi2, f, mutable, optional);
             }
             self.js_arguments.push(format!("v{}", abi));
             return Ok(());
@@ -136,10 +214,20 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
         // No need to special case `optional` here because `takeObject` will
         // naturally work.
         if arg.is_anyref() {
+            if self.passes_anyref() {
+                self.anyref_args.push((abi.clone(), Ownership::Owned));
+                self.js_arguments.push(abi);
+                return Ok(())
+            }
             self.cx.expose_take_object();
             self.js_arguments.push(format!("takeObject({})", abi));
             return Ok(())
         } else if arg.is_ref_anyref() {
+            if self.passes_anyref() {
+                self.anyref_args.push((abi.clone(), Ownership::Borrowed));
+                self.js_arguments.push(abi);
+                return Ok(())
+            }
             self.cx.expose_get_object();
             self.js_arguments.push(format!("getObject({})", abi));
             return Ok(())
@@ -206,6 +294,16 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
         }
 
         if let Some(signed) = arg.get_64() {
+            if self.passes_bigint() {
+                self.bigint_args.push(abi.clone());
+                // `i64` values always arrive as signed BigInts.
+                if signed {
+                    self.js_arguments.push(abi);
+                } else {
+                    self.js_arguments.push(format!("BigInt.asUintN(64, {})", abi));
+                }
+                return Ok(());
+            }
             let f = if signed {
                 self.cx.expose_int64_cvt_shim()
             } else {
@@ -228,6 +326,35 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
             return Ok(());
         }
 
+        if let Some(signed) = arg.get_128() {
+            let (low, high) = if self.passes_bigint() {
+                let high = self.shim_argument();
+                self.bigint_args.push(abi.clone());
+                self.bigint_args.push(high.clone());
+                (format!("BigInt.asUintN(64, {})", abi), high)
+            } else {
+                let words = self.cx.expose_u32x4_cvt_shim();
+                let f = self.cx.expose_uint128_cvt_shim();
+                let mut assign = format!("{}[0] = {};\n", words, abi);
+                for i in 1..4 {
+                    let word = self.shim_argument();
+                    assign.push_str(&format!("{}[{}] = {};\n", words, i, word));
+                }
+                self.prelude(&assign);
+                (format!("{}[0]", f), format!("{}[1]", f))
+            };
+            let name = format!("n{}", abi);
+            self.prelude(&format!(
+                "const {name} = BigInt.as{int}N(128, {low} | ({high} << BigInt(64)));",
+                name = name,
+                int = if signed { "Int" } else { "Uint" },
+                low = low,
+                high = high,
+            ));
+            self.js_arguments.push(name);
+            return Ok(());
+        }
+
         if let Some(class) = arg.rust_struct() {
             if arg.is_by_ref() {
                 bail!("cannot invoke JS functions with custom ref types yet")
@@ -324,6 +451,11 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
             return Ok(());
         }
         if ty.is_anyref() {
+            if self.passes_anyref() && !optional {
+                self.anyref_ret = true;
+                self.ret_expr = "return JS;".to_string();
+                return Ok(())
+            }
             self.cx.expose_add_heap_object();
             if optional {
                 self.cx.expose_is_like_none();
@@ -431,6 +563,11 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
             return Ok(());
         }
         if let Some(signed) = ty.get_64() {
+            if self.passes_bigint() {
+                self.bigint_ret = true;
+                self.ret_expr = "return JS;".to_string();
+                return Ok(());
+            }
             let f = if signed {
                 self.cx.expose_int64_memory();
                 "getInt64Memory"
@@ -448,6 +585,16 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
             );
             return Ok(());
         }
+        if ty.get_128().is_some() {
+            self.cx.expose_uint64_memory();
+            self.shim_arguments.insert(0, "ret".to_string());
+            self.ret_expr = "\
+                const val = JS;\n\
+                getUint64Memory()[ret / 8] = val;\n\
+                getUint64Memory()[ret / 8 + 1] = val >> BigInt(64);\n\
+            ".to_string();
+            return Ok(());
+        }
 
         if let Some(class) = ty.rust_struct() {
             if ty.is_by_ref() {
@@ -464,9 +611,11 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
                 }}
                 const ret = val.ptr;
                 val.ptr = 0;
+                {1}\
                 return ret;\
             ",
-                class
+                class,
+                self.cx.untrack_live_object("ret"),
             );
             return Ok(());
         }
@@ -479,7 +628,24 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
         Ok(())
     }
 
-    pub fn finish(&self, invoc: &str) -> Result<String, Error> {
+    pub fn finish(&mut self, invoc: &str) -> Result<String, Error> {
+        if let Some(import) = &self.wasm_import {
+            let args = self.anyref_args.iter().map(|(name, ownership)| {
+                let pos = self.shim_arguments.iter().position(|a| a == name).unwrap();
+                (pos, *ownership)
+            });
+            self.cx.anyref.import(import, anyref::Signature {
+                args: args.collect(),
+                ret: self.anyref_ret,
+            });
+            let args = self.bigint_args.iter().map(|name| {
+                self.shim_arguments.iter().position(|a| a == name).unwrap()
+            });
+            self.cx.bigint.import(import, bigint::Signature {
+                args: args.collect(),
+                ret: self.bigint_ret,
+            });
+        }
         let mut ret = String::new();
         ret.push_str("function(");
         ret.push_str(&self.shim_arguments.join(", "));
@@ -490,7 +656,7 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
             ret.push_str("exnptr");
         }
         ret.push_str(") {\n");
-        ret.push_str(&self.prelude);
+        let mut body = self.prelude.clone();
 
         let mut invoc = if self.variadic {
             if self.js_arguments.is_empty() {
@@ -523,6 +689,36 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
                 &format!("{}({})", invoc, self.js_arguments.join(", ")),
             )
         };
+        if self.watched_views.len() > 0 {
+            // Only report a detached view if the call didn't throw, so the
+            // error doesn't replace the real exception.
+            body.push_str("let threw = false;\n");
+            invoc = format!(
+                "\
+                try {{\n\
+                    {}
+                }} catch (e) {{\n\
+                    threw = true;
+                    throw e;
+                }}\
+                ",
+                invoc
+            );
+            let detached = self
+                .watched_views
+                .iter()
+                .map(|w| format!("{}.detached()", w))
+                .collect::<Vec<_>>()
+                .join(" || ");
+            self.finally(&format!(
+                "\
+                 if (!threw && ({})) {{
+                     throw detachedViewError();
+                 }}\
+                 ",
+                detached
+            ));
+        }
         if self.catch {
             let catch = "\
                          const view = getUint32Memory();\n\
@@ -554,12 +750,68 @@ impl<'a, 'b> Rust2Js<'a, 'b> {
                 &invoc, &self.finally
             );
         }
-        ret.push_str(&invoc);
+        body.push_str(&invoc);
+        match &self.instrument {
+            Some(name) => ret.push_str(&self.cx.instrument("import", name, &body)),
+            None => ret.push_str(&body),
+        }
 
         ret.push_str("\n}\n");
         Ok(ret)
     }
 
+    /// Handles a slice borrowed from Rust, which starts out as the view
+    /// `v{abi}` of wasm memory, according to `self.slices`.
+    fn borrowed_slice(&mut self, abi: &str, abi2: &str, get: &str, mutable: bool, optional: bool) {
+        let (start, end) = if optional {
+            (format!("if ({} !== 0) {{", abi), "}")
+        } else {
+            (String::new(), "")
+        };
+        match self.slices {
+            // Memory growing during the call detaches the view, after which
+            // it silently reads as empty and drops writes. That can't be
+            // prevented, but in debug mode we can at least report it, which
+            // `finish` checks.
+            SliceMode::View => {
+                if !self.cx.config.debug {
+                    return;
+                }
+                self.cx.expose_watch_view();
+                self.prelude(&format!("const w{0} = watchView(v{0}, {1});", abi, abi2));
+                self.watched_views.push(format!("w{}", abi));
+            }
+            SliceMode::Copy | SliceMode::CopyInOut => {
+                self.prelude(&format!(
+                    "\
+                     {start}
+                     v{abi} = v{abi}.slice();
+                     {end}\
+                     ",
+                    abi = abi,
+                    start = start,
+                    end = end,
+                ));
+                // The slice's memory may have moved if memory grew, so it's
+                // looked up again to write the copy back.
+                if self.slices == SliceMode::CopyInOut && mutable {
+                    self.finally(&format!(
+                        "\
+                         {start}
+                         {get}({abi}, {len}).set(v{abi});
+                         {end}\
+                         ",
+                        get = get,
+                        abi = abi,
+                        len = abi2,
+                        start = start,
+                        end = end,
+                    ));
+                }
+            }
+        }
+    }
+
     fn global_idx(&mut self) -> usize {
         let ret = self.global_idx;
         self.global_idx += 1;
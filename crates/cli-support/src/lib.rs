@@ -1,7 +1,13 @@
 #![doc(html_root_url = "https://docs.rs/wasm-bindgen-cli-support/0.2")]
 
+#[cfg(feature = "coverage")]
+extern crate addr2line;
 extern crate parity_wasm;
+extern crate rustc_demangle;
 extern crate wasm_bindgen_shared as shared;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
 extern crate wasm_gc;
 #[macro_use]
@@ -18,26 +24,70 @@ use std::path::{Path, PathBuf};
 use failure::{Error, ResultExt};
 use parity_wasm::elements::*;
 
+pub mod api_diff;
+mod anyref;
+mod bigint;
+mod binary;
+#[cfg(feature = "coverage")]
+pub mod coverage;
 mod descriptor;
 mod js;
+mod manifest;
+mod multi_value;
+mod source_map;
+mod threads;
 pub mod wasm2es6js;
 
 pub struct Bindgen {
     input: Input,
+    out_name: Option<String>,
+    bundler: bool,
     nodejs: bool,
     nodejs_experimental_modules: bool,
     browser: bool,
+    web: bool,
     no_modules: bool,
     no_modules_global: Option<String>,
     debug: bool,
     typescript: bool,
     demangle: bool,
     keep_debug: bool,
+    source_map: bool,
+    threads: bool,
+    reference_types: bool,
+    multi_value: bool,
+    bigint: bool,
+    profile_boundary: bool,
+    emit_manifest: bool,
     // Experimental support for `WeakRefGroup`, an upcoming ECMAScript feature.
     // Currently only enable-able through an env var.
     weak_refs: bool,
 }
 
+enum OutputMode {
+    Bundler { browser_only: bool },
+    Web,
+    NoModules { global: String },
+    Node { experimental_modules: bool },
+}
+
+/// The result of running `wasm-bindgen` over a wasm module, held entirely in
+/// memory.
+///
+/// This is returned from `Bindgen::generate_output` for tools which want to
+/// post-process or bundle the generated files themselves, and can be written
+/// out to a directory with `Output::emit`.
+pub struct Output {
+    stem: String,
+    extension: &'static str,
+    js: String,
+    typescript: Option<String>,
+    source_map: Option<String>,
+    node_shim: Option<String>,
+    manifest: Option<String>,
+    wasm: Vec<u8>,
+}
+
 enum Input {
     Path(PathBuf),
     Bytes(Vec<u8>, String),
@@ -49,15 +99,25 @@ impl Bindgen {
     pub fn new() -> Bindgen {
         Bindgen {
             input: Input::None,
+            out_name: None,
+            bundler: false,
             nodejs: false,
             nodejs_experimental_modules: false,
             browser: false,
+            web: false,
             no_modules: false,
             no_modules_global: None,
             debug: false,
             typescript: false,
             demangle: true,
             keep_debug: false,
+            source_map: false,
+            threads: false,
+            reference_types: false,
+            multi_value: false,
+            bigint: false,
+            profile_boundary: false,
+            emit_manifest: false,
             weak_refs: env::var("WASM_BINDGEN_WEAKREF").is_ok(),
         }
     }
@@ -97,6 +157,21 @@ impl Bindgen {
         self
     }
 
+    /// Sets the stem used for all generated file names, instead of deriving
+    /// it from the input file's name.
+    pub fn out_name(&mut self, name: &str) -> &mut Bindgen {
+        self.out_name = Some(name.to_string());
+        self
+    }
+
+    /// Generate output for a bundler, which is the default. This only needs
+    /// to be called to explicitly ask for it, in which case any other target
+    /// is rejected when generating bindings.
+    pub fn bundler(&mut self, bundler: bool) -> &mut Bindgen {
+        self.bundler = bundler;
+        self
+    }
+
     pub fn nodejs(&mut self, node: bool) -> &mut Bindgen {
         self.nodejs = node;
         self
@@ -112,6 +187,14 @@ impl Bindgen {
         self
     }
 
+    /// Generate a native ES module which can be loaded directly in a browser,
+    /// without a bundler, and which exports an `init` function to fetch and
+    /// instantiate the wasm module.
+    pub fn web(&mut self, web: bool) -> &mut Bindgen {
+        self.web = web;
+        self
+    }
+
     pub fn no_modules(&mut self, no_modules: bool) -> &mut Bindgen {
         self.no_modules = no_modules;
         self
@@ -122,6 +205,45 @@ impl Bindgen {
         self
     }
 
+    /// Works out the output mode from the target flags, failing if more than
+    /// one target was asked for or a flag doesn't apply to the target.
+    fn output_mode(&self) -> Result<OutputMode, Error> {
+        let mut targets = Vec::new();
+        if self.bundler {
+            targets.push(("bundler", OutputMode::Bundler { browser_only: self.browser }));
+        }
+        if self.web {
+            targets.push(("web", OutputMode::Web));
+        }
+        if self.no_modules {
+            let global = self.no_modules_global.clone()
+                .unwrap_or_else(|| "wasm_bindgen".to_string());
+            targets.push(("no-modules", OutputMode::NoModules { global }));
+        }
+        if self.nodejs || self.nodejs_experimental_modules {
+            let experimental_modules = self.nodejs_experimental_modules;
+            targets.push(("nodejs", OutputMode::Node { experimental_modules }));
+        }
+        if targets.len() > 1 {
+            bail!(
+                "cannot specify both `--target {}` and `--target {}`",
+                targets[0].0,
+                targets[1].0
+            );
+        }
+        let mode = match targets.pop() {
+            Some((_, mode)) => mode,
+            None => OutputMode::Bundler { browser_only: self.browser },
+        };
+        if self.browser && mode.nodejs() {
+            bail!("cannot specify `--browser` with `--target nodejs`");
+        }
+        if self.no_modules_global.is_some() && !mode.no_modules() {
+            bail!("can only specify `--no-modules-global` with `--target no-modules`");
+        }
+        Ok(mode)
+    }
+
     pub fn debug(&mut self, debug: bool) -> &mut Bindgen {
         self.debug = debug;
         self
@@ -142,11 +264,61 @@ impl Bindgen {
         self
     }
 
+    /// Emit a source map alongside the generated JS which maps shims back to
+    /// the Rust functions they were generated for.
+    pub fn source_map(&mut self, source_map: bool) -> &mut Bindgen {
+        self.source_map = source_map;
+        self
+    }
+
+    /// Prepare the module to be instantiated on multiple threads sharing one
+    /// memory, and generate JS glue for starting those threads.
+    pub fn threads(&mut self, threads: bool) -> &mut Bindgen {
+        self.threads = threads;
+        self
+    }
+
+    /// Pass JS values to and from wasm as `externref` values and keep them in
+    /// a table owned by the wasm module, rather than in a slab in the JS glue.
+    pub fn reference_types(&mut self, reference_types: bool) -> &mut Bindgen {
+        self.reference_types = reference_types;
+        self
+    }
+
+    /// Return strings and vectors from exports as a pair of results, rather
+    /// than writing them to a return pointer into the global argument buffer.
+    pub fn multi_value(&mut self, multi_value: bool) -> &mut Bindgen {
+        self.multi_value = multi_value;
+        self
+    }
+
+    /// Pass 64-bit integers to and from wasm as `i64` values, relying on the
+    /// JS BigInt integration, rather than splitting them into `u32` halves.
+    pub fn bigint(&mut self, bigint: bool) -> &mut Bindgen {
+        self.bigint = bigint;
+        self
+    }
+
+    /// Count calls to, and time spent in, every export and import shim in the
+    /// JS glue, reported by a generated `__wbindgen_profile_report` export.
+    pub fn profile_boundary(&mut self, profile_boundary: bool) -> &mut Bindgen {
+        self.profile_boundary = profile_boundary;
+        self
+    }
+
+    /// Also generate a JSON manifest describing every import and export.
+    pub fn emit_manifest(&mut self, emit_manifest: bool) -> &mut Bindgen {
+        self.emit_manifest = emit_manifest;
+        self
+    }
+
     pub fn generate<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
-        self._generate(path.as_ref())
+        self.generate_output()?.emit(path.as_ref())
     }
 
-    fn _generate(&mut self, out_dir: &Path) -> Result<(), Error> {
+    /// Runs `wasm-bindgen` over the input module, returning all generated
+    /// files in memory rather than writing them to disk.
+    pub fn generate_output(&mut self) -> Result<Output, Error> {
         let (mut module, stem) = match self.input {
             Input::None => bail!("must have an input by now"),
             Input::Module(ref mut m, ref name) => {
@@ -167,9 +339,39 @@ impl Bindgen {
                 (module, stem)
             }
         };
+        let stem = match &self.out_name {
+            Some(name) => name.clone(),
+            None => stem.to_string(),
+        };
+        let mode = self.output_mode()?;
         let programs = extract_programs(&mut module)
             .with_context(|_| "failed to extract wasm-bindgen custom sections")?;
 
+        if self.threads {
+            // The JS glue needs to control instantiation to share the memory
+            // and module with other threads, which isn't possible when a
+            // bundler or node's ES module loader imports the wasm.
+            match mode {
+                OutputMode::Bundler { .. } |
+                OutputMode::Node { experimental_modules: true } => bail!(
+                    "`--threads` is only supported with `--target web`, \
+                     `--target no-modules` or `--target nodejs`"
+                ),
+                _ => {}
+            }
+            // Each thread would have its own table of JS values, but the
+            // allocator for slots in it lives in the shared memory.
+            if self.reference_types {
+                bail!("`--reference-types` cannot currently be used with `--threads`");
+            }
+            threads::run(&mut module)
+                .context("failed to prepare the wasm module for threads")?;
+        }
+        // Both wrap imports in shims, which don't compose yet.
+        if self.bigint && self.reference_types {
+            bail!("`--bigint` cannot currently be used with `--reference-types`");
+        }
+
         // Here we're actually instantiating the module we've parsed above for
         // execution. Why, you might be asking, are we executing wasm code? A
         // good question!
@@ -185,7 +387,14 @@ impl Bindgen {
         // then generate the appropriate bindings.
         let mut instance = wasm_interpreter::Interpreter::new(&module);
 
-        let (js, ts) = {
+        let manifest = if self.emit_manifest {
+            let manifest = manifest::build(&programs, &mut instance, &module)?;
+            Some(serde_json::to_string_pretty(&manifest)?)
+        } else {
+            None
+        };
+
+        let (js, ts, mode, source_locations, multi_value, anyref) = {
             let mut cx = js::Context {
                 globals: String::new(),
                 imports: String::new(),
@@ -197,12 +406,17 @@ impl Bindgen {
                 imported_identifiers: Default::default(),
                 exported_classes: Default::default(),
                 config: &self,
+                mode,
                 module: &mut module,
                 function_table_needed: false,
                 interpreter: &mut instance,
                 memory_init: None,
                 imported_functions: Default::default(),
                 imported_statics: Default::default(),
+                source_locations: Vec::new(),
+                multi_value: Default::default(),
+                bigint: Default::default(),
+                anyref: Default::default(),
             };
             for program in programs.iter() {
                 js::SubContext {
@@ -210,36 +424,68 @@ impl Bindgen {
                     cx: &mut cx,
                 }.generate()?;
             }
-            cx.finalize(stem)?
+            let (js, ts) = cx.finalize(&stem)?;
+            (js, ts, cx.mode, cx.source_locations, cx.multi_value, cx.anyref)
         };
 
-        let extension = if self.nodejs_experimental_modules { "mjs" } else { "js" };
-        let js_path = out_dir.join(stem).with_extension(extension);
-        fs::write(&js_path, reset_indentation(&js))
-            .with_context(|_| format!("failed to write `{}`", js_path.display()))?;
-
-        if self.typescript {
-            let ts_path = out_dir.join(stem).with_extension("d.ts");
-            fs::write(&ts_path, ts)
-                .with_context(|_| format!("failed to write `{}`", ts_path.display()))?;
+        let extension = if mode.nodejs_experimental_modules() { "mjs" } else { "js" };
+        // With threads the JS glue instantiates the module itself.
+        let node_shim = if mode.nodejs() && !self.threads {
+            let wasm_name = format!("{}_bg.wasm", stem);
+            Some(self.generate_node_wasm_import(&module, &wasm_name, &mode))
+        } else {
+            None
+        };
+        let typescript = if self.typescript { Some(ts) } else { None };
+        let mut wasm = parity_wasm::serialize(module)?;
+        if self.multi_value {
+            wasm = multi_value.finish(&wasm)
+                .context("failed to rewrite the wasm module to use multi-value")?;
         }
-
-        let wasm_path = out_dir.join(format!("{}_bg", stem)).with_extension("wasm");
-
-        if self.nodejs {
-            let js_path = wasm_path.with_extension(extension);
-            let shim = self.generate_node_wasm_import(&module, &wasm_path);
-            fs::write(&js_path, shim)
-                .with_context(|_| format!("failed to write `{}`", js_path.display()))?;
+        if self.reference_types {
+            wasm = anyref.finish(&wasm)
+                .context("failed to rewrite the wasm module to use reference types")?;
         }
 
-        let wasm_bytes = parity_wasm::serialize(module)?;
-        fs::write(&wasm_path, wasm_bytes)
-            .with_context(|_| format!("failed to write `{}`", wasm_path.display()))?;
-        Ok(())
+        let mut js = reset_indentation(&js);
+        let source_map = if self.source_map {
+            // Without locations the map would be empty, which is almost
+            // certainly not what was asked for, see `Function::location` in
+            // the backend for when they're recorded.
+            let has_functions = programs
+                .iter()
+                .any(|p| !p.exports.is_empty() || !p.imports.is_empty());
+            if has_functions && source_locations.is_empty() {
+                bail!(
+                    "`--source-map` was passed, but the wasm file doesn't record \
+                     where any `#[wasm_bindgen]` function is defined; source \
+                     locations are only recorded when the crate is compiled \
+                     with a nightly compiler and \
+                     `RUSTFLAGS=\"--cfg procmacro2_semver_exempt\"`"
+                );
+            }
+            let js_name = format!("{}.{}", stem, extension);
+            let (stripped, map) = source_map::generate(&js, &js_name, &source_locations);
+            js = stripped;
+            js.push_str(&format!("//# sourceMappingURL={}.map\n", js_name));
+            Some(map)
+        } else {
+            None
+        };
+
+        Ok(Output {
+            stem,
+            extension,
+            js,
+            typescript,
+            source_map,
+            node_shim,
+            manifest,
+            wasm,
+        })
     }
 
-    fn generate_node_wasm_import(&self, m: &Module, path: &Path) -> String {
+    fn generate_node_wasm_import(&self, m: &Module, wasm_name: &str, mode: &OutputMode) -> String {
         let mut imports = BTreeSet::new();
         if let Some(i) = m.import_section() {
             for i in i.entries() {
@@ -249,7 +495,7 @@ impl Bindgen {
 
         let mut shim = String::new();
 
-        if self.nodejs_experimental_modules {
+        if mode.nodejs_experimental_modules() {
             for (i, module) in imports.iter().enumerate() {
                 shim.push_str(&format!("import * as import{} from '{}';\n",
                                        i, module));
@@ -267,16 +513,16 @@ impl Bindgen {
                     file = file.substring(1);
                 }}
                 const bytes = fs.readFileSync(path.join(file, '{}'));
-            ", path.file_name().unwrap().to_str().unwrap()));
+            ", wasm_name));
         } else {
             shim.push_str(&format!("
                 const path = require('path').join(__dirname, '{}');
                 const bytes = require('fs').readFileSync(path);
-            ", path.file_name().unwrap().to_str().unwrap()));
+            ", wasm_name));
         }
         shim.push_str("let imports = {};\n");
         for (i, module) in imports.iter().enumerate() {
-            if self.nodejs_experimental_modules {
+            if mode.nodejs_experimental_modules() {
                 shim.push_str(&format!("imports['{}'] = import{};\n", module, i));
             } else {
                 shim.push_str(&format!("imports['{0}'] = require('{0}');\n", module));
@@ -290,7 +536,7 @@ impl Bindgen {
             ",
         ));
 
-        if self.nodejs_experimental_modules {
+        if mode.nodejs_experimental_modules() {
             if let Some(e) = m.export_section() {
                 for name in e.entries().iter().map(|e| e.field()) {
                     shim.push_str("export const ");
@@ -308,6 +554,128 @@ impl Bindgen {
     }
 }
 
+impl Output {
+    /// The stem that all generated file names are derived from.
+    pub fn stem(&self) -> &str {
+        &self.stem
+    }
+
+    /// The generated JS bindings.
+    pub fn js(&self) -> &str {
+        &self.js
+    }
+
+    /// The generated TypeScript definitions, if TypeScript output is enabled.
+    pub fn typescript(&self) -> Option<&str> {
+        self.typescript.as_ref().map(|s| &**s)
+    }
+
+    /// The source map for the generated JS, if source maps are enabled.
+    pub fn source_map(&self) -> Option<&str> {
+        self.source_map.as_ref().map(|s| &**s)
+    }
+
+    /// The JS shim which loads and instantiates the wasm file, only generated
+    /// when targeting Node.js.
+    pub fn node_shim(&self) -> Option<&str> {
+        self.node_shim.as_ref().map(|s| &**s)
+    }
+
+    /// The JSON manifest of the module's imports and exports, if enabled.
+    pub fn manifest(&self) -> Option<&str> {
+        self.manifest.as_ref().map(|s| &**s)
+    }
+
+    /// The processed wasm module.
+    pub fn wasm(&self) -> &[u8] {
+        &self.wasm
+    }
+
+    /// Writes all generated files into `out_dir`, which must already exist.
+    pub fn emit<P: AsRef<Path>>(&self, out_dir: P) -> Result<(), Error> {
+        self._emit(out_dir.as_ref())
+    }
+
+    fn _emit(&self, out_dir: &Path) -> Result<(), Error> {
+        let js_path = out_dir.join(&self.stem).with_extension(self.extension);
+        fs::write(&js_path, &self.js)
+            .with_context(|_| format!("failed to write `{}`", js_path.display()))?;
+
+        if let Some(map) = &self.source_map {
+            let map_path = out_dir
+                .join(&self.stem)
+                .with_extension(format!("{}.map", self.extension));
+            fs::write(&map_path, map)
+                .with_context(|_| format!("failed to write `{}`", map_path.display()))?;
+        }
+
+        if let Some(ts) = &self.typescript {
+            let ts_path = out_dir.join(&self.stem).with_extension("d.ts");
+            fs::write(&ts_path, ts)
+                .with_context(|_| format!("failed to write `{}`", ts_path.display()))?;
+        }
+
+        if let Some(manifest) = &self.manifest {
+            let manifest_path = out_dir.join(&self.stem).with_extension("manifest.json");
+            fs::write(&manifest_path, manifest)
+                .with_context(|_| format!("failed to write `{}`", manifest_path.display()))?;
+        }
+
+        let wasm_path = out_dir.join(format!("{}_bg", self.stem)).with_extension("wasm");
+
+        if let Some(shim) = &self.node_shim {
+            let js_path = wasm_path.with_extension(self.extension);
+            fs::write(&js_path, shim)
+                .with_context(|_| format!("failed to write `{}`", js_path.display()))?;
+        }
+
+        fs::write(&wasm_path, &self.wasm)
+            .with_context(|_| format!("failed to write `{}`", wasm_path.display()))?;
+        Ok(())
+    }
+}
+
+impl OutputMode {
+    fn nodejs_experimental_modules(&self) -> bool {
+        match self {
+            OutputMode::Node { experimental_modules } => *experimental_modules,
+            _ => false,
+        }
+    }
+
+    fn nodejs(&self) -> bool {
+        match self {
+            OutputMode::Node { .. } => true,
+            _ => false,
+        }
+    }
+
+    fn no_modules(&self) -> bool {
+        match self {
+            OutputMode::NoModules { .. } => true,
+            _ => false,
+        }
+    }
+
+    fn web(&self) -> bool {
+        match self {
+            OutputMode::Web => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the generated JS is guaranteed to only ever run in a browser,
+    /// meaning we don't need to fall back to node's `require` for globals.
+    fn always_run_in_browser(&self) -> bool {
+        match self {
+            OutputMode::Web => true,
+            OutputMode::NoModules { .. } => true,
+            OutputMode::Bundler { browser_only } => *browser_only,
+            _ => false,
+        }
+    }
+}
+
 fn extract_programs(module: &mut Module) -> Result<Vec<shared::Program>, Error> {
     let version = shared::version();
     let mut ret = Vec::new();
@@ -0,0 +1,303 @@
+//! A JSON description of everything a module imports and exports, written
+//! alongside the generated bindings with `--emit-manifest`.
+//!
+//! This is built from the same `shared::Program` and descriptor data used to
+//! generate the JS glue, so tools consuming it see exactly the interface the
+//! glue implements without having to parse the generated `*.d.ts` file.
+
+use std::collections::BTreeMap;
+
+use failure::{Error, ResultExt};
+use parity_wasm;
+use parity_wasm::elements::Module;
+use shared;
+use wasm_interpreter::Interpreter;
+
+use descriptor::{self, Descriptor};
+
+#[derive(Serialize)]
+pub struct Manifest {
+    pub version: String,
+    pub functions: Vec<Function>,
+    pub classes: Vec<Class>,
+    pub enums: Vec<Enum>,
+    pub imports: Vec<Import>,
+}
+
+#[derive(Serialize)]
+pub struct Function {
+    pub name: String,
+    pub signature: descriptor::Function,
+    pub comments: Vec<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct Class {
+    pub name: String,
+    pub fields: Vec<Field>,
+    pub methods: Vec<Method>,
+    pub comments: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Field {
+    pub name: String,
+    pub readonly: bool,
+    #[serde(rename = "type")]
+    pub ty: Descriptor,
+    pub comments: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Method {
+    pub name: String,
+    pub kind: MethodKind,
+    pub signature: descriptor::Function,
+    pub comments: Vec<String>,
+}
+
+#[derive(Serialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MethodKind {
+    Constructor,
+    /// Takes `&self` or `&mut self`.
+    Method,
+    /// Takes `self`, freeing the JS object.
+    Consuming,
+    Static,
+}
+
+#[derive(Serialize)]
+pub struct Enum {
+    pub name: String,
+    pub variants: Vec<EnumVariant>,
+    pub comments: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct EnumVariant {
+    pub name: String,
+    pub value: u32,
+}
+
+#[derive(Serialize)]
+pub struct Import {
+    pub module: Option<String>,
+    pub js_namespace: Option<String>,
+    /// What the import refers to in JS, like `console.log`,
+    /// `Element.prototype.append` or `new Foo`.
+    pub target: String,
+    #[serde(flatten)]
+    pub kind: ImportKind,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ImportKind {
+    Function {
+        name: String,
+        catch: bool,
+        variadic: bool,
+        structural: bool,
+        signature: descriptor::Function,
+    },
+    Static {
+        name: String,
+    },
+    Type {
+        name: String,
+    },
+}
+
+/// Builds the manifest for the wasm file `wasm` without generating bindings
+/// for it.
+pub fn extract(wasm: &[u8]) -> Result<Manifest, Error> {
+    let mut module = parity_wasm::deserialize_buffer::<Module>(wasm)
+        .context("failed to parse input file as wasm")?;
+    let programs = ::extract_programs(&mut module)
+        .context("failed to extract wasm-bindgen custom sections")?;
+    let mut interpreter = Interpreter::new(&module);
+    build(&programs, &mut interpreter, &module)
+}
+
+/// Builds the manifest for `programs`, running the descriptor functions in
+/// `module` with `interpreter` to find the type of each import and export.
+pub fn build(
+    programs: &[shared::Program],
+    interpreter: &mut Interpreter,
+    module: &Module,
+) -> Result<Manifest, Error> {
+    let mut describe = |name: &str| {
+        let name = format!("__wbindgen_describe_{}", name);
+        interpreter
+            .interpret_descriptor(&name, module)
+            .map(Descriptor::decode)
+    };
+
+    let mut functions = Vec::new();
+    let mut classes = BTreeMap::new();
+    let mut enums = Vec::new();
+    let mut imports = Vec::new();
+    for program in programs {
+        for s in program.structs.iter() {
+            let class = class_entry(&mut classes, &s.name);
+            class.comments = s.comments.clone();
+            for field in s.fields.iter() {
+                let getter = shared::struct_field_get(&s.name, &field.name);
+                let ty = match describe(&getter) {
+                    Some(d) => d,
+                    None => continue,
+                };
+                class.fields.push(Field {
+                    name: field.name.clone(),
+                    readonly: field.readonly,
+                    ty,
+                    comments: field.comments.clone(),
+                });
+            }
+        }
+
+        for export in program.exports.iter() {
+            let class_name = match &export.class {
+                Some(class) => class,
+                None => {
+                    let name = &export.function.name;
+                    if let Some(d) = describe(name) {
+                        let signature = function(name, d)?;
+                        functions.push(Function {
+                            name: export.function.name.clone(),
+                            signature,
+                            comments: export.comments.clone(),
+                        });
+                    }
+                    continue;
+                }
+            };
+            let wasm_name =
+                shared::struct_function_export_name(class_name, &export.function.name);
+            let signature = match describe(&wasm_name) {
+                Some(d) => function(&wasm_name, d)?,
+                None => continue,
+            };
+            let kind = if export.is_constructor {
+                MethodKind::Constructor
+            } else if export.consumed {
+                MethodKind::Consuming
+            } else if export.method {
+                MethodKind::Method
+            } else {
+                MethodKind::Static
+            };
+            class_entry(&mut classes, class_name).methods.push(Method {
+                name: export.function.name.clone(),
+                kind,
+                signature,
+                comments: export.comments.clone(),
+            });
+        }
+
+        for e in program.enums.iter() {
+            enums.push(Enum {
+                name: e.name.clone(),
+                variants: e
+                    .variants
+                    .iter()
+                    .map(|v| EnumVariant {
+                        name: v.name.clone(),
+                        value: v.value,
+                    })
+                    .collect(),
+                comments: e.comments.clone(),
+            });
+        }
+
+        for import in program.imports.iter() {
+            let (target, kind) = match &import.kind {
+                shared::ImportKind::Function(f) => {
+                    let signature = match describe(&f.shim) {
+                        Some(d) => function(&f.shim, d)?,
+                        None => continue,
+                    };
+                    let target = function_target(import, f);
+                    let kind = ImportKind::Function {
+                        name: f.function.name.clone(),
+                        catch: f.catch,
+                        variadic: f.variadic,
+                        structural: f.structural,
+                        signature,
+                    };
+                    (target, kind)
+                }
+                shared::ImportKind::Static(s) => {
+                    let target = namespaced(import, &s.name);
+                    (target, ImportKind::Static { name: s.name.clone() })
+                }
+                shared::ImportKind::Type(t) => {
+                    let target = namespaced(import, &t.name);
+                    (target, ImportKind::Type { name: t.name.clone() })
+                }
+                shared::ImportKind::Enum(_) => continue,
+            };
+            imports.push(Import {
+                module: import.module.clone(),
+                js_namespace: import.js_namespace.clone(),
+                target,
+                kind,
+            });
+        }
+    }
+
+    Ok(Manifest {
+        version: shared::version(),
+        functions,
+        classes: classes.into_iter().map(|(_, c)| c).collect(),
+        enums,
+        imports,
+    })
+}
+
+fn function(name: &str, descriptor: Descriptor) -> Result<descriptor::Function, Error> {
+    match descriptor {
+        Descriptor::Function(f) => Ok(*f),
+        _ => bail!("`{}` is described as {:?} rather than a function", name, descriptor),
+    }
+}
+
+fn class_entry<'a>(classes: &'a mut BTreeMap<String, Class>, name: &str) -> &'a mut Class {
+    classes.entry(name.to_string()).or_insert_with(|| Class {
+        name: name.to_string(),
+        ..Class::default()
+    })
+}
+
+fn namespaced(import: &shared::Import, name: &str) -> String {
+    match &import.js_namespace {
+        Some(ns) => format!("{}.{}", ns, name),
+        None => name.to_string(),
+    }
+}
+
+fn function_target(import: &shared::Import, f: &shared::ImportFunction) -> String {
+    let method = match &f.method {
+        Some(method) => method,
+        None => return namespaced(import, &f.function.name),
+    };
+    let class = namespaced(import, &method.class);
+    let op = match &method.kind {
+        shared::MethodKind::Constructor => return format!("new {}", class),
+        shared::MethodKind::Operation(op) => op,
+    };
+    let location = if op.is_static {
+        class
+    } else {
+        format!("{}.prototype", class)
+    };
+    match &op.kind {
+        shared::OperationKind::Regular => format!("{}.{}", location, f.function.name),
+        shared::OperationKind::Getter(g) => format!("get {}.{}", location, g),
+        shared::OperationKind::Setter(s) => format!("set {}.{}", location, s),
+        shared::OperationKind::IndexingGetter => format!("{}[]", location),
+        shared::OperationKind::IndexingSetter => format!("{}[] =", location),
+        shared::OperationKind::IndexingDeleter => format!("delete {}[]", location),
+    }
+}
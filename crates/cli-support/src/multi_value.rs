@@ -0,0 +1,194 @@
+//! Support for `--multi-value`, returning strings and vectors from exports as
+//! a `(ptr, len)` pair of results.
+//!
+//! Rust exports returning a string or vector take an extra first parameter,
+//! a pointer to which they write the pointer and length of the returned
+//! value. By default the JS glue passes a pointer to the global argument
+//! buffer there and reads the result back out of memory. With this transform
+//! each such export is instead replaced with a shim which reserves space on
+//! the Rust stack, calls the original export, and returns both words directly.
+//!
+//! As with `anyref`, `parity-wasm` can't represent functions with multiple
+//! results, so `Transform::prepare` adds the shims with a single result and
+//! `Transform::finish` patches their types in the serialized module. The
+//! shims are exported under temporary names in between so they can be found
+//! again after gc, even if `anyref` has wrapped the public export in a shim
+//! of its own.
+
+use failure::Error;
+use parity_wasm::elements::*;
+
+use binary::RawModule;
+
+/// Bytes of stack reserved by each shim for the return value, keeping the
+/// stack pointer aligned to 16 bytes.
+pub const RET_AREA: i32 = 16;
+
+const SHIM_PREFIX: &str = "__wbindgen_multi_value_shim";
+
+#[derive(Default)]
+pub struct Transform {
+    exports: Vec<String>,
+}
+
+impl Transform {
+    /// Records that the JS shim for the export `name` expects it to return a
+    /// `(ptr, len)` pair instead of taking a return pointer.
+    pub fn export(&mut self, name: &str) {
+        self.exports.push(name.to_string());
+    }
+
+    /// Replaces all recorded exports with shims returning the two words their
+    /// original function writes through its return pointer.
+    ///
+    /// As in the interpreter, the stack pointer is assumed to be global 0.
+    pub fn prepare(&mut self, module: &mut Module) -> Result<(), Error> {
+        if self.exports.is_empty() {
+            return Ok(());
+        }
+        check_stack_pointer(module, "--multi-value")?;
+
+        let imported = module
+            .import_section()
+            .map(|s| s.functions())
+            .unwrap_or(0) as u32;
+        let mut next = imported + module
+            .function_section()
+            .map(|s| s.entries().len())
+            .unwrap_or(0) as u32;
+        let mut shims = Vec::new();
+
+        for name in self.exports.iter() {
+            let func = match module.export_section().and_then(|s| {
+                s.entries().iter().find(|e| e.field() == name)
+            }) {
+                Some(export) => match export.internal() {
+                    Internal::Function(f) if *f >= imported => *f,
+                    _ => bail!("export `{}` is not a function defined in wasm", name),
+                },
+                None => bail!("failed to find the export `{}`", name),
+            };
+            let ty = module.function_section().unwrap().entries()[(func - imported) as usize]
+                .type_ref();
+            let params = match &module.type_section().unwrap().types()[ty as usize] {
+                Type::Function(f) => f.params().to_vec(),
+            };
+            if params.first() != Some(&ValueType::I32) {
+                bail!("export `{}` doesn't take a return pointer", name);
+            }
+
+            // The shim has a single `i32` result for now, see `finish`.
+            let shim_params = params[1..].to_vec();
+            let sp = shim_params.len() as u32;
+            let shim_ty = add_type(module, FunctionType::new(shim_params, Some(ValueType::I32)));
+
+            let mut body = vec![
+                Instruction::GetGlobal(0),
+                Instruction::I32Const(RET_AREA),
+                Instruction::I32Sub,
+                Instruction::TeeLocal(sp),
+                Instruction::SetGlobal(0),
+                Instruction::GetLocal(sp),
+            ];
+            body.extend((0..sp).map(Instruction::GetLocal));
+            body.extend(vec![
+                Instruction::Call(func),
+                Instruction::GetLocal(sp),
+                Instruction::I32Load(2, 0),
+                Instruction::GetLocal(sp),
+                Instruction::I32Load(2, 4),
+                Instruction::GetLocal(sp),
+                Instruction::I32Const(RET_AREA),
+                Instruction::I32Add,
+                Instruction::SetGlobal(0),
+                Instruction::End,
+            ]);
+            shims.push((name.clone(), shim_ty, body, next));
+            next += 1;
+        }
+
+        for (i, (name, ty, body, idx)) in shims.into_iter().enumerate() {
+            module
+                .function_section_mut()
+                .unwrap()
+                .entries_mut()
+                .push(Func::new(ty));
+            module
+                .code_section_mut()
+                .unwrap()
+                .bodies_mut()
+                .push(FuncBody::new(
+                    vec![Local::new(1, ValueType::I32)],
+                    Instructions::new(body),
+                ));
+            let exports = module.export_section_mut().unwrap().entries_mut();
+            for export in exports.iter_mut() {
+                if export.field() == name {
+                    *export.internal_mut() = Internal::Function(idx);
+                }
+            }
+            exports.push(ExportEntry::new(
+                format!("{}{}", SHIM_PREFIX, i),
+                Internal::Function(idx),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Gives the shims added by `prepare`, and anything they're exported
+    /// through, their real `(i32, i32)` results in the serialized `wasm`
+    /// module.
+    pub fn finish(&self, wasm: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.exports.is_empty() {
+            return Ok(wasm.to_vec());
+        }
+        let mut module = RawModule::parse(wasm)?;
+        let imported = module.imported_functions();
+        for (i, name) in self.exports.iter().enumerate() {
+            let shim = format!("{}{}", SHIM_PREFIX, i);
+            let mut funcs = vec![module.exported_function(&shim)?];
+            funcs.push(module.exported_function(name)?);
+            funcs.dedup();
+            for func in funcs {
+                let idx = (func - imported) as usize;
+                let params = module.types[module.functions[idx] as usize].0.clone();
+                module.functions[idx] = module.add_type((params, vec![0x7f, 0x7f]));
+            }
+            module.exports.retain(|e| e.0 != shim);
+        }
+        Ok(module.emit())
+    }
+}
+
+/// Checks that global 0 looks like the stack pointer, which shims reserving
+/// space on the stack for return values assume.
+pub fn check_stack_pointer(module: &Module, flag: &str) -> Result<(), Error> {
+    let imported_globals = module
+        .import_section()
+        .map(|s| s.globals())
+        .unwrap_or(0);
+    let stack_pointer_ok = imported_globals == 0 && match module.global_section() {
+        Some(s) => s.entries().first().map(|g| {
+            let ty = g.global_type();
+            ty.is_mutable() && ty.content_type() == ValueType::I32
+        }) == Some(true),
+        None => false,
+    };
+    if !stack_pointer_ok {
+        bail!("failed to find the stack pointer, which is required for `{}`", flag);
+    }
+    Ok(())
+}
+
+pub fn add_type(module: &mut Module, ty: FunctionType) -> u32 {
+    let types = module.type_section_mut().unwrap().types_mut();
+    match types.iter().position(|t| match t {
+        Type::Function(f) => *f == ty,
+    }) {
+        Some(i) => i as u32,
+        None => {
+            types.push(Type::Function(ty));
+            types.len() as u32 - 1
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Generation of source maps for the JS glue.
+//!
+//! While generating JS, shims which correspond to a Rust item are bracketed by
+//! marker comments (see `Context::source_begin` and `Context::source_end`)
+//! naming the item's location in the Rust source. Once the final JS has been
+//! assembled the markers are stripped out here, and every generated line
+//! between a pair of markers is mapped back to the Rust item that produced it.
+
+use std::collections::HashMap;
+use std::fs;
+
+use serde_json;
+use shared;
+
+pub const BEGIN_MARKER: &str = "/* __wbindgen_source_begin ";
+pub const END_MARKER: &str = "/* __wbindgen_source_end */";
+
+/// Strips all source markers from `js`, returning the JS without them and the
+/// JSON source map describing where the marked lines came from.
+///
+/// `file` is the name of the generated JS file which the source map is for,
+/// and `locations` is indexed by the number in each begin marker.
+pub fn generate(js: &str, file: &str, locations: &[shared::Location]) -> (String, String) {
+    let mut dst = String::new();
+    let mut sources = Vec::new();
+    let mut source_indices = HashMap::new();
+    let mut mappings = String::new();
+    let mut current = None;
+
+    // All fields of a segment other than the generated column are relative
+    // to the previous segment in the whole map.
+    let mut prev_source = 0;
+    let mut prev_line = 0;
+    let mut prev_column = 0;
+
+    for line in js.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with(BEGIN_MARKER) {
+            let idx = trimmed[BEGIN_MARKER.len()..]
+                .trim_end_matches("*/")
+                .trim();
+            current = idx.parse::<usize>().ok().and_then(|i| locations.get(i));
+            continue;
+        }
+        if trimmed == END_MARKER {
+            current = None;
+            continue;
+        }
+
+        if let Some(location) = current {
+            if !trimmed.is_empty() {
+                let source = *source_indices
+                    .entry(location.file.clone())
+                    .or_insert_with(|| {
+                        sources.push(location.file.clone());
+                        sources.len() as i64 - 1
+                    });
+                // Lines in source maps are 0-based whereas the lines we get
+                // from the compiler are 1-based.
+                let line_idx = location.line.saturating_sub(1) as i64;
+                let column = location.column as i64;
+                let gen_column = (line.len() - line.trim_start().len()) as i64;
+
+                vlq(&mut mappings, gen_column);
+                vlq(&mut mappings, source - prev_source);
+                vlq(&mut mappings, line_idx - prev_line);
+                vlq(&mut mappings, column - prev_column);
+                prev_source = source;
+                prev_line = line_idx;
+                prev_column = column;
+            }
+        }
+
+        mappings.push(';');
+        dst.push_str(line);
+        dst.push_str("\n");
+    }
+
+    // Embed the Rust sources if they're readable from here, which is usually
+    // the case when `wasm-bindgen` is run from the crate's root directory.
+    let contents = sources
+        .iter()
+        .map(|path| fs::read_to_string(path).ok())
+        .collect::<Vec<_>>();
+
+    let map = json!({
+        "version": 3,
+        "file": file,
+        "sources": sources,
+        "sourcesContent": contents,
+        "names": [],
+        "mappings": mappings,
+    });
+    (dst, serde_json::to_string(&map).unwrap())
+}
+
+/// Appends `value` to `dst` as a base64 VLQ, the encoding used for all numbers
+/// in the `mappings` field of a source map.
+fn vlq(dst: &mut String, value: i64) {
+    const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut value = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+    loop {
+        let mut digit = value & 0b11111;
+        value >>= 5;
+        if value > 0 {
+            digit |= 0b100000;
+        }
+        dst.push(BASE64[digit as usize] as char);
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: i64) -> String {
+        let mut dst = String::new();
+        vlq(&mut dst, value);
+        dst
+    }
+
+    #[test]
+    fn vlq_encoding() {
+        assert_eq!(encode(0), "A");
+        assert_eq!(encode(1), "C");
+        assert_eq!(encode(-1), "D");
+        assert_eq!(encode(15), "e");
+        assert_eq!(encode(16), "gB");
+        assert_eq!(encode(-16), "hB");
+        assert_eq!(encode(123), "2H");
+        assert_eq!(encode(1000), "w+B");
+    }
+
+    fn location(file: &str, line: u32, column: u32) -> shared::Location {
+        shared::Location {
+            file: file.to_string(),
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn strips_markers() {
+        let js = "\
+const a = 1;
+/* __wbindgen_source_begin 0 */
+export function foo() {
+    return 1;
+}
+/* __wbindgen_source_end */
+/* __wbindgen_source_begin 1 */
+export function bar() {}
+/* __wbindgen_source_end */
+/* __wbindgen_source_begin 7 */
+const b = 2;
+/* __wbindgen_source_end */
+";
+        let locations = [
+            location("src/missing.rs", 3, 7),
+            location("src/other-missing.rs", 1, 0),
+        ];
+        let (js, map) = generate(js, "demo.js", &locations);
+        assert_eq!(
+            js,
+            "\
+const a = 1;
+export function foo() {
+    return 1;
+}
+export function bar() {}
+const b = 2;
+"
+        );
+
+        let map: serde_json::Value = serde_json::from_str(&map).unwrap();
+        assert_eq!(map["version"], 3);
+        assert_eq!(map["file"], "demo.js");
+        assert_eq!(map["sources"], json!(["src/missing.rs", "src/other-missing.rs"]));
+        assert_eq!(map["sourcesContent"], json!([null, null]));
+        // The first line and the one with an unknown location aren't mapped,
+        // and `bar` is relative to the last line of `foo`.
+        assert_eq!(map["mappings"], ";AAEO;IAAA;AAAA;ACFP;;");
+    }
+}
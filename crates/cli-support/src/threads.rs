@@ -0,0 +1,233 @@
+//! Support for running a wasm module on multiple threads.
+//!
+//! Each thread (the main thread or a Web Worker / node worker thread) has its
+//! own instance of the wasm module, and all of these instances share one
+//! `WebAssembly.Memory`. This module rewrites the wasm module so that's
+//! possible:
+//!
+//! * The memory is imported rather than defined, and is marked as shared so it
+//!   can be sent to other threads.
+//! * Data segments are moved out of the data section into a custom section.
+//!   Active segments would otherwise be written to memory every time an
+//!   instance is created, clobbering whatever the other threads have done
+//!   since. The JS glue instead initializes memory from the custom section
+//!   once, on the main thread.
+//! * A `__wbindgen_thread_init` function is exported which sets the stack
+//!   pointer, so each additional thread can be given its own stack.
+//!
+//! The rest, allocating stacks and starting instances on other threads, is
+//! handled by the JS glue generated in `js/mod.rs`.
+
+use failure::Error;
+use parity_wasm::elements::*;
+
+/// Name of the custom section that data segments are moved into.
+///
+/// The payload is a sequence of segments, each encoded as a little-endian
+/// `u32` offset, a little-endian `u32` length, and then the bytes themselves.
+pub const DATA_SECTION: &str = "__wasm_bindgen_thread_data";
+
+/// Number of 64KiB pages reserved for the stack of each new thread, 1MiB to
+/// match the default stack size of the main thread.
+pub const STACK_PAGES: u32 = 16;
+
+/// Maximum size, in pages, given to the shared memory if the module doesn't
+/// specify one. Shared memories must have a maximum size.
+const DEFAULT_MAXIMUM_PAGES: u32 = 16384;
+
+pub fn run(module: &mut Module) -> Result<(), Error> {
+    import_shared_memory(module)?;
+    move_data_segments(module)?;
+    inject_thread_init(module)?;
+    Ok(())
+}
+
+fn import_shared_memory(module: &mut Module) -> Result<(), Error> {
+    let mut limits = None;
+
+    if let Some(imports) = module.import_section_mut() {
+        for entry in imports.entries_mut() {
+            let memory = match entry.external() {
+                External::Memory(m) => *m,
+                _ => continue,
+            };
+            if entry.module() != "env" || entry.field() != "memory" {
+                bail!("imported memory must be named `env.memory` to use threads");
+            }
+            let limits = memory.limits();
+            let maximum = limits.maximum().unwrap_or(DEFAULT_MAXIMUM_PAGES);
+            *entry.external_mut() = External::Memory(shared_memory(limits.initial(), maximum)?);
+        }
+    }
+
+    // If the memory is defined by the module then we remove it here and
+    // import it instead. The index of the memory stays 0 either way, so
+    // references to it elsewhere in the module don't need updating.
+    for i in (0..module.sections().len()).rev() {
+        let remove = match &mut module.sections_mut()[i] {
+            Section::Memory(s) => {
+                if s.entries().len() != 1 {
+                    bail!("can only use threads with exactly one memory");
+                }
+                limits = Some(*s.entries()[0].limits());
+                true
+            }
+            _ => false,
+        };
+        if remove {
+            module.sections_mut().remove(i);
+        }
+    }
+
+    if let Some(limits) = limits {
+        let maximum = limits.maximum().unwrap_or(DEFAULT_MAXIMUM_PAGES);
+        let entry = ImportEntry::new(
+            "env".to_string(),
+            "memory".to_string(),
+            External::Memory(shared_memory(limits.initial(), maximum)?),
+        );
+        import_section(module).entries_mut().push(entry);
+    }
+
+    // The JS glue needs access to the memory so it can be sent to other
+    // threads, so make sure it's exported.
+    let exports = match module.export_section_mut() {
+        Some(s) => s,
+        None => bail!("the wasm module must export its memory to use threads"),
+    };
+    let exported = exports.entries().iter().any(|e| match e.internal() {
+        Internal::Memory(_) => e.field() == "memory",
+        _ => false,
+    });
+    if !exported {
+        exports
+            .entries_mut()
+            .push(ExportEntry::new("memory".to_string(), Internal::Memory(0)));
+    }
+    Ok(())
+}
+
+/// `parity-wasm` has no way to construct shared memory limits, so go through
+/// the binary encoding instead.
+fn shared_memory(initial: u32, maximum: u32) -> Result<MemoryType, Error> {
+    let mut bytes = vec![0x03];
+    VarUint32::from(initial).serialize(&mut bytes)?;
+    VarUint32::from(maximum).serialize(&mut bytes)?;
+    Ok(MemoryType::deserialize(&mut &bytes[..])?)
+}
+
+fn import_section(module: &mut Module) -> &mut ImportSection {
+    if module.import_section().is_none() {
+        // The import section comes after the type section, if any.
+        let pos = module
+            .sections()
+            .iter()
+            .position(|s| match s {
+                Section::Type(_) | Section::Custom(_) => false,
+                _ => true,
+            })
+            .unwrap_or(module.sections().len());
+        module
+            .sections_mut()
+            .insert(pos, Section::Import(ImportSection::default()));
+    }
+    module.import_section_mut().unwrap()
+}
+
+fn move_data_segments(module: &mut Module) -> Result<(), Error> {
+    let segments = match module.data_section_mut() {
+        Some(s) => s.entries_mut().drain(..).collect::<Vec<_>>(),
+        None => return Ok(()),
+    };
+
+    let mut payload = Vec::new();
+    for segment in segments {
+        let offset = match segment.offset().code() {
+            [Instruction::I32Const(n), Instruction::End] => *n as u32,
+            _ => bail!("data segments must have constant offsets to use threads"),
+        };
+        payload.extend_from_slice(&u32_le(offset));
+        payload.extend_from_slice(&u32_le(segment.value().len() as u32));
+        payload.extend_from_slice(segment.value());
+    }
+
+    let mut section = CustomSection::default();
+    *section.name_mut() = DATA_SECTION.to_string();
+    *section.payload_mut() = payload;
+    module.sections_mut().push(Section::Custom(section));
+    Ok(())
+}
+
+fn u32_le(n: u32) -> [u8; 4] {
+    [n as u8, (n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8]
+}
+
+/// Injects and exports `__wbindgen_thread_init(stack_top: i32)`, which sets
+/// the stack pointer of this instance.
+///
+/// As in the interpreter, the stack pointer is assumed to be global 0, which
+/// is where LLVM places it.
+fn inject_thread_init(module: &mut Module) -> Result<(), Error> {
+    let imported_globals = module
+        .import_section()
+        .map(|s| s.globals())
+        .unwrap_or(0);
+    let stack_pointer_ok = imported_globals == 0 && match module.global_section() {
+        Some(s) => s.entries().first().map(|g| {
+            let ty = g.global_type();
+            ty.is_mutable() && ty.content_type() == ValueType::I32
+        }) == Some(true),
+        None => false,
+    };
+    if !stack_pointer_ok {
+        bail!("failed to find the stack pointer, which is required to use threads");
+    }
+
+    let ty = FunctionType::new(vec![ValueType::I32], None);
+    let types = match module.type_section_mut() {
+        Some(s) => s,
+        None => bail!("the wasm module has no type section"),
+    };
+    let type_idx = match types.types().iter().position(|t| match t {
+        Type::Function(f) => *f == ty,
+    }) {
+        Some(i) => i as u32,
+        None => {
+            types.types_mut().push(Type::Function(ty));
+            types.types().len() as u32 - 1
+        }
+    };
+
+    let imported_functions = module
+        .import_section()
+        .map(|s| s.functions())
+        .unwrap_or(0) as u32;
+    let functions = match module.function_section_mut() {
+        Some(s) => s,
+        None => bail!("the wasm module has no function section"),
+    };
+    let func_idx = imported_functions + functions.entries().len() as u32;
+    functions.entries_mut().push(Func::new(type_idx));
+
+    let body = FuncBody::new(
+        Vec::new(),
+        Instructions::new(vec![
+            Instruction::GetLocal(0),
+            Instruction::SetGlobal(0),
+            Instruction::End,
+        ]),
+    );
+    match module.code_section_mut() {
+        Some(s) => s.bodies_mut().push(body),
+        None => bail!("the wasm module has no code section"),
+    }
+
+    match module.export_section_mut() {
+        Some(s) => s.entries_mut().push(ExportEntry::new(
+            "__wbindgen_thread_init".to_string(),
+            Internal::Function(func_idx),
+        )),
+        None => bail!("the wasm module has no export section"),
+    }
+    Ok(())
+}
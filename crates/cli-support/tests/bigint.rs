@@ -0,0 +1,156 @@
+extern crate parity_wasm;
+extern crate serde_json;
+extern crate tempfile;
+extern crate wasm_bindgen_cli_support;
+extern crate wasm_bindgen_shared;
+
+mod common;
+
+use common::*;
+use parity_wasm::elements::Instruction;
+use parity_wasm::elements::Instruction::*;
+
+const I128: u32 = 25;
+
+/// Joins the `i32` halves in the locals `lo` and `hi` into an `i64`.
+fn join(lo: u32, hi: u32) -> Vec<Instruction> {
+    vec![
+        GetLocal(hi),
+        I64ExtendUI32,
+        I64Const(32),
+        I64Shl,
+        GetLocal(lo),
+        I64ExtendUI32,
+        I64Or,
+    ]
+}
+
+/// The following, in the Rust ABI, where 64-bit integers are passed as two
+/// `i32` halves and returned through a return pointer:
+///
+/// ```ignore
+/// #[wasm_bindgen]
+/// extern { fn double(x: i64) -> i64; }
+///
+/// #[wasm_bindgen]
+/// pub fn add(a: i64, b: u64) -> i64 { a.wrapping_add(b as i64) }
+/// #[wasm_bindgen]
+/// pub fn call_double(x: i64) -> i64 { double(x) }
+/// #[wasm_bindgen]
+/// pub fn ident128(x: i128) -> i128 { x }
+/// ```
+fn module() -> Module {
+    let mut m = Module::new();
+    let double = m.import("double", &[FUNCTION, 1, I64, I64], &[I, I, I], None);
+    let global_argument_ptr = m.function(&[], Some(I), vec![I32Const(256)]);
+    m.export_function("__wbindgen_global_argument_ptr", global_argument_ptr);
+
+    let mut add = vec![GetLocal(0)];
+    add.extend(join(1, 2));
+    add.extend(join(3, 4));
+    add.extend(vec![I64Add, I64Store(3, 0)]);
+    m.export("add", &[FUNCTION, 2, I64, U64, I64], &[I, I, I, I, I], None, add);
+    m.export("call_double", &[FUNCTION, 1, I64, I64], &[I, I, I], None, vec![
+        GetLocal(0),
+        GetLocal(1),
+        GetLocal(2),
+        Call(double),
+    ]);
+    let mut ident128 = Vec::new();
+    for i in 0..4 {
+        ident128.extend(vec![GetLocal(0), GetLocal(i + 1), I32Store(2, i * 4)]);
+    }
+    m.export("ident128", &[FUNCTION, 1, I128, I128], &[I, I, I, I, I], None, ident128);
+    m
+}
+
+const CHECK: &str = "
+    const assert = require('assert');
+    globalThis.double = x => x * BigInt(2);
+
+    assert.strictEqual(wasm.add(BigInt(-1), BigInt(2)), BigInt(1));
+    const max = (BigInt(1) << BigInt(63)) - BigInt(1);
+    assert.strictEqual(wasm.add(max, BigInt(1)), -max - BigInt(1));
+    assert.strictEqual(wasm.call_double(BigInt(-21)), BigInt(-42));
+    const big = -(BigInt(1) << BigInt(100)) + BigInt(3);
+    assert.strictEqual(wasm.ident128(big), big);
+";
+
+#[test]
+fn passes_i64() {
+    let mut b = module().bindgen();
+    b.bigint(true);
+    run_node(
+        &mut b,
+        &format!(
+            "
+            {}
+            // The exports take and return `i64` values, except for 128-bit
+            // integers, which are two `i64` halves and a return pointer.
+            const raw = require('./demo_bg');
+            assert.strictEqual(raw.add.length, 2);
+            assert.strictEqual(raw.add(BigInt(2), BigInt(3)), BigInt(5));
+            assert.strictEqual(raw.call_double.length, 1);
+            assert.strictEqual(raw.ident128.length, 3);
+            ",
+            CHECK
+        ),
+    );
+}
+
+#[test]
+fn passes_halves_without_bigint() {
+    let mut b = module().bindgen();
+    run_node(
+        &mut b,
+        &format!(
+            "
+            {}
+            const raw = require('./demo_bg');
+            assert.strictEqual(raw.add.length, 5);
+            assert.strictEqual(raw.call_double.length, 3);
+            ",
+            CHECK
+        ),
+    );
+}
+
+#[test]
+fn rejects_reference_types() {
+    let mut b = module().bindgen();
+    b.bigint(true).reference_types(true);
+    match b.generate_output() {
+        Ok(_) => panic!("expected an error"),
+        Err(e) => assert_eq!(
+            e.to_string(),
+            "`--bigint` cannot currently be used with `--reference-types`"
+        ),
+    }
+}
+
+#[test]
+fn operators_catch_exceptions() {
+    // `fn add(a: &JsValue, b: &JsValue) -> u32`, returning whether `a + b`
+    // threw, as `JsValue::try_add` sees it.
+    let mut m = Module::new();
+    let add = m.intrinsic("__wbindgen_add", &[I, I, I], Some(I));
+    m.export("threw", &[FUNCTION, 2, REF, ANYREF, REF, ANYREF, U32], &[I, I], Some(I), vec![
+        GetLocal(0),
+        GetLocal(1),
+        I32Const(512),
+        Call(add),
+        Drop,
+        I32Const(512),
+        I32Load(2, 0),
+    ]);
+    let mut b = m.bindgen();
+    run_node(
+        &mut b,
+        "
+        const assert = require('assert');
+        assert.strictEqual(wasm.threw(BigInt(1), BigInt(2)), 0);
+        assert.strictEqual(wasm.threw(1, 2), 0);
+        assert.strictEqual(wasm.threw(BigInt(1), 2), 1);
+        ",
+    );
+}
@@ -0,0 +1,499 @@
+//! Helpers to build small wasm modules which look like what `rustc` emits for
+//! crates using `#[wasm_bindgen]`, run `wasm-bindgen` over them, and run the
+//! generated bindings in node.
+//!
+//! The modules are written by hand with `parity-wasm`, so each function's
+//! descriptor (see `src/describe.rs`) is given as the list of words its
+//! describe shim passes to `__wbindgen_describe`.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use parity_wasm::elements;
+use parity_wasm::elements::*;
+use tempfile::TempDir;
+use wasm_bindgen_cli_support::Bindgen;
+use wasm_bindgen_shared as shared;
+
+// NB: this list must be kept in sync with `src/describe.rs`
+pub const I32: u32 = 4;
+pub const U32: u32 = 5;
+pub const I64: u32 = 6;
+pub const U64: u32 = 7;
+pub const F64: u32 = 9;
+pub const BOOLEAN: u32 = 10;
+pub const FUNCTION: u32 = 11;
+pub const STRING: u32 = 13;
+pub const REF: u32 = 14;
+pub const REFMUT: u32 = 15;
+pub const SLICE: u32 = 16;
+pub const VECTOR: u32 = 17;
+pub const ANYREF: u32 = 18;
+pub const RUST_STRUCT: u32 = 20;
+pub const U8: u32 = 1;
+pub const UNIT: u32 = 23;
+
+pub const I: ValueType = ValueType::I32;
+
+/// Addresses of the words `Module::anyref_allocator` keeps the next free slot
+/// of the table of JS values in, the size of the table, and the slot freed
+/// last.
+pub const ANYREF_NEXT: i32 = 0;
+pub const ANYREF_SIZE: i32 = 4;
+pub const ANYREF_FREED: i32 = 8;
+
+/// How a method added with `Module::method` is called.
+pub enum MethodKind {
+    Constructor,
+    Static,
+    Ref,
+    Consumed,
+}
+
+/// Offset of the first byte of memory left free for data segments and the
+/// bump allocator of `Module::allocator`.
+pub const HEAP_BASE: i32 = 1024;
+
+struct Func {
+    params: Vec<ValueType>,
+    result: Option<ValueType>,
+    locals: Vec<ValueType>,
+    body: Vec<Instruction>,
+}
+
+pub struct Module {
+    program: shared::Program,
+    imports: Vec<(String, Vec<ValueType>, Option<ValueType>)>,
+    functions: Vec<Func>,
+    descriptors: Vec<(String, Vec<u32>)>,
+    exports: Vec<(String, u32)>,
+    data: Vec<(i32, Vec<u8>)>,
+}
+
+impl Module {
+    /// Creates a module with just the `__wbindgen_describe` import, a page of
+    /// memory and a stack pointer global.
+    pub fn new() -> Module {
+        let mut m = Module {
+            program: shared::Program {
+                exports: Vec::new(),
+                enums: Vec::new(),
+                imports: Vec::new(),
+                structs: Vec::new(),
+                version: shared::version(),
+                schema_version: shared::SCHEMA_VERSION.to_string(),
+            },
+            imports: Vec::new(),
+            functions: Vec::new(),
+            descriptors: Vec::new(),
+            exports: Vec::new(),
+            data: Vec::new(),
+        };
+        m.intrinsic("__wbindgen_describe", &[I], None);
+        m
+    }
+
+    /// Imports `name` from the placeholder module, returning the index of the
+    /// function. All imports have to be added before any function.
+    pub fn intrinsic(
+        &mut self,
+        name: &str,
+        params: &[ValueType],
+        result: Option<ValueType>,
+    ) -> u32 {
+        assert!(self.functions.is_empty(), "imports must be added before functions");
+        self.imports.push((name.to_string(), params.to_vec(), result));
+        self.imports.len() as u32 - 1
+    }
+
+    /// Adds `#[wasm_bindgen] extern { fn name(...); }`, imported from the
+    /// global scope, returning the index of the function.
+    pub fn import(
+        &mut self,
+        name: &str,
+        descriptor: &[u32],
+        params: &[ValueType],
+        result: Option<ValueType>,
+    ) -> u32 {
+        let shim = format!("__wbg_{}", name);
+        self.program.imports.push(shared::Import {
+            module: None,
+            js_namespace: None,
+            kind: shared::ImportKind::Function(shared::ImportFunction {
+                shim: shim.clone(),
+                catch: false,
+                variadic: false,
+                slices: shared::SliceMode::View,
+                method: None,
+                structural: false,
+                function: shared::Function {
+                    name: name.to_string(),
+                    location: None,
+                },
+            }),
+        });
+        self.descriptors.push((format!("__wbindgen_describe_{}", shim), descriptor.to_vec()));
+        self.intrinsic(&shim, params, result)
+    }
+
+    /// Changes how slices are passed to the import added last.
+    pub fn slices(&mut self, mode: shared::SliceMode) {
+        match &mut self.program.imports.last_mut().unwrap().kind {
+            shared::ImportKind::Function(f) => f.slices = mode,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Adds `#[wasm_bindgen] pub fn name(...)`, returning the index of the
+    /// function.
+    pub fn export(
+        &mut self,
+        name: &str,
+        descriptor: &[u32],
+        params: &[ValueType],
+        result: Option<ValueType>,
+        body: Vec<Instruction>,
+    ) -> u32 {
+        self.program.exports.push(shared::Export {
+            class: None,
+            method: false,
+            consumed: false,
+            is_constructor: false,
+            function: shared::Function {
+                name: name.to_string(),
+                location: None,
+            },
+            comments: Vec::new(),
+        });
+        self.descriptors.push((format!("__wbindgen_describe_{}", name), descriptor.to_vec()));
+        let idx = self.function(params, result, body);
+        self.export_function(name, idx);
+        idx
+    }
+
+    /// Adds `#[wasm_bindgen] pub struct name;`, whose `free` function does
+    /// nothing.
+    pub fn class(&mut self, name: &str) {
+        self.program.structs.push(shared::Struct {
+            name: name.to_string(),
+            fields: Vec::new(),
+            comments: Vec::new(),
+        });
+        let free = self.function(&[I], None, vec![]);
+        self.export_function(&shared::free_function(name), free);
+    }
+
+    /// Adds the method `name` to `impl class`, returning the index of the
+    /// function. Methods taking `self` get the pointer as their first wasm
+    /// parameter, which isn't part of the descriptor.
+    pub fn method(
+        &mut self,
+        class: &str,
+        name: &str,
+        kind: MethodKind,
+        descriptor: &[u32],
+        params: &[ValueType],
+        result: Option<ValueType>,
+        body: Vec<Instruction>,
+    ) -> u32 {
+        let (method, consumed, is_constructor) = match kind {
+            MethodKind::Constructor => (false, false, true),
+            MethodKind::Static => (false, false, false),
+            MethodKind::Ref => (true, false, false),
+            MethodKind::Consumed => (true, true, false),
+        };
+        self.program.exports.push(shared::Export {
+            class: Some(class.to_string()),
+            method,
+            consumed,
+            is_constructor,
+            function: shared::Function {
+                name: name.to_string(),
+                location: None,
+            },
+            comments: Vec::new(),
+        });
+        let wasm_name = shared::struct_function_export_name(class, name);
+        self.descriptors.push((format!("__wbindgen_describe_{}", wasm_name), descriptor.to_vec()));
+        let idx = self.function(params, result, body);
+        self.export_function(&wasm_name, idx);
+        idx
+    }
+
+    /// Gives access to the `#[wasm_bindgen]` program, for anything the other
+    /// methods don't cover.
+    pub fn program(&mut self) -> &mut shared::Program {
+        &mut self.program
+    }
+
+    /// Adds the describe shim `__wbindgen_describe_{name}`, like the one the
+    /// macro emits for a struct field's getter.
+    pub fn describe(&mut self, name: &str, descriptor: &[u32]) {
+        self.descriptors.push((format!("__wbindgen_describe_{}", name), descriptor.to_vec()));
+    }
+
+    /// Records where the export added last is defined in the Rust source.
+    pub fn location(&mut self, file: &str, line: u32, column: u32) {
+        self.program.exports.last_mut().unwrap().function.location = Some(shared::Location {
+            file: file.to_string(),
+            line,
+            column,
+        });
+    }
+
+    /// Adds a function which isn't exported, returning its index. The body
+    /// doesn't need the final `end`.
+    pub fn function(
+        &mut self,
+        params: &[ValueType],
+        result: Option<ValueType>,
+        body: Vec<Instruction>,
+    ) -> u32 {
+        self.function_with_locals(params, result, &[], body)
+    }
+
+    pub fn function_with_locals(
+        &mut self,
+        params: &[ValueType],
+        result: Option<ValueType>,
+        locals: &[ValueType],
+        mut body: Vec<Instruction>,
+    ) -> u32 {
+        body.push(Instruction::End);
+        self.functions.push(Func {
+            params: params.to_vec(),
+            result,
+            locals: locals.to_vec(),
+            body,
+        });
+        (self.imports.len() + self.functions.len()) as u32 - 1
+    }
+
+    /// Exports the function `idx` as `name`, without any bindings.
+    pub fn export_function(&mut self, name: &str, idx: u32) {
+        self.exports.push((name.to_string(), idx));
+    }
+
+    pub fn data(&mut self, offset: i32, bytes: &[u8]) {
+        self.data.push((offset, bytes.to_vec()));
+    }
+
+    /// Adds `__wbindgen_malloc`, `__wbindgen_realloc` and `__wbindgen_free`
+    /// as a bump allocator starting at `HEAP_BASE + 1024`.
+    pub fn allocator(&mut self) {
+        use self::Instruction::*;
+        // The next free byte is kept in global 1.
+        let malloc = self.function(&[I], Some(I), vec![
+            GetGlobal(1),
+            GetGlobal(1),
+            GetLocal(0),
+            I32Add,
+            SetGlobal(1),
+        ]);
+        self.export_function("__wbindgen_malloc", malloc);
+        // Copying isn't needed as nothing else is allocated while JS reallocs.
+        let realloc = self.function(&[I, I, I], Some(I), vec![
+            GetGlobal(1),
+            GetLocal(2),
+            GetLocal(1),
+            I32Sub,
+            I32Add,
+            SetGlobal(1),
+            GetLocal(0),
+        ]);
+        self.export_function("__wbindgen_realloc", realloc);
+        let free = self.function(&[I, I], None, vec![]);
+        self.export_function("__wbindgen_free", free);
+    }
+
+    /// Adds `__wbindgen_anyref_table_alloc` and `__wbindgen_anyref_table_dealloc`
+    /// as in the `wasm-bindgen` crate, except that slots are never reused. The
+    /// slot freed last is written to `ANYREF_FREED`. This uses the
+    /// `__wbindgen_anyref_table_grow` and `__wbindgen_anyref_table_set_null`
+    /// intrinsics `grow` and `set_null`.
+    pub fn anyref_allocator(&mut self, grow: u32, set_null: u32) {
+        use self::Instruction::*;
+        self.data(ANYREF_NEXT, &[8, 0, 0, 0]);
+        self.data(ANYREF_SIZE, &[8, 0, 0, 0]);
+        let alloc = self.function(&[], Some(I), vec![
+            I32Const(ANYREF_NEXT),
+            I32Load(2, 0),
+            I32Const(ANYREF_SIZE),
+            I32Load(2, 0),
+            I32Eq,
+            If(BlockType::NoResult),
+            I32Const(128),
+            Call(grow),
+            Drop,
+            I32Const(ANYREF_SIZE),
+            I32Const(ANYREF_SIZE),
+            I32Load(2, 0),
+            I32Const(128),
+            I32Add,
+            I32Store(2, 0),
+            End,
+            I32Const(ANYREF_NEXT),
+            I32Load(2, 0),
+            I32Const(ANYREF_NEXT),
+            I32Const(ANYREF_NEXT),
+            I32Load(2, 0),
+            I32Const(1),
+            I32Add,
+            I32Store(2, 0),
+        ]);
+        self.export_function("__wbindgen_anyref_table_alloc", alloc);
+        let dealloc = self.function(&[I], None, vec![
+            GetLocal(0),
+            I32Const(8),
+            I32LtU,
+            If(BlockType::NoResult),
+            Return,
+            End,
+            I32Const(ANYREF_FREED),
+            GetLocal(0),
+            I32Store(2, 0),
+            GetLocal(0),
+            Call(set_null),
+        ]);
+        self.export_function("__wbindgen_anyref_table_dealloc", dealloc);
+    }
+
+    pub fn build(mut self) -> elements::Module {
+        let mut types = Vec::new();
+        let mut type_of = |params: &[ValueType], result: Option<ValueType>| {
+            let ty = Type::Function(FunctionType::new(params.to_vec(), result));
+            match types.iter().position(|t| *t == ty) {
+                Some(i) => i as u32,
+                None => {
+                    types.push(ty);
+                    types.len() as u32 - 1
+                }
+            }
+        };
+
+        let mut imports = Vec::new();
+        for (name, params, result) in self.imports.iter() {
+            let ty = type_of(params, *result);
+            imports.push(ImportEntry::new(
+                "__wbindgen_placeholder__".to_string(),
+                name.clone(),
+                External::Function(ty),
+            ));
+        }
+
+        // Each descriptor is a function calling `__wbindgen_describe` with
+        // every word in turn.
+        for (name, words) in self.descriptors.clone() {
+            let mut body = Vec::new();
+            for word in words {
+                body.push(Instruction::I32Const(word as i32));
+                body.push(Instruction::Call(0));
+            }
+            let idx = self.function(&[], None, body);
+            self.export_function(&name, idx);
+        }
+
+        let mut functions = Vec::new();
+        let mut bodies = Vec::new();
+        for f in self.functions.iter() {
+            functions.push(elements::Func::new(type_of(&f.params, f.result)));
+            let locals = f.locals.iter().map(|t| Local::new(1, *t)).collect();
+            bodies.push(FuncBody::new(locals, Instructions::new(f.body.clone())));
+        }
+
+        let mut exports = vec![ExportEntry::new("memory".to_string(), Internal::Memory(0))];
+        for (name, idx) in self.exports.iter() {
+            exports.push(ExportEntry::new(name.clone(), Internal::Function(*idx)));
+        }
+
+        let globals = vec![
+            // The stack pointer, growing down from `HEAP_BASE`.
+            GlobalEntry::new(
+                GlobalType::new(ValueType::I32, true),
+                InitExpr::new(vec![Instruction::I32Const(HEAP_BASE), Instruction::End]),
+            ),
+            // The next free byte for `Module::allocator`.
+            GlobalEntry::new(
+                GlobalType::new(ValueType::I32, true),
+                InitExpr::new(vec![Instruction::I32Const(HEAP_BASE + 1024), Instruction::End]),
+            ),
+        ];
+
+        let data = self
+            .data
+            .iter()
+            .map(|(offset, bytes)| {
+                DataSegment::new(
+                    0,
+                    InitExpr::new(vec![Instruction::I32Const(*offset), Instruction::End]),
+                    bytes.clone(),
+                )
+            })
+            .collect();
+
+        let json = serde_json::to_vec(&self.program).unwrap();
+        let mut payload = (json.len() as u32).to_le_bytes().to_vec();
+        payload.extend(json);
+        let mut custom = CustomSection::default();
+        *custom.name_mut() = "__wasm_bindgen_unstable".to_string();
+        *custom.payload_mut() = payload;
+
+        elements::Module::new(vec![
+            Section::Type(TypeSection::with_types(types)),
+            Section::Import(ImportSection::with_entries(imports)),
+            Section::Function(FunctionSection::with_entries(functions)),
+            Section::Memory(MemorySection::with_entries(vec![
+                MemoryType::new(1, None),
+            ])),
+            Section::Global(GlobalSection::with_entries(globals)),
+            Section::Export(ExportSection::with_entries(exports)),
+            Section::Code(CodeSection::with_bodies(bodies)),
+            Section::Data(DataSection::with_entries(data)),
+            Section::Custom(custom),
+        ])
+    }
+
+    /// Builds the module and sets it as the input of a new `Bindgen`, named
+    /// `demo`.
+    pub fn bindgen(self) -> Bindgen {
+        let mut b = Bindgen::new();
+        b.input_module("demo", self.build(), |m| parity_wasm::serialize(m).unwrap());
+        b
+    }
+}
+
+/// Runs `b` and writes the output to a new temporary directory.
+pub fn generate(b: &mut Bindgen) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    b.generate(dir.path()).unwrap();
+    dir
+}
+
+/// Runs `script` with node in `dir`, failing if it exits unsuccessfully, and
+/// returns what it printed. Scripts named `*.mjs` are loaded as ES modules.
+pub fn node(dir: &Path, name: &str, script: &str) -> String {
+    let path = dir.join(name);
+    fs::write(&path, script).unwrap();
+    let output = Command::new("node")
+        .arg(&path)
+        .current_dir(dir)
+        .output()
+        .expect("failed to run node, is it installed?");
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        panic!("node failed: {}\nstdout:\n{}\nstderr:\n{}", output.status, stdout, stderr);
+    }
+    stdout
+}
+
+/// Generates bindings for node and runs `script` against them, with the
+/// generated module available as `wasm`.
+pub fn run_node(b: &mut Bindgen, script: &str) -> String {
+    b.nodejs(true);
+    let dir = generate(b);
+    node(dir.path(), "test.js", &format!("const wasm = require('./demo');\n{}", script))
+}
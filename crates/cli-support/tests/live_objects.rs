@@ -0,0 +1,219 @@
+extern crate parity_wasm;
+extern crate serde_json;
+extern crate tempfile;
+extern crate wasm_bindgen_cli_support;
+extern crate wasm_bindgen_shared;
+
+mod common;
+
+use common::*;
+use parity_wasm::elements::Instruction::*;
+
+/// Address `keep` and `keep_str` store the value they keep at.
+const KEPT: i32 = 64;
+
+/// Address of the string `keep_str` keeps.
+const STR: i32 = 128;
+
+/// Descriptor words of the exported struct `Foo`.
+const FOO: &[u32] = &[RUST_STRUCT, 3, 'F' as u32, 'o' as u32, 'o' as u32];
+
+fn descriptor(parts: &[&[u32]]) -> Vec<u32> {
+    parts.iter().flat_map(|p| p.iter().cloned()).collect()
+}
+
+/// ```ignore
+/// #[wasm_bindgen]
+/// pub struct Foo { .. }
+///
+/// #[wasm_bindgen]
+/// impl Foo {
+///     #[wasm_bindgen(constructor)]
+///     pub fn new() -> Foo { .. }
+///     pub fn consume(self) {}
+/// }
+///
+/// #[wasm_bindgen]
+/// pub fn make_foo() -> Foo { .. }
+/// #[wasm_bindgen]
+/// pub fn take_foo(foo: Foo) {}
+/// #[wasm_bindgen]
+/// pub fn keep(x: JsValue) { .. }
+/// #[wasm_bindgen]
+/// pub fn keep_str() { /* keeps JsValue::from("hi") */ }
+/// #[wasm_bindgen]
+/// pub fn drop_kept() { .. }
+/// ```
+///
+/// `Foo::new` always returns the pointer 16 and `make_foo` the pointer 32.
+fn module(reference_types: bool) -> Module {
+    let mut m = Module::new();
+    let drop_ref = m.intrinsic("__wbindgen_object_drop_ref", &[I], None);
+    let string_new = m.intrinsic("__wbindgen_string_new", &[I, I], Some(I));
+    if reference_types {
+        let grow = m.intrinsic("__wbindgen_anyref_table_grow", &[I], Some(I));
+        let set_null = m.intrinsic("__wbindgen_anyref_table_set_null", &[I], None);
+        m.anyref_allocator(grow, set_null);
+    }
+    m.class("Foo");
+    m.method(
+        "Foo",
+        "new",
+        MethodKind::Constructor,
+        &descriptor(&[&[FUNCTION, 0], FOO]),
+        &[],
+        Some(I),
+        vec![I32Const(16)],
+    );
+    m.method(
+        "Foo",
+        "consume",
+        MethodKind::Consumed,
+        &[FUNCTION, 0, UNIT],
+        &[I],
+        None,
+        vec![],
+    );
+    m.export("make_foo", &descriptor(&[&[FUNCTION, 0], FOO]), &[], Some(I), vec![
+        I32Const(32),
+    ]);
+    m.export("take_foo", &descriptor(&[&[FUNCTION, 1], FOO, &[UNIT]]), &[I], None, vec![]);
+    m.export("keep", &[FUNCTION, 1, ANYREF, UNIT], &[I], None, vec![
+        I32Const(KEPT),
+        GetLocal(0),
+        I32Store(2, 0),
+    ]);
+    m.data(STR, b"hi");
+    m.export("keep_str", &[FUNCTION, 0, UNIT], &[], None, vec![
+        I32Const(KEPT),
+        I32Const(STR),
+        I32Const(2),
+        Call(string_new),
+        I32Store(2, 0),
+    ]);
+    m.export("drop_kept", &[FUNCTION, 0, UNIT], &[], None, vec![
+        I32Const(KEPT),
+        I32Load(2, 0),
+        Call(drop_ref),
+    ]);
+    m
+}
+
+#[test]
+fn tracks_class_instances() {
+    let mut b = module(false).bindgen();
+    b.debug(true);
+    run_node(
+        &mut b,
+        "
+        const assert = require('assert');
+        const classes = () => wasm.__wbindgen_live_objects().classes
+            .map(c => [c.class, c.ptr, c.site]);
+
+        assert.deepStrictEqual(classes(), []);
+        const a = new wasm.Foo();
+        const b = wasm.make_foo();
+        assert.deepStrictEqual(classes(), [
+            ['Foo', 16, 'export Foo.constructor'],
+            ['Foo', 32, 'export make_foo'],
+        ]);
+
+        b.free();
+        assert.deepStrictEqual(classes(), [['Foo', 16, 'export Foo.constructor']]);
+        a.consume();
+        assert.deepStrictEqual(classes(), []);
+
+        wasm.take_foo(wasm.make_foo());
+        assert.deepStrictEqual(classes(), []);
+        ",
+    );
+}
+
+#[test]
+fn tracking_does_not_keep_instances_alive() {
+    let mut b = module(false).bindgen();
+    b.debug(true);
+    run_node(
+        &mut b,
+        "
+        const assert = require('assert');
+        require('v8').setFlagsFromString('--expose-gc');
+        const gc = require('vm').runInNewContext('gc');
+
+        // Leak an instance without freeing it.
+        const weak = (() => new WeakRef(wasm.make_foo()))();
+        setTimeout(() => {
+            gc();
+            assert.strictEqual(weak.deref(), undefined);
+            const classes = wasm.__wbindgen_live_objects().classes;
+            assert.deepStrictEqual(classes.map(c => c.ptr), [32]);
+        }, 0);
+        ",
+    );
+}
+
+#[test]
+fn tracks_heap_objects() {
+    let mut b = module(false).bindgen();
+    b.debug(true);
+    run_node(
+        &mut b,
+        "
+        const assert = require('assert');
+        const heap = () => wasm.__wbindgen_live_objects().heap;
+
+        assert.deepStrictEqual(heap(), []);
+        const obj = {};
+        wasm.keep(obj);
+        const live = heap();
+        assert.strictEqual(live.length, 1);
+        assert.strictEqual(live[0].site, 'export keep');
+        assert.strictEqual(live[0].value, obj);
+
+        wasm.drop_kept();
+        assert.deepStrictEqual(heap(), []);
+        ",
+    );
+}
+
+#[test]
+fn tracks_table_slots_with_reference_types() {
+    let mut b = module(true).bindgen();
+    b.debug(true).reference_types(true);
+    run_node(
+        &mut b,
+        "
+        const assert = require('assert');
+        const heap = () => wasm.__wbindgen_live_objects().heap;
+
+        assert.deepStrictEqual(heap(), []);
+        const obj = {};
+        wasm.keep(obj);
+        const live = heap();
+        assert.strictEqual(live.length, 1);
+        assert(live[0].index >= 8);
+        assert.strictEqual(live[0].value, obj);
+        // Arguments are put in the table by wasm, so there's no site.
+        assert.strictEqual(live[0].site, null);
+        wasm.drop_kept();
+        assert.deepStrictEqual(heap(), []);
+
+        wasm.keep_str();
+        assert.deepStrictEqual(heap().map(h => [h.site, h.value]), [['export keep_str', 'hi']]);
+        wasm.drop_kept();
+        assert.deepStrictEqual(heap(), []);
+
+        const a = new wasm.Foo();
+        assert.deepStrictEqual(wasm.__wbindgen_live_objects().classes.map(c => c.ptr), [16]);
+        a.free();
+        ",
+    );
+}
+
+#[test]
+fn only_with_debug() {
+    let mut b = module(false).bindgen();
+    let output = b.nodejs(true).generate_output().unwrap();
+    assert!(!output.js().contains("__wbindgen_live_objects"));
+    assert!(!output.js().contains("trackLiveObject"));
+}
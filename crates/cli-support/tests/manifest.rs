@@ -0,0 +1,248 @@
+extern crate parity_wasm;
+#[macro_use]
+extern crate serde_json;
+extern crate tempfile;
+extern crate wasm_bindgen_cli_support;
+extern crate wasm_bindgen_shared;
+
+mod common;
+
+use common::*;
+use parity_wasm::elements::Instruction::*;
+use serde_json::Value;
+use wasm_bindgen_shared as shared;
+
+const FOO: &[u32] = &[RUST_STRUCT, 3, 'F' as u32, 'o' as u32, 'o' as u32];
+
+fn descriptor(parts: &[&[u32]]) -> Vec<u32> {
+    parts.iter().flat_map(|p| p.iter().cloned()).collect()
+}
+
+fn method_import(class: &str, kind: shared::MethodKind) -> Option<shared::MethodData> {
+    Some(shared::MethodData {
+        class: class.to_string(),
+        kind,
+    })
+}
+
+fn operation(is_static: bool, kind: shared::OperationKind) -> shared::MethodKind {
+    shared::MethodKind::Operation(shared::Operation { is_static, kind })
+}
+
+/// ```ignore
+/// /// Adds two numbers.
+/// #[wasm_bindgen]
+/// pub fn add(a: u32, b: u32) -> u32 { a + b }
+///
+/// #[wasm_bindgen]
+/// pub struct Foo { pub x: u32 }
+///
+/// #[wasm_bindgen]
+/// impl Foo {
+///     #[wasm_bindgen(constructor)]
+///     pub fn new() -> Foo { .. }
+///     pub fn get(&self) -> u32 { .. }
+///     pub fn into_inner(self) -> u32 { .. }
+///     pub fn zero() -> u32 { 0 }
+/// }
+///
+/// #[wasm_bindgen]
+/// pub enum Color { Red, Green }
+///
+/// #[wasm_bindgen]
+/// extern {
+///     #[wasm_bindgen(js_namespace = console)]
+///     fn log(s: &str);
+///     type Element;
+///     #[wasm_bindgen(method)]
+///     fn append(this: &Element, other: &Element);
+///     #[wasm_bindgen(method, getter = id)]
+///     fn id(this: &Element) -> String;
+///     #[wasm_bindgen(constructor)]
+///     fn new() -> Element;
+///     #[wasm_bindgen(static_method_of = Element)]
+///     fn count() -> u32;
+/// }
+/// ```
+fn module() -> Module {
+    let mut m = Module::new();
+    m.import("log", &[FUNCTION, 1, REF, STRING, UNIT], &[I, I], None);
+    m.program().imports.last_mut().unwrap().js_namespace = Some("console".to_string());
+    let imports = vec![
+        ("append", operation(false, shared::OperationKind::Regular)),
+        ("id", operation(false, shared::OperationKind::Getter("id".to_string()))),
+        ("new", shared::MethodKind::Constructor),
+        ("count", operation(true, shared::OperationKind::Regular)),
+    ];
+    for (name, kind) in imports {
+        m.import(name, &[FUNCTION, 0, UNIT], &[], None);
+        if let shared::ImportKind::Function(f) = &mut m.program().imports.last_mut().unwrap().kind {
+            f.method = method_import("Element", kind);
+        }
+    }
+    m.program().imports.push(shared::Import {
+        module: None,
+        js_namespace: None,
+        kind: shared::ImportKind::Type(shared::ImportType {
+            name: "Element".to_string(),
+            instanceof_shim: "__wbg_instanceof_Element".to_string(),
+        }),
+    });
+
+    m.export("add", &[FUNCTION, 2, U32, U32, U32], &[I, I], Some(I), vec![
+        GetLocal(0),
+        GetLocal(1),
+        I32Add,
+    ]);
+    m.program().exports.last_mut().unwrap().comments = vec![" Adds two numbers.".to_string()];
+
+    m.class("Foo");
+    m.program().structs.last_mut().unwrap().fields.push(shared::StructField {
+        name: "x".to_string(),
+        readonly: false,
+        comments: Vec::new(),
+    });
+    let getter = shared::struct_field_get("Foo", "x");
+    m.describe(&getter, &[U32]);
+    let get = m.function(&[I], Some(I), vec![I32Const(0)]);
+    m.export_function(&getter, get);
+    let set = m.function(&[I, I], None, vec![]);
+    m.export_function(&shared::struct_field_set("Foo", "x"), set);
+
+    let new = descriptor(&[&[FUNCTION, 0], FOO]);
+    m.method("Foo", "new", MethodKind::Constructor, &new, &[], Some(I), vec![I32Const(8)]);
+    m.method("Foo", "get", MethodKind::Ref, &[FUNCTION, 0, U32], &[I], Some(I), vec![I32Const(0)]);
+    m.method("Foo", "into_inner", MethodKind::Consumed, &[FUNCTION, 0, U32], &[I], Some(I), vec![
+        I32Const(0),
+    ]);
+    m.method("Foo", "zero", MethodKind::Static, &[FUNCTION, 0, U32], &[], Some(I), vec![I32Const(0)]);
+
+    m.program().enums.push(shared::Enum {
+        name: "Color".to_string(),
+        variants: vec![
+            shared::EnumVariant {
+                name: "Red".to_string(),
+                value: 0,
+            },
+            shared::EnumVariant {
+                name: "Green".to_string(),
+                value: 1,
+            },
+        ],
+        comments: Vec::new(),
+    });
+    m
+}
+
+fn manifest(m: Module) -> Value {
+    let mut b = m.bindgen();
+    let output = b.nodejs(true).emit_manifest(true).generate_output().unwrap();
+    serde_json::from_str(output.manifest().unwrap()).unwrap()
+}
+
+#[test]
+fn describes_exports() {
+    let manifest = manifest(module());
+    assert_eq!(
+        manifest["functions"],
+        json!([{
+            "name": "add",
+            "signature": { "arguments": ["U32", "U32"], "ret": "U32" },
+            "comments": [" Adds two numbers."],
+        }])
+    );
+    assert_eq!(
+        manifest["classes"],
+        json!([{
+            "name": "Foo",
+            "fields": [{ "name": "x", "readonly": false, "type": "U32", "comments": [] }],
+            "methods": [
+                {
+                    "name": "new",
+                    "kind": "constructor",
+                    "signature": { "arguments": [], "ret": { "RustStruct": "Foo" } },
+                    "comments": [],
+                },
+                {
+                    "name": "get",
+                    "kind": "method",
+                    "signature": { "arguments": [], "ret": "U32" },
+                    "comments": [],
+                },
+                {
+                    "name": "into_inner",
+                    "kind": "consuming",
+                    "signature": { "arguments": [], "ret": "U32" },
+                    "comments": [],
+                },
+                {
+                    "name": "zero",
+                    "kind": "static",
+                    "signature": { "arguments": [], "ret": "U32" },
+                    "comments": [],
+                },
+            ],
+            "comments": [],
+        }])
+    );
+    assert_eq!(
+        manifest["enums"],
+        json!([{
+            "name": "Color",
+            "variants": [{ "name": "Red", "value": 0 }, { "name": "Green", "value": 1 }],
+            "comments": [],
+        }])
+    );
+    assert_eq!(manifest["version"], json!(shared::version()));
+}
+
+#[test]
+fn describes_imports() {
+    let manifest = manifest(module());
+    let imports = manifest["imports"].as_array().unwrap();
+    let targets = imports
+        .iter()
+        .map(|i| (i["kind"].as_str().unwrap(), i["target"].as_str().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        targets,
+        [
+            ("function", "console.log"),
+            ("function", "Element.prototype.append"),
+            ("function", "get Element.prototype.id"),
+            ("function", "new Element"),
+            ("function", "Element.count"),
+            ("type", "Element"),
+        ]
+    );
+    assert_eq!(
+        imports[0],
+        json!({
+            "module": null,
+            "js_namespace": "console",
+            "target": "console.log",
+            "kind": "function",
+            "name": "log",
+            "catch": false,
+            "variadic": false,
+            "structural": false,
+            "signature": { "arguments": [{ "Ref": "String" }], "ret": "Unit" },
+        })
+    );
+}
+
+#[test]
+fn only_with_emit_manifest() {
+    let mut b = module().bindgen();
+    let output = b.nodejs(true).generate_output().unwrap();
+    assert!(output.manifest().is_none());
+}
+
+#[test]
+fn rejects_exports_not_described_as_functions() {
+    let mut m = Module::new();
+    m.export("bad", &[U32], &[], Some(I), vec![I32Const(0)]);
+    let mut b = m.bindgen();
+    let err = b.nodejs(true).emit_manifest(true).generate_output().err().unwrap();
+    assert_eq!(err.to_string(), "`bad` is described as U32 rather than a function");
+}
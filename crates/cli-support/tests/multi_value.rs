@@ -0,0 +1,87 @@
+extern crate parity_wasm;
+extern crate serde_json;
+extern crate tempfile;
+extern crate wasm_bindgen_cli_support;
+extern crate wasm_bindgen_shared;
+
+mod common;
+
+use common::*;
+use parity_wasm::elements::Instruction::*;
+
+/// `pub fn greet() -> String`, returning "hello", and
+/// `pub fn twice(n: u8) -> Vec<u8>`, returning `[n, n]`, both written the way
+/// `rustc` does, through a return pointer.
+fn module() -> Module {
+    let mut m = Module::new();
+    m.allocator();
+    m.data(64, b"hello");
+    m.export("greet", &[FUNCTION, 0, STRING], &[I], None, vec![
+        GetLocal(0),
+        I32Const(64),
+        I32Store(2, 0),
+        GetLocal(0),
+        I32Const(5),
+        I32Store(2, 4),
+    ]);
+    m.export("twice", &[FUNCTION, 1, U8, VECTOR, U8], &[I, I], None, vec![
+        I32Const(96),
+        GetLocal(1),
+        I32Store8(0, 0),
+        I32Const(97),
+        GetLocal(1),
+        I32Store8(0, 0),
+        GetLocal(0),
+        I32Const(96),
+        I32Store(2, 0),
+        GetLocal(0),
+        I32Const(2),
+        I32Store(2, 4),
+    ]);
+    m
+}
+
+#[test]
+fn returns_pairs() {
+    let mut b = module().bindgen();
+    b.multi_value(true);
+    run_node(
+        &mut b,
+        "
+        const assert = require('assert');
+        const raw = require('./demo_bg');
+
+        // The exports themselves return `(i32, i32)`.
+        assert.strictEqual(raw.greet.length, 0);
+        assert.deepStrictEqual(raw.greet(), [64, 5]);
+        assert.strictEqual(raw.twice.length, 1);
+        assert.deepStrictEqual(raw.twice(7), [96, 2]);
+
+        // Many more calls than fit on the stack, so each has to give back
+        // the space it reserved.
+        for (let i = 0; i < 1000; i++) {
+            assert.strictEqual(wasm.greet(), 'hello');
+            assert.deepStrictEqual(wasm.twice(i & 0xff), new Uint8Array([i & 0xff, i & 0xff]));
+        }
+        ",
+    );
+}
+
+#[test]
+fn return_pointer_without_multi_value() {
+    // Results are written to the global argument buffer instead.
+    let mut m = module();
+    let global_argument_ptr = m.function(&[], Some(I), vec![I32Const(256)]);
+    m.export_function("__wbindgen_global_argument_ptr", global_argument_ptr);
+    let mut b = m.bindgen();
+    run_node(
+        &mut b,
+        "
+        const assert = require('assert');
+        const raw = require('./demo_bg');
+        assert.strictEqual(raw.greet.length, 1);
+        assert.strictEqual(wasm.greet(), 'hello');
+        assert.deepStrictEqual(wasm.twice(3), new Uint8Array([3, 3]));
+        ",
+    );
+}
@@ -0,0 +1,58 @@
+extern crate parity_wasm;
+extern crate serde_json;
+extern crate tempfile;
+extern crate wasm_bindgen_cli_support;
+extern crate wasm_bindgen_shared;
+
+mod common;
+
+use common::*;
+use parity_wasm::elements::Instruction::*;
+
+/// `pub fn boom()`, which panics with "oh no" at src/lib.rs:12:5 like
+/// `wasm_bindgen::set_panic_hook` would.
+fn module() -> Module {
+    let mut m = Module::new();
+    let panic = m.intrinsic("__wbindgen_panic", &[I, I, I, I, I, I], None);
+    m.data(8, b"oh no");
+    m.data(16, b"src/lib.rs");
+    m.export("boom", &[FUNCTION, 0, UNIT], &[], None, vec![
+        I32Const(8),
+        I32Const(5),
+        I32Const(16),
+        I32Const(10),
+        I32Const(12),
+        I32Const(5),
+        Call(panic),
+    ]);
+    m
+}
+
+const CALL_BOOM: &str = "
+    let error = null;
+    try {
+        wasm.boom();
+    } catch (e) {
+        error = e;
+    }
+    if (!(error instanceof Error)) throw new Error('not an Error: ' + error);
+    console.log(error.message);
+";
+
+#[test]
+fn throws_error() {
+    let out = run_node(&mut module().bindgen(), CALL_BOOM);
+    assert_eq!(out, "panicked at 'oh no', src/lib.rs:12:5\n");
+}
+
+#[test]
+fn debug_includes_rust_stack() {
+    let mut b = module().bindgen();
+    b.debug(true);
+    let out = run_node(&mut b, CALL_BOOM);
+    assert!(
+        out.starts_with("panicked at 'oh no', src/lib.rs:12:5\n\nRust stack:\n    at "),
+        "{}",
+        out
+    );
+}
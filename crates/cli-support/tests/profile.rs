@@ -0,0 +1,100 @@
+extern crate parity_wasm;
+extern crate serde_json;
+extern crate tempfile;
+extern crate wasm_bindgen_cli_support;
+extern crate wasm_bindgen_shared;
+
+mod common;
+
+use common::*;
+use parity_wasm::elements::Instruction::*;
+
+/// `pub fn add(a: u32, b: u32) -> u32`, which also passes `a + b` to an
+/// imported `log` function, and `pub fn idle() -> u32` which calls nothing.
+fn module() -> Module {
+    let mut m = Module::new();
+    let log = m.import("log", &[FUNCTION, 1, U32, UNIT], &[I], None);
+    m.export("add", &[FUNCTION, 2, U32, U32, U32], &[I, I], Some(I), vec![
+        GetLocal(0),
+        GetLocal(1),
+        I32Add,
+        Call(log),
+        GetLocal(0),
+        GetLocal(1),
+        I32Add,
+    ]);
+    m.export("idle", &[FUNCTION, 0, U32], &[], Some(I), vec![I32Const(0)]);
+    m
+}
+
+#[test]
+fn counts_and_times_calls() {
+    let mut b = module().bindgen();
+    b.profile_boundary(true);
+    run_node(
+        &mut b,
+        "
+        const assert = require('assert');
+        const { performance } = require('perf_hooks');
+        // `log` takes at least 5ms.
+        globalThis.log = () => {
+            const start = performance.now();
+            while (performance.now() - start < 5) {}
+        };
+
+        assert.deepStrictEqual(wasm.__wbindgen_profile_report(), { exports: {}, imports: {} });
+        for (let i = 0; i < 3; i++) {
+            assert.strictEqual(wasm.add(i, 1), i + 1);
+        }
+        wasm.idle();
+
+        const report = wasm.__wbindgen_profile_report();
+        assert.deepStrictEqual(Object.keys(report.exports).sort(), ['add', 'idle']);
+        assert.deepStrictEqual(Object.keys(report.imports), ['log']);
+        const { add, idle } = report.exports;
+        const { log } = report.imports;
+        assert.strictEqual(add.calls, 3);
+        assert.strictEqual(idle.calls, 1);
+        assert.strictEqual(log.calls, 3);
+        for (const stats of [add, idle, log]) {
+            assert.strictEqual(stats.meanMs, stats.totalMs / stats.calls);
+        }
+        assert(log.totalMs >= 15);
+        // An export's time includes the imports it calls.
+        assert(add.totalMs >= log.totalMs);
+        ",
+    );
+}
+
+#[test]
+fn counts_calls_which_throw() {
+    let mut b = module().bindgen();
+    b.profile_boundary(true);
+    run_node(
+        &mut b,
+        "
+        const assert = require('assert');
+        globalThis.log = () => { throw new Error('oops'); };
+        assert.throws(() => wasm.add(1, 2), /oops/);
+        const report = wasm.__wbindgen_profile_report();
+        assert.strictEqual(report.exports.add.calls, 1);
+        assert.strictEqual(report.imports.log.calls, 1);
+        ",
+    );
+}
+
+#[test]
+fn only_with_profile_boundary() {
+    let mut b = module().bindgen();
+    b.nodejs(true).typescript(true);
+    let output = b.generate_output().unwrap();
+    assert!(!output.js().contains("profile"));
+
+    let mut b = module().bindgen();
+    b.nodejs(true).typescript(true).profile_boundary(true);
+    let output = b.generate_output().unwrap();
+    assert!(output
+        .typescript()
+        .unwrap()
+        .contains("export function __wbindgen_profile_report(): any;"));
+}
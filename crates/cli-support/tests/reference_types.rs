@@ -0,0 +1,117 @@
+extern crate parity_wasm;
+extern crate serde_json;
+extern crate tempfile;
+extern crate wasm_bindgen_cli_support;
+extern crate wasm_bindgen_shared;
+
+mod common;
+
+use common::*;
+use parity_wasm::elements::Instruction::*;
+
+/// A module using `JsValue`s, along with the table allocator of the
+/// `wasm-bindgen` crate, which here never reuses slots:
+///
+/// ```ignore
+/// #[wasm_bindgen]
+/// extern { fn ident(x: &JsValue) -> JsValue; }
+///
+/// #[wasm_bindgen]
+/// pub fn roundtrip(x: JsValue) -> JsValue { ident(&x) }
+/// #[wasm_bindgen]
+/// pub fn clone_it(x: &JsValue) -> JsValue { x.clone() }
+/// #[wasm_bindgen]
+/// pub fn drop_it(x: JsValue) {}
+/// #[wasm_bindgen]
+/// pub fn is_undef(x: &JsValue) -> bool { x.is_undefined() }
+/// #[wasm_bindgen]
+/// pub fn undef() -> JsValue { JsValue::UNDEFINED }
+/// ```
+fn module() -> Module {
+    let mut m = Module::new();
+    let clone_ref = m.intrinsic("__wbindgen_object_clone_ref", &[I], Some(I));
+    let drop_ref = m.intrinsic("__wbindgen_object_drop_ref", &[I], None);
+    let grow = m.intrinsic("__wbindgen_anyref_table_grow", &[I], Some(I));
+    let set_null = m.intrinsic("__wbindgen_anyref_table_set_null", &[I], None);
+    let is_undefined = m.intrinsic("__wbindgen_is_undefined", &[I], Some(I));
+    let ident = m.import("ident", &[FUNCTION, 1, REF, ANYREF, ANYREF], &[I], Some(I));
+    m.anyref_allocator(grow, set_null);
+
+    m.export("roundtrip", &[FUNCTION, 1, ANYREF, ANYREF], &[I], Some(I), vec![
+        GetLocal(0),
+        Call(ident),
+        GetLocal(0),
+        Call(drop_ref),
+    ]);
+    m.export("clone_it", &[FUNCTION, 1, REF, ANYREF, ANYREF], &[I], Some(I), vec![
+        GetLocal(0),
+        Call(clone_ref),
+    ]);
+    m.export("drop_it", &[FUNCTION, 1, ANYREF, UNIT], &[I], None, vec![
+        GetLocal(0),
+        Call(drop_ref),
+    ]);
+    m.export("is_undef", &[FUNCTION, 1, REF, ANYREF, BOOLEAN], &[I], Some(I), vec![
+        GetLocal(0),
+        Call(is_undefined),
+    ]);
+    m.export("undef", &[FUNCTION, 0, ANYREF], &[], Some(I), vec![I32Const(0)]);
+    m.export("last_freed", &[FUNCTION, 0, U32], &[], Some(I), vec![
+        I32Const(ANYREF_FREED),
+        I32Load(2, 0),
+    ]);
+    m
+}
+
+#[test]
+fn round_trips_values_through_the_table() {
+    let mut b = module().bindgen();
+    b.reference_types(true);
+    run_node(
+        &mut b,
+        "
+        const assert = require('assert');
+        const table = require('./demo_bg').__wbindgen_anyref_table;
+        assert(table instanceof WebAssembly.Table);
+        const seen = [];
+        globalThis.ident = x => {
+            seen.push(x);
+            return x;
+        };
+
+        const obj = { a: 1 };
+        assert.strictEqual(wasm.roundtrip(obj), obj);
+        assert.deepStrictEqual(seen, [obj]);
+        assert.strictEqual(wasm.roundtrip(null), null);
+        assert.strictEqual(wasm.roundtrip('s'), 's');
+
+        assert.strictEqual(wasm.is_undef(undefined), true);
+        assert.strictEqual(wasm.is_undef(obj), false);
+        assert.strictEqual(wasm.undef(), undefined);
+
+        // Dropping a value clears its slot.
+        const other = {};
+        wasm.drop_it(other);
+        assert(wasm.last_freed() >= 8);
+        assert.strictEqual(table.get(wasm.last_freed()), null);
+
+        // Clones are new slots pointing at the same value, and enough of
+        // them grow the table.
+        const length = table.length;
+        for (let i = 0; i < 200; i++) {
+            assert.strictEqual(wasm.clone_it(obj), obj);
+        }
+        assert(table.length > length);
+        for (let i = 0; i < table.length; i++) {
+            assert.notStrictEqual(table.get(i), obj);
+        }
+        ",
+    );
+}
+
+#[test]
+fn without_reference_types() {
+    let mut b = module().bindgen();
+    let output = b.nodejs(true).generate_output().unwrap();
+    assert!(!output.js().contains("__wbindgen_anyref_table"));
+}
@@ -0,0 +1,121 @@
+extern crate parity_wasm;
+extern crate serde_json;
+extern crate tempfile;
+extern crate wasm_bindgen_cli_support;
+extern crate wasm_bindgen_shared;
+
+mod common;
+
+use common::*;
+use parity_wasm::elements::Instruction::*;
+use wasm_bindgen_shared::SliceMode;
+
+/// Passes the 4 bytes at 64 to `#[wasm_bindgen] extern { fn fill(buf: &mut
+/// [u8]); }` from `pub fn run()`, along with exports to grow memory and read
+/// the first byte back.
+fn module(slices: SliceMode) -> Module {
+    let mut m = Module::new();
+    let fill = m.import("fill", &[FUNCTION, 1, REFMUT, SLICE, U8, UNIT], &[I, I], None);
+    m.slices(slices);
+    m.export("run", &[FUNCTION, 0, UNIT], &[], None, vec![
+        I32Const(64),
+        I32Const(4),
+        Call(fill),
+    ]);
+    m.export("grow", &[FUNCTION, 0, UNIT], &[], None, vec![
+        I32Const(1),
+        GrowMemory(0),
+        Drop,
+    ]);
+    m.export("first", &[FUNCTION, 0, U8], &[], Some(I), vec![
+        I32Const(64),
+        I32Load8U(0, 0),
+    ]);
+    m
+}
+
+#[test]
+fn debug_reports_detached_views() {
+    let mut b = module(SliceMode::View).bindgen();
+    b.debug(true);
+    run_node(
+        &mut b,
+        "
+        const assert = require('assert');
+        const detached = /wasm memory grew while a slice was passed to JS/;
+
+        // The view is still a real typed array.
+        globalThis.fill = buf => {
+            assert(buf instanceof Uint8Array);
+            assert(ArrayBuffer.isView(buf));
+            assert.strictEqual(buf.length, 4);
+            buf.fill(1);
+        };
+        wasm.run();
+        assert.strictEqual(wasm.first(), 1);
+
+        // Memory growing while JS has the view is reported, right away by its
+        // methods and otherwise once the import returns.
+        globalThis.fill = buf => {
+            wasm.grow();
+            buf.fill(2);
+        };
+        assert.throws(() => wasm.run(), detached);
+        globalThis.fill = buf => {
+            wasm.grow();
+            for (let i = 0; i < buf.length; i++) {
+                buf[i] = 2;
+            }
+        };
+        assert.throws(() => wasm.run(), detached);
+        globalThis.fill = buf => {
+            wasm.grow();
+            buf[0] = 2;
+            assert.strictEqual(buf[0], undefined);
+        };
+        assert.throws(() => wasm.run(), detached);
+        assert.strictEqual(wasm.first(), 1);
+
+        // Even once JS is done with it, as indexing the view isn't watched.
+        globalThis.fill = buf => {
+            buf[0] = 3;
+            wasm.grow();
+        };
+        assert.throws(() => wasm.run(), detached);
+        assert.strictEqual(wasm.first(), 3);
+
+        // And exceptions thrown by JS aren't replaced.
+        globalThis.fill = buf => {
+            wasm.grow();
+            assert.strictEqual(buf.length, 0);
+            throw new Error('from fill');
+        };
+        assert.throws(() => wasm.run(), /from fill/);
+        ",
+    );
+}
+
+#[test]
+fn no_check_without_debug() {
+    let mut b = module(SliceMode::View).bindgen();
+    let output = b.nodejs(true).generate_output().unwrap();
+    assert!(!output.js().contains("watchView"));
+}
+
+#[test]
+fn copy_in_out_survives_growth() {
+    let mut b = module(SliceMode::CopyInOut).bindgen();
+    b.debug(true);
+    run_node(
+        &mut b,
+        "
+        const assert = require('assert');
+        globalThis.fill = buf => {
+            wasm.grow();
+            buf.fill(5);
+        };
+        wasm.run();
+        assert.strictEqual(wasm.first(), 5);
+        ",
+    );
+}
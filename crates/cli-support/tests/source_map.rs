@@ -0,0 +1,42 @@
+extern crate parity_wasm;
+extern crate serde_json;
+extern crate tempfile;
+extern crate wasm_bindgen_cli_support;
+extern crate wasm_bindgen_shared;
+
+mod common;
+
+use common::*;
+use parity_wasm::elements::Instruction::*;
+
+fn module(located: bool) -> Module {
+    let mut m = Module::new();
+    m.export("answer", &[FUNCTION, 0, U32], &[], Some(I), vec![I32Const(42)]);
+    if located {
+        m.location("src/lib.rs", 3, 7);
+    }
+    m
+}
+
+#[test]
+fn maps_exports() {
+    let mut b = module(true).bindgen();
+    b.source_map(true);
+    let output = b.generate_output().unwrap();
+    assert!(output.js().ends_with("//# sourceMappingURL=demo.js.map\n"));
+    assert!(!output.js().contains("__wbindgen_source"));
+    let map: serde_json::Value = serde_json::from_str(output.source_map().unwrap()).unwrap();
+    assert_eq!(map["sources"][0], "src/lib.rs");
+    assert!(map["mappings"].as_str().unwrap().contains("AAEO"));
+}
+
+#[test]
+fn requires_locations() {
+    let mut b = module(false).bindgen();
+    b.source_map(true);
+    let err = match b.generate_output() {
+        Ok(_) => panic!("expected an error"),
+        Err(e) => e.to_string(),
+    };
+    assert!(err.contains("doesn't record where any `#[wasm_bindgen]` function is defined"));
+}
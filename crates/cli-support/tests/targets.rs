@@ -0,0 +1,140 @@
+extern crate parity_wasm;
+extern crate serde_json;
+extern crate tempfile;
+extern crate wasm_bindgen_cli_support;
+extern crate wasm_bindgen_shared;
+
+mod common;
+
+use std::fs;
+
+use common::*;
+use parity_wasm::elements::Instruction::*;
+
+/// `pub fn add(a: u32, b: u32) -> u32`, which also passes `a + b` to an
+/// imported `log` function.
+fn module() -> Module {
+    let mut m = Module::new();
+    let log = m.import("log", &[FUNCTION, 1, U32, UNIT], &[I], None);
+    m.export("add", &[FUNCTION, 2, U32, U32, U32], &[I, I], Some(I), vec![
+        GetLocal(0),
+        GetLocal(1),
+        I32Add,
+        Call(log),
+        GetLocal(0),
+        GetLocal(1),
+        I32Add,
+    ]);
+    m
+}
+
+const CHECK_ADD: &str = "
+    let logged = null;
+    globalThis.log = n => { logged = n; };
+    const check = add => {
+        if (add(1, 2) !== 3) throw new Error('wrong sum');
+        if (logged !== 3) throw new Error('log not called');
+    };
+";
+
+#[test]
+fn bundler() {
+    let mut b = module().bindgen();
+    b.bundler(true).typescript(true);
+    let output = b.generate_output().unwrap();
+    assert!(output.js().contains("import * as wasm from './demo_bg';"));
+    assert!(output.js().contains("export function add("));
+    let ts = output.typescript().unwrap();
+    assert!(ts.contains("export function add(arg0: number, arg1: number): number;"));
+    assert!(!ts.contains("init"));
+}
+
+#[test]
+fn nodejs() {
+    let mut b = module().bindgen();
+    b.typescript(true);
+    run_node(&mut b, &format!("{}\ncheck(wasm.add);", CHECK_ADD));
+}
+
+#[test]
+fn web() {
+    let mut b = module().bindgen();
+    b.web(true).typescript(true);
+    let dir = generate(&mut b);
+    let ts = fs::read_to_string(dir.path().join("demo.d.ts")).unwrap();
+    assert!(ts.contains("export default function init (module_or_path?: InitInput): Promise<any>;"));
+    fs::write(dir.path().join("package.json"), r#"{"type":"module"}"#).unwrap();
+    node(
+        dir.path(),
+        "test.mjs",
+        &format!(
+            "
+            import {{ readFileSync }} from 'fs';
+            import init, {{ add }} from './demo.js';
+            {}
+            await init(readFileSync(new URL('./demo_bg.wasm', import.meta.url)));
+            check(add);
+            ",
+            CHECK_ADD
+        ),
+    );
+}
+
+#[test]
+fn no_modules() {
+    let mut b = module().bindgen();
+    b.no_modules(true).no_modules_global("demo").typescript(true);
+    let dir = generate(&mut b);
+    let ts = fs::read_to_string(dir.path().join("demo.d.ts")).unwrap();
+    assert!(ts.contains("export default function init (module_or_path?: InitInput): Promise<any>;"));
+    node(
+        dir.path(),
+        "test.js",
+        &format!(
+            "
+            const fs = require('fs');
+            global.self = global;
+            require('vm').runInThisContext(fs.readFileSync(__dirname + '/demo.js', 'utf8'));
+            {}
+            self.demo(fs.readFileSync(__dirname + '/demo_bg.wasm'))
+                .then(() => check(self.demo.add))
+                .catch(e => {{ console.error(e); process.exit(1); }});
+            ",
+            CHECK_ADD
+        ),
+    );
+}
+
+fn error(b: &mut wasm_bindgen_cli_support::Bindgen) -> String {
+    match b.generate_output() {
+        Ok(_) => panic!("expected an error"),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn conflicting_targets() {
+    let mut b = module().bindgen();
+    b.bundler(true).nodejs(true);
+    assert_eq!(error(&mut b), "cannot specify both `--target bundler` and `--target nodejs`");
+
+    let mut b = module().bindgen();
+    b.web(true).no_modules(true);
+    assert_eq!(error(&mut b), "cannot specify both `--target web` and `--target no-modules`");
+
+    let mut b = module().bindgen();
+    b.nodejs(true).browser(true);
+    assert_eq!(error(&mut b), "cannot specify `--browser` with `--target nodejs`");
+
+    let mut b = module().bindgen();
+    b.web(true).no_modules_global("demo");
+    assert_eq!(
+        error(&mut b),
+        "can only specify `--no-modules-global` with `--target no-modules`"
+    );
+
+    // `--browser` is just a hint for the browser targets.
+    let mut b = module().bindgen();
+    b.browser(true).web(true);
+    b.generate_output().unwrap();
+}
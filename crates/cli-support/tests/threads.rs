@@ -0,0 +1,113 @@
+extern crate parity_wasm;
+extern crate serde_json;
+extern crate tempfile;
+extern crate wasm_bindgen_cli_support;
+extern crate wasm_bindgen_shared;
+
+mod common;
+
+use std::fs;
+
+use common::*;
+use parity_wasm::elements::Instruction::*;
+
+/// Exports reading and writing a word of memory initialized to 7, and the
+/// thread id, thread control block and stack pointer of the instance.
+fn module() -> Module {
+    let mut m = Module::new();
+    let current_id = m.intrinsic("__wbindgen_current_id", &[], Some(I));
+    let tcb_get = m.intrinsic("__wbindgen_tcb_get", &[], Some(I));
+    let tcb_set = m.intrinsic("__wbindgen_tcb_set", &[I], None);
+    m.data(16, &[7, 0, 0, 0]);
+    let getter = &[FUNCTION, 0, U32];
+    let setter = &[FUNCTION, 1, U32, UNIT];
+    m.export("thread_id", getter, &[], Some(I), vec![Call(current_id)]);
+    m.export("tcb", getter, &[], Some(I), vec![Call(tcb_get)]);
+    m.export("set_tcb", setter, &[I], None, vec![GetLocal(0), Call(tcb_set)]);
+    m.export("stack_pointer", getter, &[], Some(I), vec![GetGlobal(0)]);
+    m.export("load", getter, &[], Some(I), vec![I32Const(16), I32Load(2, 0)]);
+    m.export("store", setter, &[I], None, vec![I32Const(16), GetLocal(0), I32Store(2, 0)]);
+    m
+}
+
+#[test]
+fn node_worker_threads() {
+    let mut b = module().bindgen();
+    b.nodejs(true).threads(true);
+    let dir = generate(&mut b);
+    fs::write(
+        dir.path().join("worker.js"),
+        "
+        const { parentPort } = require('worker_threads');
+        const wasm = require('./demo');
+        const state = {
+            id: wasm.thread_id(),
+            tcb: wasm.tcb(),
+            sp: wasm.stack_pointer(),
+            value: wasm.load(),
+        };
+        wasm.set_tcb(state.tcb + 1);
+        wasm.store(state.value + 1);
+        parentPort.postMessage(state);
+        ",
+    )
+    .unwrap();
+    node(
+        dir.path(),
+        "test.js",
+        "
+        const assert = require('assert');
+        const { Worker } = require('worker_threads');
+        const wasm = require('./demo');
+
+        function run(thread) {
+            return new Promise((resolve, reject) => {
+                let state;
+                const worker = new Worker(__dirname + '/worker.js', { workerData: thread });
+                worker.on('message', s => state = s);
+                worker.on('error', reject);
+                worker.on('exit', () => resolve(state));
+            });
+        }
+
+        async function main() {
+            // Data segments are only written by the main thread.
+            assert.strictEqual(wasm.load(), 7);
+            wasm.store(8);
+            wasm.set_tcb(100);
+
+            const pages = wasm.memory.buffer.byteLength / 65536;
+            const thread = wasm.prepareThread();
+            assert.strictEqual(wasm.memory.buffer.byteLength / 65536, pages + 16);
+            const first = await run(thread);
+            assert.strictEqual(first.id, thread.id);
+            assert.notStrictEqual(first.id, 0);
+            assert.strictEqual(first.tcb, 0);
+            assert.strictEqual(first.sp, thread.stack);
+            assert.strictEqual(first.value, 8);
+
+            // The worker's writes are visible here, but it has its own
+            // thread-local state.
+            assert.strictEqual(wasm.load(), 9);
+            assert.strictEqual(wasm.thread_id(), 0);
+            assert.strictEqual(wasm.tcb(), 100);
+
+            // A released thread's stack and control block are reused.
+            wasm.releaseThread(thread);
+            const second = wasm.prepareThread();
+            assert.strictEqual(second.id, thread.id);
+            assert.strictEqual(wasm.memory.buffer.byteLength / 65536, pages + 16);
+            const state = await run(second);
+            assert.strictEqual(state.tcb, 1);
+            assert.strictEqual(state.value, 9);
+
+            assert.notStrictEqual(wasm.prepareThread().id, thread.id);
+        }
+
+        main().catch(e => {
+            console.error(e);
+            process.exit(1);
+        });
+        ",
+    );
+}
@@ -0,0 +1,92 @@
+//! The parts of the script running tests which are the same in every
+//! runtime.
+//!
+//! Each runtime's script imports the generated bindings as `support` and the
+//! wasm module as `wasm`, sets up the globals the test harness relies on with
+//! `globals`, defines `main` with `main` and finally lists the tests to run
+//! with `tests`. What's left to the runtime is how to load the modules, where
+//! uncaptured console output goes and what happens once the tests are done.
+
+/// Imports the bindings and wasm module as ES modules, with `ext` appended
+/// to their paths.
+pub fn es_imports(module: &str, ext: &str) -> String {
+    format!(
+        r#"
+        import * as support from './{0}{1}';
+        import * as wasm from './{0}_bg{1}';
+        "#,
+        module, ext,
+    )
+}
+
+/// Overrides `console.log` and `console.error` so the test harness can
+/// capture what tests print, and tells the harness how to invoke tests.
+///
+/// Output the harness doesn't capture goes to `orig_console_log` and
+/// `orig_console_error`, which the runtime defines. The harness installs its
+/// redirects as properties of `global`, the runtime's global object.
+pub fn globals(global: &str) -> String {
+    format!(
+        r#"
+        console.log = function() {{
+            if ({0}.console_log_redirect)
+                {0}.console_log_redirect(orig_console_log, arguments);
+            else
+                orig_console_log.apply(this, arguments);
+        }};
+        console.error = function() {{
+            if ({0}.console_error_redirect)
+                {0}.console_error_redirect(orig_console_error, arguments);
+            else
+                orig_console_error.apply(this, arguments);
+        }};
+
+        {0}.__wbg_test_invoke = f => f();
+        "#,
+        global,
+    )
+}
+
+/// Defines `async function main(tests)`, which runs the exports named in
+/// `tests`.
+///
+/// `args` is the JS expression for the arguments to pass to the harness, and
+/// `done` runs with `ok` set to whether all the tests passed.
+pub fn main(global: &str, args: &str, done: &str) -> String {
+    format!(
+        r#"
+        async function main(tests) {{
+            // This is a facet of using wasm2es6js, a hack until browsers have
+            // native ESM support for wasm modules. In node it's undefined.
+            await wasm.booted;
+
+            const cx = new support.Context();
+            {0}.console_log_redirect = support.__wbgtest_console_log;
+            {0}.console_error_redirect = support.__wbgtest_console_error;
+
+            // Forward runtime arguments. These are the arguments passed to
+            // `wasm-bindgen-test-runner` after the wasm file, from which the
+            // harness parses test filters and libtest flags like `--exact`,
+            // `--format` and `--list`.
+            cx.args({1});
+
+            const ok = await cx.run(tests.map(n => wasm[n]));
+            {2}
+        }}
+        "#,
+        global, args, done,
+    )
+}
+
+/// Declares `tests`, the names of the exports which are tests.
+///
+/// Note that we're collecting *JS objects* that represent the functions to
+/// execute, and then those objects are passed into wasm for it to execute
+/// when it sees fit.
+pub fn tests(tests: &[String]) -> String {
+    let mut js = String::from("const tests = [];\n");
+    for test in tests {
+        js.push_str(&format!("tests.push('{}');\n", test));
+    }
+    js
+}
@@ -0,0 +1,84 @@
+//! Code coverage of tests run in node, turned on by setting
+//! `WASM_BINDGEN_TEST_COVERAGE` to the directory to write reports to.
+//!
+//! The wasm file is instrumented with counters before `wasm-bindgen` runs
+//! over it, see `wasm_bindgen_cli_support::coverage`. Every node process
+//! dumps the counters to a file in the temporary directory once its tests
+//! have run, and those are added up here into an lcov report named after the
+//! wasm file.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use failure::{Error, ResultExt};
+use parity_wasm::elements::Module;
+use wasm_bindgen_cli_support::coverage;
+
+pub struct Coverage {
+    info: coverage::Coverage,
+    report: PathBuf,
+}
+
+impl Coverage {
+    /// Instruments `wasm`, the contents of `wasm_file`, if coverage was asked
+    /// for in the environment.
+    pub fn new(wasm_file: &Path, wasm: &[u8]) -> Result<Option<(Module, Coverage)>, Error> {
+        let dir = match env::var_os("WASM_BINDGEN_TEST_COVERAGE") {
+            Some(dir) => PathBuf::from(dir),
+            None => return Ok(None),
+        };
+        let (module, info) = coverage::instrument(wasm)
+            .context("failed to instrument the wasm file for coverage")?;
+        let name = wasm_file.file_stem().unwrap_or("tests".as_ref());
+        let report = dir.join(name).with_extension("lcov");
+        Ok(Some((module, Coverage { info, report })))
+    }
+
+    /// JS which dumps the counters, to run in node once tests have finished.
+    /// `wasm` must be the wasm module's exports.
+    pub fn dump_js(&self) -> String {
+        format!(
+            r#"
+            require('fs').writeFileSync(
+                require('path').join(__dirname, `coverage-${{process.pid}}.bin`),
+                Buffer.from(wasm.memory.buffer, {}, {}));
+            "#,
+            self.info.base(),
+            self.info.counters() * 4,
+        )
+    }
+
+    /// Adds up the counters dumped in `tmpdir` and writes the report.
+    pub fn report(&self, tmpdir: &Path) -> Result<(), Error> {
+        let mut counts = vec![0u64; self.info.counters()];
+        for entry in fs::read_dir(tmpdir)? {
+            let path = entry?.path();
+            let is_dump = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .map(|n| n.starts_with("coverage-") && n.ends_with(".bin"))
+                .unwrap_or(false);
+            if !is_dump {
+                continue
+            }
+            let dump = fs::read(&path)?;
+            for (count, n) in counts.iter_mut().zip(dump.chunks(4)) {
+                if n.len() == 4 {
+                    let n = u32::from(n[0])
+                        | u32::from(n[1]) << 8
+                        | u32::from(n[2]) << 16
+                        | u32::from(n[3]) << 24;
+                    *count += u64::from(n);
+                }
+            }
+        }
+
+        if let Some(dir) = self.report.parent() {
+            fs::create_dir_all(dir).context("failed to create coverage directory")?;
+        }
+        fs::write(&self.report, self.info.lcov(&counts))
+            .context("failed to write coverage report")?;
+        Ok(())
+    }
+}
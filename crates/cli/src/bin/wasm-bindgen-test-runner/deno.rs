@@ -0,0 +1,85 @@
+//! Support for running tests in Deno, selected with
+//! `WASM_BINDGEN_TEST_RUNTIME=deno`.
+//!
+//! Deno only loads ES modules, so the bindings are generated the same way as
+//! for browsers and the wasm module is shimmed as an ES module, embedded in
+//! base64 as there's no server to fetch it from. The generated imports don't
+//! have file extensions, which Deno requires, so an import map adds them.
+//!
+//! Deno has a global `self` but no `document`, so the test harness treats it
+//! like a web worker and writes its output through
+//! `__wbg_test_output_writeln`.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use failure::{Error, ResultExt};
+use wasm_bindgen_cli_support::wasm2es6js::Config;
+
+use bootstrap;
+use engine_args;
+use node;
+
+pub fn execute(
+    module: &str,
+    tmpdir: &Path,
+    args: &[OsString],
+    tests: &[String],
+) -> Result<(), Error> {
+    let wasm = fs::read(tmpdir.join(format!("{}_bg.wasm", module)))?;
+    let output = Config::new().base64(true).generate(&wasm)?;
+    fs::write(tmpdir.join(format!("{}_bg.js", module)), output.js()?)
+        .context("failed to write JS file")?;
+
+    let import_map = json!({
+        "imports": {
+            format!("./{}", module): format!("./{}.js", module),
+            format!("./{}_bg", module): format!("./{}_bg.js", module),
+        }
+    });
+    let import_map_path = tmpdir.join("import-map.json");
+    fs::write(&import_map_path, import_map.to_string())
+        .context("failed to write import map")?;
+
+    let js_path = tmpdir.join("run.js");
+    fs::write(&js_path, script(module, tests))
+        .context("failed to write JS file")?;
+
+    // Like WebDriver binaries for headless testing, a specific `deno` can be
+    // used by setting `DENO`.
+    let deno = env::var_os("DENO").unwrap_or_else(|| "deno".into());
+    let mut import_map_arg = OsString::from("--import-map=");
+    import_map_arg.push(&import_map_path);
+    let mut cmd = Command::new(deno);
+    cmd.arg("run")
+        .arg(import_map_arg)
+        .args(engine_args("deno"))
+        .arg(&js_path)
+        .args(args);
+    node::exec(&mut cmd, "deno")
+}
+
+fn script(module: &str, tests: &[String]) -> String {
+    let mut js_to_execute = bootstrap::es_imports(module, ".js");
+    js_to_execute.push_str(r#"
+        const orig_console_log = console.log.bind(console);
+        const orig_console_error = console.error.bind(console);
+
+        // Output from the harness, see `Worker` in `wasm-bindgen-test`.
+        self.__wbg_test_output_writeln = line => orig_console_log(line);
+    "#);
+    js_to_execute.push_str(&bootstrap::globals("self"));
+    js_to_execute.push_str(&bootstrap::main("self", "Deno.args", "if (!ok) Deno.exit(1);"));
+    js_to_execute.push_str(&bootstrap::tests(tests));
+    js_to_execute.push_str("
+        main(tests)
+            .catch(e => {
+                console.error(e);
+                Deno.exit(1);
+            });
+    ");
+    js_to_execute
+}
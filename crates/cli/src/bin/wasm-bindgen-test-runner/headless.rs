@@ -20,7 +20,9 @@ use shell::Shell;
 /// binary, controlling it, running tests, scraping output, displaying output,
 /// etc. It will return `Ok` if all tests finish successfully, and otherwise it
 /// will return an error if some tests failed.
-pub fn run(server: &SocketAddr, shell: &Shell) -> Result<(), Error> {
+///
+/// `timeout` is the longest any test may run for before the harness fails it.
+pub fn run(server: &SocketAddr, shell: &Shell, timeout: Duration) -> Result<(), Error> {
     let (driver, args) = Driver::find()?;
     println!("Running headless tests in {} with `{}`",
              driver.browser(),
@@ -82,7 +84,11 @@ pub fn run(server: &SocketAddr, shell: &Shell) -> Result<(), Error> {
     // just go with a loop.
     //
     // We periodically check the page to see if the output contains a known
-    // string to only be printed when tests have finished running.
+    // string to only be printed when tests have finished running. Tests
+    // running in a worker post their output back to the page, which renders
+    // it into the same elements. We give up if the output hasn't changed for
+    // a while, which is longer than the longest timeout of any test so that a
+    // test which hangs is reported by the harness itself.
     //
     // TODO: harness failures aren't well handled here, they always force a
     //       timeout. These sorts of failures could be "you typo'd the path to a
@@ -90,12 +96,18 @@ pub fn run(server: &SocketAddr, shell: &Shell) -> Result<(), Error> {
     //       this on the page and look for such output here, printing diagnostic
     //       information.
     shell.status("Waiting for test to finish...");
-    let start = Instant::now();
-    let max = Duration::new(20, 0);
+    let mut start = Instant::now();
+    let mut last = String::new();
+    let max = timeout + Duration::new(10, 0);
     while start.elapsed() < max {
-        if client.text(&id, &output)?.contains("test result: ") {
+        let text = client.text(&id, &output)?;
+        if finished(&text) {
             break
         }
+        if text != last {
+            start = Instant::now();
+            last = text;
+        }
         thread::sleep(Duration::from_millis(100));
     }
     shell.clear();
@@ -108,7 +120,7 @@ pub fn run(server: &SocketAddr, shell: &Shell) -> Result<(), Error> {
     let logs = client.text(&id, &logs)?;
     let errors = client.text(&id, &errors)?;
 
-    if output.contains("test result: ") {
+    if finished(&output) {
         println!("{}", output);
 
         // If the tests harness finished (either successfully or unsuccessfully)
@@ -128,13 +140,28 @@ pub fn run(server: &SocketAddr, shell: &Shell) -> Result<(), Error> {
         println!("console.log div contained:\n{}", tab(&errors));
     }
 
-    if !output.contains("test result: ok") {
+    if !succeeded(&output) {
         bail!("some tests failed")
     }
 
     Ok(())
 }
 
+/// Whether the harness has printed its final results, in any of the formats
+/// it supports.
+fn finished(output: &str) -> bool {
+    output.contains("test result: ")
+        || output.contains(r#"{ "type": "suite", "event": "ok""#)
+        || output.contains(r#"{ "type": "suite", "event": "failed""#)
+        || output.contains("</testsuites>")
+}
+
+fn succeeded(output: &str) -> bool {
+    output.contains("test result: ok")
+        || output.contains(r#"{ "type": "suite", "event": "ok""#)
+        || (output.contains("</testsuites>") && output.contains(r#"failures="0""#))
+}
+
 enum Driver {
     Gecko(PathBuf),
     Safari(PathBuf),
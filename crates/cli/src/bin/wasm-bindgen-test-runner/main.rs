@@ -26,21 +26,43 @@ extern crate serde_derive;
 extern crate serde_json;
 extern crate wasm_bindgen_cli_support;
 
+use std::cmp;
 use std::env;
 use std::fs;
 use std::path::PathBuf;
 use std::process;
 use std::thread;
+use std::time::Duration;
 
 use failure::{ResultExt, Error};
 use parity_wasm::elements::{Module, Deserialize, Section};
 use wasm_bindgen_cli_support::Bindgen;
 
+mod bootstrap;
+#[cfg_attr(not(feature = "coverage"), path = "no_coverage.rs")]
+mod coverage;
+mod deno;
 mod headless;
 mod node;
+// The reports of the test harness, to merge those of several processes.
+#[path = "../../../../test/src/rt/report.rs"]
+mod report;
 mod server;
+mod shard;
 mod shell;
 
+/// How long an async test may run for unless it sets its own timeout, which
+/// must match `DEFAULT_TIMEOUT_MS` in the `wasm-bindgen-test` crate.
+const DEFAULT_TIMEOUT_MS: u32 = 10_000;
+
+/// Where tests are run.
+#[derive(Copy, Clone, PartialEq)]
+enum Runtime {
+    Node,
+    Deno,
+    Browser,
+}
+
 fn main() {
     env_logger::init();
     let err = match rmain() {
@@ -58,8 +80,8 @@ fn rmain() -> Result<(), Error> {
     let mut args = env::args_os().skip(1);
     let shell = shell::Shell::new();
 
-    // Currently no flags are supported, and assume there's only one argument
-    // which is the wasm file to test. This'll want to improve over time!
+    // The first argument is the wasm file to test. The rest are passed on to
+    // the test harness, except for `--test-threads` which is handled below.
     let wasm_file_to_test = match args.next() {
         Some(file) => PathBuf::from(file),
         None => bail!("must have a file to test as first argument"),
@@ -84,18 +106,22 @@ fn rmain() -> Result<(), Error> {
 
     // Collect all tests that the test harness is supposed to run. We assume
     // that any exported function with the prefix `__wbg_test` is a test we need
-    // to execute.
-    let wasm = fs::read(&wasm_file_to_test)
+    // to execute, and likewise `__wbg_setup` and `__wbg_teardown` for the
+    // hooks to run around them.
+    let wasm_bytes = fs::read(&wasm_file_to_test)
         .context("failed to read wasm file")?;
-    let wasm = Module::deserialize(&mut &wasm[..])
+    let mut wasm = Module::deserialize(&mut &wasm_bytes[..])
         .context("failed to deserialize wasm module")?;
     let mut tests = Vec::new();
+    let mut hooks = Vec::new();
     if let Some(exports) = wasm.export_section() {
         for export in exports.entries() {
-            if !export.field().starts_with("__wbg_test") {
-                continue
+            let name = export.field();
+            if name.starts_with("__wbg_test") {
+                tests.push(name.to_string());
+            } else if name.starts_with("__wbg_setup") || name.starts_with("__wbg_teardown") {
+                hooks.push(name.to_string());
             }
-            tests.push(export.field().to_string());
         }
     }
 
@@ -107,38 +133,132 @@ fn rmain() -> Result<(), Error> {
         return Ok(())
     }
 
-    // Figure out if this tests is supposed to execute in node.js or a browser.
-    // That's done on a per-test-binary basis with the
-    // `wasm_bindgen_test_configure` macro, which emits a custom section for us
-    // to read later on.
-    let mut node = true;
+    // Hooks are handed to the harness the same way as tests, registering
+    // themselves when called.
+    tests.extend(hooks);
+
+    // Figure out if this tests is supposed to execute in node.js, a browser,
+    // or a worker in a browser. That's done on a per-test-binary basis with
+    // the `wasm_bindgen_test_configure` macro, which emits a custom section
+    // for us to read later on.
+    //
+    // Tests with their own timeout are also listed in a custom section, which
+    // tells us how long a test might run in the browser without printing
+    // anything.
+    let mut runtime = Runtime::Node;
+    let mut worker = None;
+    let mut timeout = DEFAULT_TIMEOUT_MS;
     for section in wasm.sections() {
         let custom = match section {
             Section::Custom(section) => section,
             _ => continue,
         };
+        if custom.name() == "__wasm_bindgen_test_timeouts" {
+            for millis in custom.payload().chunks(4) {
+                let millis = millis
+                    .iter()
+                    .rev()
+                    .fold(0, |sum, byte| (sum << 8) | u32::from(*byte));
+                timeout = cmp::max(timeout, millis);
+            }
+            continue
+        }
         if custom.name() != "__wasm_bindgen_test_unstable" {
             continue
         }
-        node = !custom.payload().contains(&0x01);
+        for byte in custom.payload() {
+            match byte {
+                0x01 => {}
+                0x02 => worker = Some(server::Worker::Dedicated),
+                0x03 => worker = Some(server::Worker::Shared),
+                0x04 => worker = Some(server::Worker::Service),
+                _ => continue,
+            }
+            runtime = Runtime::Browser;
+        }
     }
+
+    // The environment can override that, to run the same tests in several
+    // places.
+    if let Ok(name) = env::var("WASM_BINDGEN_TEST_RUNTIME") {
+        runtime = match &name[..] {
+            "node" => Runtime::Node,
+            "deno" => Runtime::Deno,
+            "browser" => Runtime::Browser,
+            _ => bail!(
+                "unsupported WASM_BINDGEN_TEST_RUNTIME `{}`, expected node, deno or browser",
+                name
+            ),
+        };
+    }
+
+    // Coverage is collected from the memory of each node process once its
+    // tests have run, which isn't possible in a browser.
+    let mut coverage = None;
+    if let Some((module, c)) = coverage::Coverage::new(&wasm_file_to_test, &wasm_bytes)? {
+        if runtime != Runtime::Node {
+            bail!("coverage is only supported for tests run in node.js");
+        }
+        wasm = module;
+        coverage = Some(c);
+    }
+
     let headless = env::var("NO_HEADLESS").is_err();
     let debug = env::var("WASM_BINDGEN_NO_DEBUG").is_err();
 
+    // `--test-threads` is handled here, spreading tests in node over that many
+    // processes (it's ignored in browsers). Everything else is passed along
+    // to the test harness. A report format can also be selected through the
+    // environment since `cargo test` doesn't forward arguments to every test
+    // binary in the same way.
+    let mut threads = 1;
+    let mut harness_args = Vec::new();
+    while let Some(arg) = args.next() {
+        let value = match arg.to_str() {
+            Some("--test-threads") => args.next(),
+            Some(s) if s.starts_with("--test-threads=") => {
+                Some(s["--test-threads=".len()..].into())
+            }
+            _ => {
+                harness_args.push(arg);
+                continue
+            }
+        };
+        threads = match value.as_ref().and_then(|v| v.to_str()).map(|v| v.parse()) {
+            Some(Ok(n)) if n > 0 => n,
+            _ => bail!("`--test-threads` requires a positive number"),
+        };
+    }
+    // Deno could honor it the same way node does, but until then it's better
+    // to say so than to quietly run one test at a time.
+    if threads > 1 && runtime == Runtime::Deno {
+        bail!("`--test-threads` is only supported for tests run in node.js");
+    }
+    let mut args = harness_args;
+    if let Some(format) = env::var_os("WASM_BINDGEN_TEST_FORMAT") {
+        args.push("--format".into());
+        args.push(format);
+    }
+
     // Make the generated bindings available for the tests to execute against.
     shell.status("Executing bindgen...");
     let mut b = Bindgen::new();
     b.debug(debug)
-        .nodejs(node)
+        .nodejs(runtime == Runtime::Node)
         .input_module(module, wasm, |w| parity_wasm::serialize(w).unwrap())
         .keep_debug(false)
         .generate(&tmpdir)
         .context("executing `wasm-bindgen` over the wasm file")?;
     shell.clear();
 
-    // If we're executing in node.js, that module will take it from here.
-    if node {
-        return node::execute(&module, &tmpdir, &args.collect::<Vec<_>>(), &tests)
+    // If we're executing in node.js or Deno, that module will take it from
+    // here.
+    match runtime {
+        Runtime::Node => {
+            return node::execute(&module, &tmpdir, &args, &tests, threads, coverage.as_ref())
+        }
+        Runtime::Deno => return deno::execute(&module, &tmpdir, &args, &tests),
+        Runtime::Browser => {}
     }
 
     // Otherwise we're executing in a browser. Spawn a server which serves up
@@ -150,9 +270,10 @@ fn rmain() -> Result<(), Error> {
             "127.0.0.1:8000".parse().unwrap()
         },
         headless,
+        worker,
         &module,
         &tmpdir,
-        &args.collect::<Vec<_>>(),
+        &args,
         &tests,
     )?;
     let addr = srv.server_addr();
@@ -170,6 +291,16 @@ fn rmain() -> Result<(), Error> {
     }
 
     thread::spawn(|| srv.run());
-    headless::run(&addr, &shell)?;
+    headless::run(&addr, &shell, Duration::from_millis(u64::from(timeout)))?;
     Ok(())
 }
+
+/// Extra arguments for the JS engine `name`, from an environment variable like
+/// `NODE_ARGS`, in the same way as `GECKODRIVER_ARGS` for headless testing.
+fn engine_args(name: &str) -> Vec<String> {
+    env::var(format!("{}_ARGS", name.to_uppercase()))
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|s| s.to_string())
+        .collect()
+}
@@ -0,0 +1,30 @@
+//! Stands in for `coverage.rs` when the runner is built without the
+//! `coverage` feature, in which case asking for coverage is an error.
+
+use std::env;
+use std::path::Path;
+
+use failure::Error;
+use parity_wasm::elements::Module;
+
+pub enum Coverage {}
+
+impl Coverage {
+    pub fn new(_wasm_file: &Path, _wasm: &[u8]) -> Result<Option<(Module, Coverage)>, Error> {
+        if env::var_os("WASM_BINDGEN_TEST_COVERAGE").is_some() {
+            bail!(
+                "`WASM_BINDGEN_TEST_COVERAGE` is set, but this `wasm-bindgen-test-runner` \
+                 was built without the `coverage` feature"
+            );
+        }
+        Ok(None)
+    }
+
+    pub fn dump_js(&self) -> String {
+        match *self {}
+    }
+
+    pub fn report(&self, _tmpdir: &Path) -> Result<(), Error> {
+        match *self {}
+    }
+}
@@ -2,71 +2,92 @@ use std::env;
 use std::ffi::OsString;
 use std::fs;
 use std::path::Path;
-use std::process::Command;
+use std::process::{self, Command};
 
 use failure::{ResultExt, Error};
 
-pub fn execute(module: &str, tmpdir: &Path, args: &[OsString], tests: &[String])
-    -> Result<(), Error>
-{
-    let mut js_to_execute = format!(r#"
-        const {{ exit }} = require('process');
+use bootstrap;
+use coverage::Coverage;
+use engine_args;
+use shard;
 
-        let console_log_redirect = null;
-        let console_error_redirect = null;
-
-        // override `console.log` and `console.error` before we import tests to
-        // ensure they're bound correctly in wasm. This'll allow us to intercept
-        // all these calls and capture the output of tests
-        const prev_log = console.log;
-        console.log = function() {{
-            if (console_log_redirect === null)  {{
-                prev_log.apply(null, arguments);
-            }} else {{
-                console_log_redirect(prev_log, arguments);
-            }}
-        }};
-        const prev_error = console.error;
-        console.error = function() {{
-            if (console_error_redirect === null) {{
-                prev_error.apply(null, arguments);
-            }} else {{
-                console_error_redirect(prev_error, arguments);
-            }}
-        }};
-
-        global.__wbg_test_invoke = f => f();
+pub fn execute(
+    module: &str,
+    tmpdir: &Path,
+    args: &[OsString],
+    tests: &[String],
+    threads: usize,
+    coverage: Option<&Coverage>,
+) -> Result<(), Error> {
+    // Listing tests is quick, so it's never worth spreading over processes,
+    // and there's no coverage to report. Benchmarks are measured one at a
+    // time so they don't slow each other down.
+    if args.iter().any(|a| a == "--list") {
+        return run(module, tmpdir, args, tests, None)
+    }
+    if threads > 1 && !args.iter().any(|a| a == "--bench") {
+        return shard::execute(module, tmpdir, args, tests, threads, coverage)
+    }
+    run(module, tmpdir, args, tests, coverage)
+}
 
-        async function main(tests) {{
-            const support = require("./{0}");
-            const wasm = require("./{0}_bg");
+fn run(
+    module: &str,
+    tmpdir: &Path,
+    args: &[OsString],
+    tests: &[String],
+    coverage: Option<&Coverage>,
+) -> Result<(), Error> {
+    let js_path = tmpdir.join("run.js");
+    fs::write(&js_path, script(module, tests, coverage))
+        .context("failed to write JS file")?;
+    let mut cmd = command(tmpdir, &js_path);
+    cmd.args(args);
+    let coverage = match coverage {
+        Some(coverage) => coverage,
+        None => return exec(&mut cmd, "node"),
+    };
 
-            cx = new support.Context();
-            console_log_redirect = support.__wbgtest_console_log;
-            console_error_redirect = support.__wbgtest_console_error;
+    // The report can only be written once node has exited, so node can't
+    // take over this process.
+    let status = cmd.status().context("failed to execute `node`")?;
+    coverage.report(tmpdir)?;
+    if !status.success() {
+        process::exit(status.code().unwrap_or(3));
+    }
+    Ok(())
+}
 
-            // Forward runtime arguments. These arguments are also arguments to the
-            // `wasm-bindgen-test-runner` which forwards them to node which we
-            // forward to the test harness. this is basically only used for test
-            // filters for now.
-            cx.args(process.argv.slice(2));
+/// Generates the script which runs `tests`, exiting with a nonzero code if
+/// any of them fail. If `coverage` is given the script also dumps the
+/// coverage counters once the tests have run.
+pub fn script(module: &str, tests: &[String], coverage: Option<&Coverage>) -> String {
+    let mut js_to_execute = String::from(r#"
+        const { exit } = require('process');
 
-            const ok = await cx.run(tests.map(n => wasm[n]));
-            if (!ok)
-                exit(1);
-        }}
+        const orig_console_log = console.log;
+        const orig_console_error = console.error;
+    "#);
+    // Override the console before we import tests to ensure they're bound
+    // correctly in wasm.
+    js_to_execute.push_str(&bootstrap::globals("global"));
+    js_to_execute.push_str(&format!(r#"
+        const support = require("./{0}");
+        const wasm = require("./{0}_bg");
 
-        const tests = [];
+        // Benchmarks are timed with `performance.now()`, which older versions
+        // of node only have in `perf_hooks`.
+        if (typeof performance === 'undefined')
+            global.performance = require('perf_hooks').performance;
     "#,
-        module
+        module,
+    ));
+    let done = format!(
+        "{}\nif (!ok) exit(1);",
+        coverage.map(|c| c.dump_js()).unwrap_or_default(),
     );
-
-    // Note that we're collecting *JS objects* that represent the functions to
-    // execute, and then those objects are passed into wasm for it to execute
-    // when it sees fit.
-    for test in tests {
-        js_to_execute.push_str(&format!("tests.push('{}')\n", test));
-    }
+    js_to_execute.push_str(&bootstrap::main("global", "process.argv.slice(2)", &done));
+    js_to_execute.push_str(&bootstrap::tests(tests));
     // And as a final addendum, exit with a nonzero code if any tests fail.
     js_to_execute.push_str("
         main(tests)
@@ -75,11 +96,11 @@ pub fn execute(module: &str, tmpdir: &Path, args: &[OsString], tests: &[String])
                 exit(1);
             });
     ");
+    js_to_execute
+}
 
-    let js_path = tmpdir.join("run.js");
-    fs::write(&js_path, js_to_execute)
-        .context("failed to write JS file")?;
-
+/// Creates the command which runs the script at `js_path` in node.
+pub fn command(tmpdir: &Path, js_path: &Path) -> Command {
     // Augment `NODE_PATH` so things like `require("tests/my-custom.js")` work
     // and Rust code can import from custom JS shims. This is a bit of a hack
     // and should probably be removed at some point.
@@ -87,23 +108,27 @@ pub fn execute(module: &str, tmpdir: &Path, args: &[OsString], tests: &[String])
     let mut path = env::split_paths(&path).collect::<Vec<_>>();
     path.push(env::current_dir().unwrap());
     path.push(tmpdir.to_path_buf());
-    exec(
-        Command::new("node")
-            .env("NODE_PATH", env::join_paths(&path).unwrap())
-            .arg(&js_path)
-            .args(args)
-    )
+    // Like `DENO` for Deno, a specific `node` can be used by setting `NODE`.
+    let node = env::var_os("NODE").unwrap_or_else(|| "node".into());
+    let mut cmd = Command::new(node);
+    cmd.env("NODE_PATH", env::join_paths(&path).unwrap())
+        .args(engine_args("node"))
+        .arg(js_path);
+    cmd
 }
 
+/// Runs `cmd` in place of this process, exiting with its status. `program`
+/// names what it runs in errors.
 #[cfg(unix)]
-fn exec(cmd: &mut Command) -> Result<(), Error> {
+pub fn exec(cmd: &mut Command, program: &str) -> Result<(), Error> {
     use std::os::unix::prelude::*;
-    Err(Error::from(cmd.exec()).context("failed to execute `node`").into())
+    let msg = format!("failed to execute `{}`", program);
+    Err(Error::from(cmd.exec()).context(msg).into())
 }
 
 #[cfg(windows)]
-fn exec(cmd: &mut Command) -> Result<(), Error> {
-    use std::process;
-    let status = cmd.status()?;
+pub fn exec(cmd: &mut Command, program: &str) -> Result<(), Error> {
+    let status = cmd.status()
+        .with_context(|_| format!("failed to execute `{}`", program))?;
     process::exit(status.code().unwrap_or(3));
 }
@@ -7,49 +7,35 @@ use failure::{ResultExt, Error};
 use rouille::{self, Response, Request, Server};
 use wasm_bindgen_cli_support::wasm2es6js::Config;
 
+use bootstrap;
+
+/// The kind of web worker to run tests in, rather than on the page itself.
+#[derive(Copy, Clone)]
+pub enum Worker {
+    Dedicated,
+    Shared,
+    Service,
+}
+
 pub fn spawn(
     addr: &SocketAddr,
     headless: bool,
+    worker: Option<Worker>,
     module: &str,
     tmpdir: &Path,
     args: &[OsString],
     tests: &[String],
 ) -> Result<Server<impl Fn(&Request) -> Response + Send + Sync>, Error> {
-    let mut js_to_execute = format!(r#"
-        import {{ Context, __wbgtest_console_log, __wbgtest_console_error }} from './{0}';
-        import * as wasm from './{0}_bg';
-
-        // Now that we've gotten to the point where JS is executing, update our
-        // status text as at this point we should be asynchronously fetching the
-        // wasm module.
-        document.getElementById('output').innerHTML = "Loading wasm module...";
-
-        async function main(test) {{
-            // this is a facet of using wasm2es6js, a hack until browsers have
-            // native ESM support for wasm modules.
-            await wasm.booted;
-
-            const cx = new Context();
-            window.console_log_redirect = __wbgtest_console_log;
-            window.console_error_redirect = __wbgtest_console_error;
-
-            // Forward runtime arguments. These arguments are also arguments to the
-            // `wasm-bindgen-test-runner` which forwards them to node which we
-            // forward to the test harness. this is basically only used for test
-            // filters for now.
-            cx.args({1:?});
-
-            await cx.run(test.map(s => wasm[s]));
-        }}
-
-        const tests = [];
-    "#,
-        module, args,
-    );
-    for test in tests {
-        js_to_execute.push_str(&format!("tests.push('{}');\n", test));
-    }
-    js_to_execute.push_str("main(tests);\n");
+    // The page always runs `run.js`. When testing in a worker that script
+    // only starts the worker, which runs the tests from `worker.js`.
+    let js_to_execute = match worker {
+        Some(worker) => {
+            fs::write(tmpdir.join("worker.js"), worker_js(module, args, tests))
+                .context("failed to write JS file")?;
+            worker_page_js(worker)
+        }
+        None => page_js(module, args, tests),
+    };
 
     let js_path = tmpdir.join("run.js");
     fs::write(&js_path, js_to_execute)
@@ -131,3 +117,117 @@ pub fn spawn(
         response
     }
 }
+
+/// The script which runs tests on the page itself. The console is already
+/// routed through `window` by `index.html`.
+fn page_js(module: &str, args: &[OsString], tests: &[String]) -> String {
+    let mut js_to_execute = bootstrap::es_imports(module, "");
+    js_to_execute.push_str(r#"
+        // Now that we've gotten to the point where JS is executing, update our
+        // status text as at this point we should be asynchronously fetching the
+        // wasm module.
+        document.getElementById('output').innerHTML = "Loading wasm module...";
+    "#);
+    js_to_execute.push_str(&bootstrap::main("window", &format!("{:?}", args), ""));
+    js_to_execute.push_str(&bootstrap::tests(tests));
+    js_to_execute.push_str("main(tests);\n");
+    js_to_execute
+}
+
+/// The script which starts a worker running `worker.js` and relays what it
+/// posts back onto the page.
+///
+/// The worker is handed one end of a `MessageChannel`, over which it sends
+/// `[kind, message]` pairs. Test output goes into the same `#output` element
+/// as when testing on the page, where headless testing scrapes it from, and
+/// console output is logged on the page so it's routed like the page's own.
+fn worker_page_js(worker: Worker) -> String {
+    let start = match worker {
+        Worker::Dedicated => r#"
+            const worker = new Worker('worker.js', { type: 'module' });
+            worker.onerror = e => console.error(e.message);
+            worker.postMessage(null, [channel.port2]);
+        "#,
+        Worker::Shared => r#"
+            const worker = new SharedWorker('worker.js', { type: 'module' });
+            worker.onerror = e => console.error(e.message);
+            worker.port.postMessage(null, [channel.port2]);
+        "#,
+        Worker::Service => r#"
+            navigator.serviceWorker.register('worker.js', { type: 'module' })
+                .then(() => navigator.serviceWorker.ready)
+                .then(registration => {
+                    registration.active.postMessage(null, [channel.port2]);
+                })
+                .catch(e => console.error(`${e}`));
+        "#,
+    };
+    format!(r#"
+        const output = document.getElementById('output');
+        output.innerHTML = "Loading worker...";
+
+        let started = false;
+        const channel = new MessageChannel();
+        channel.port1.onmessage = e => {{
+            const [kind, msg] = e.data;
+            if (kind === 'output') {{
+                if (!started) {{
+                    output.textContent = '';
+                    started = true;
+                }}
+                output.textContent += `${{msg}}\n`;
+            }} else if (kind === 'console_log') {{
+                console.log(msg);
+            }} else {{
+                console.error(msg);
+            }}
+        }};
+        {}
+    "#,
+        start,
+    )
+}
+
+/// The script run inside of a worker, running the tests and posting their
+/// output back to the page.
+fn worker_js(module: &str, args: &[OsString], tests: &[String]) -> String {
+    let mut js_to_execute = bootstrap::es_imports(module, "");
+    js_to_execute.push_str(r#"
+        let port = null;
+        const post = (kind, msg) => port.postMessage([kind, msg]);
+
+        // Output from the harness, see `Worker` in `wasm-bindgen-test`.
+        self.__wbg_test_output_writeln = line => post('output', line);
+
+        // Console output which isn't captured by a test is sent to the page.
+        // Messages are stringified here as not everything can be posted.
+        const orig_console_log = function(...args) {
+            for (let msg of args) {
+                post('console_log', `${msg}`);
+            }
+        };
+        const orig_console_error = function(...args) {
+            for (let msg of args) {
+                post('console_error', `${msg}`);
+            }
+        };
+    "#);
+    js_to_execute.push_str(&bootstrap::globals("self"));
+    js_to_execute.push_str(&bootstrap::main("self", &format!("{:?}", args), ""));
+    js_to_execute.push_str(r#"
+        // The page starts the tests by sending us the port to post output
+        // to. Dedicated and service workers receive it directly, while
+        // shared workers receive it over the port of their connection.
+        function start(e) {
+            port = e.ports[0];
+            const done = main(tests).catch(e => console.error(e.stack || e));
+            // Keep a service worker alive until the tests are done.
+            if (e.waitUntil)
+                e.waitUntil(done);
+        }
+        self.onmessage = start;
+        self.onconnect = e => { e.ports[0].onmessage = start; };
+    "#);
+    js_to_execute.push_str(&bootstrap::tests(tests));
+    js_to_execute
+}
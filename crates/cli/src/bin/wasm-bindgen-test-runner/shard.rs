@@ -0,0 +1,517 @@
+//! Running tests in node over several processes, for `--test-threads`.
+//!
+//! The `__wbg_test_*` exports are dealt out round-robin into one shard per
+//! process, and every process runs the test harness with `--format json`.
+//! Their results are then merged here into a single report, sorted by test
+//! name so it doesn't depend on which process finished first, and printed in
+//! the format that was asked for.
+//!
+//! If a process dies partway through, say from a hard trap or running out of
+//! memory, the test it was running fails and the rest of its shard is run
+//! again in a new process.
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Instant;
+
+use failure::{Error, ResultExt};
+use serde_json::{self, Value};
+
+use coverage::Coverage;
+use node;
+use report::{self, Outcome, Record, Summary};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Format {
+    Pretty,
+    Json,
+    Junit,
+}
+
+/// The results of one shard.
+#[derive(Default)]
+struct Shard {
+    records: Vec<Record>,
+    /// Output which wasn't part of the report, like that of tests run with
+    /// `--nocapture`.
+    output: Vec<String>,
+    /// Whether the harness failed after all of its tests finished, like when
+    /// a `once` teardown hook fails.
+    failed: bool,
+}
+
+/// An event of the JSON report of a shard's process.
+#[derive(Debug, PartialEq)]
+enum Event {
+    SuiteStarted,
+    TestStarted(String),
+    TestFinished(Record),
+    SuiteFinished,
+}
+
+pub fn execute(
+    module: &str,
+    tmpdir: &Path,
+    args: &[OsString],
+    tests: &[String],
+    threads: usize,
+    coverage: Option<&Coverage>,
+) -> Result<(), Error> {
+    let (format, args) = split_format(args)?;
+
+    // Setup and teardown hooks are exported alongside tests and need to be in
+    // every shard.
+    let (exports, hooks): (Vec<_>, Vec<_>) = tests
+        .iter()
+        .cloned()
+        .partition(|t| t.starts_with("__wbg_test"));
+    let threads = threads.min(exports.len());
+
+    let start = Instant::now();
+    let mut shards = Vec::new();
+    for i in 0..threads {
+        let mut tests = exports
+            .iter()
+            .skip(i)
+            .step_by(threads)
+            .cloned()
+            .collect::<Vec<_>>();
+        tests.extend(hooks.iter().cloned());
+        let js_path = tmpdir.join(format!("run-{}.js", i));
+        fs::write(&js_path, node::script(module, &tests, coverage))
+            .context("failed to write JS file")?;
+        let tmpdir = tmpdir.to_path_buf();
+        let args = args.clone();
+        shards.push(thread::spawn(move || run_shard(&tmpdir, &js_path, &args)));
+    }
+
+    let mut records = Vec::new();
+    let mut failed = false;
+    for shard in shards {
+        let shard = shard.join().unwrap()?;
+        for line in shard.output {
+            println!("{}", line);
+        }
+        records.extend(shard.records);
+        failed |= shard.failed;
+    }
+    records.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let elapsed = start.elapsed();
+    let exec_time = elapsed.as_secs() as f64 + elapsed.subsec_millis() as f64 / 1000.0;
+    let filtered_out = exports.len().saturating_sub(records.len());
+    let count = |f: fn(&Outcome) -> bool| records.iter().filter(|r| f(&r.outcome)).count();
+    let passed = count(|o| match o {
+        Outcome::Ok => true,
+        _ => false,
+    });
+    let ignored = count(|o| match o {
+        Outcome::Ignored(_) => true,
+        _ => false,
+    });
+    let measured = count(|o| match o {
+        Outcome::Bench(_) => true,
+        _ => false,
+    });
+    let failures = records
+        .iter()
+        .filter_map(|r| match &r.outcome {
+            Outcome::Failed(output) => Some((r.name.clone(), output.clone())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    match format {
+        Format::Pretty => {
+            println!("{}", report::pretty_suite_started(records.len()));
+            for record in records.iter() {
+                println!("{}", report::pretty_test_finished(record));
+            }
+            println!(
+                "{}",
+                report::pretty_suite_finished(&failures, passed, ignored, measured, filtered_out)
+            );
+        }
+        Format::Json => {
+            println!("{}", report::json_suite_started(records.len()));
+            for record in records.iter() {
+                println!("{}", report::json_test_started(&record.name));
+                println!("{}", report::json_test_finished(record));
+            }
+            println!(
+                "{}",
+                report::json_suite_finished(
+                    passed,
+                    failures.len(),
+                    ignored,
+                    measured,
+                    filtered_out,
+                    exec_time,
+                )
+            );
+        }
+        Format::Junit => println!("{}", report::junit(&records, exec_time)),
+    }
+    if let Some(coverage) = coverage {
+        coverage.report(tmpdir)?;
+    }
+    if failed || failures.len() > 0 {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Runs the tests of one shard to completion, in as many processes as it
+/// takes.
+fn run_shard(tmpdir: &Path, js_path: &PathBuf, args: &[OsString]) -> Result<Shard, Error> {
+    let mut shard = Shard::default();
+    let mut seen = HashSet::new();
+    let mut run_args = args.to_vec();
+    loop {
+        let output = node::command(tmpdir, js_path)
+            .args(&run_args)
+            .arg("--format")
+            .arg("json")
+            .output()
+            .context("failed to execute `node`")?;
+
+        let mut running = None;
+        let mut finished = false;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            match parse_event(line) {
+                Some(Event::TestStarted(name)) => running = Some(name),
+                Some(Event::TestFinished(record)) => {
+                    running = None;
+                    seen.insert(record.name.clone());
+                    shard.records.push(record);
+                }
+                Some(Event::SuiteStarted) => {}
+                Some(Event::SuiteFinished) => finished = true,
+                None => shard.output.push(line.to_string()),
+            }
+        }
+        // The harness can fail after reporting every test, like when a
+        // `once` teardown hook fails, whether or not it gets as far as
+        // finishing the suite.
+        if !output.status.success() {
+            shard.failed = true;
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.len() > 0 {
+                shard.output.push(stderr.into_owned());
+            }
+        }
+        if finished {
+            return Ok(shard)
+        }
+
+        // The process died before finishing, so fail the test it was running
+        // and figure out which tests are left by listing them all.
+        let crash = format!(
+            "node exited with {} while running this test\nstderr:\n{}",
+            output.status,
+            report::tab(&String::from_utf8_lossy(&output.stderr)),
+        );
+        let remaining = list(tmpdir, js_path, args)?
+            .into_iter()
+            .filter(|name| !seen.contains(name))
+            .collect::<Vec<_>>();
+        let crashed = match running {
+            Some(name) => vec![name],
+            // If no test was running then it's not going to get any better
+            // by trying again, so fail everything that's left.
+            None => remaining.clone(),
+        };
+        for name in crashed {
+            seen.insert(name.clone());
+            shard.records.push(Record {
+                name,
+                outcome: Outcome::Failed(crash.clone()),
+                exec_time: 0.0,
+            });
+        }
+
+        let remaining = remaining
+            .into_iter()
+            .filter(|name| !seen.contains(name))
+            .collect::<Vec<_>>();
+        if remaining.len() == 0 {
+            return Ok(shard)
+        }
+        run_args = retry_args(args, &remaining);
+    }
+}
+
+/// Parses a line of a process' output as an event of its JSON report,
+/// returning `None` for lines which aren't one.
+fn parse_event(line: &str) -> Option<Event> {
+    let event = serde_json::from_str::<Value>(line).ok()?;
+    let name = || event["name"].as_str().unwrap_or("").to_string();
+    let outcome = match (event["type"].as_str()?, event["event"].as_str()) {
+        ("test", Some("started")) => return Some(Event::TestStarted(name())),
+        ("test", Some("ok")) => Outcome::Ok,
+        ("test", Some("failed")) => {
+            Outcome::Failed(event["stdout"].as_str().unwrap_or("").to_string())
+        }
+        ("test", Some("ignored")) => {
+            Outcome::Ignored(event["message"].as_str().map(|s| s.to_string()))
+        }
+        ("bench", _) => Outcome::Bench(Summary {
+            mean: event["mean"].as_f64().unwrap_or(0.0),
+            median: event["median"].as_f64().unwrap_or(0.0),
+            deviation: event["deviation"].as_f64().unwrap_or(0.0),
+        }),
+        ("suite", Some("ok")) | ("suite", Some("failed")) => return Some(Event::SuiteFinished),
+        ("suite", Some("started")) => return Some(Event::SuiteStarted),
+        _ => return None,
+    };
+    Some(Event::TestFinished(Record {
+        name: name(),
+        outcome,
+        exec_time: event["exec_time"].as_f64().unwrap_or(0.0),
+    }))
+}
+
+/// Lists the names of the tests in the script at `js_path` which `args`
+/// select, including benchmarks which are run as tests.
+fn list(tmpdir: &Path, js_path: &PathBuf, args: &[OsString]) -> Result<Vec<String>, Error> {
+    let output = node::command(tmpdir, js_path)
+        .args(args)
+        .arg("--list")
+        .output()
+        .context("failed to execute `node`")?;
+    if !output.status.success() {
+        bail!(
+            "failed to list tests:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            if line.ends_with(": test") {
+                Some(line[..line.len() - ": test".len()].to_string())
+            } else if line.ends_with(": bench") {
+                Some(line[..line.len() - ": bench".len()].to_string())
+            } else {
+                None
+            }
+        })
+        .collect())
+}
+
+/// Arguments running exactly the tests `remaining`, keeping the flags of
+/// `args` which affect how they're run.
+fn retry_args(args: &[OsString], remaining: &[String]) -> Vec<OsString> {
+    let mut ret = args
+        .iter()
+        .filter(|arg| {
+            *arg == "--ignored" || *arg == "--include-ignored" || *arg == "--nocapture"
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    ret.push("--exact".into());
+    ret.extend(remaining.iter().map(|name| name.into()));
+    ret
+}
+
+/// Takes `--format` out of `args`, since every process uses JSON and the
+/// report is formatted here instead.
+fn split_format(args: &[OsString]) -> Result<(Format, Vec<OsString>), Error> {
+    let mut format = "pretty".to_string();
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--format") => match args.next().and_then(|a| a.to_str()) {
+                Some(f) => format = f.to_string(),
+                None => bail!("flag --format requires an argument"),
+            },
+            Some(s) if s.starts_with("--format=") => format = s["--format=".len()..].to_string(),
+            Some("-Zunstable-options") => {}
+            Some("-Z") => {
+                args.next();
+            }
+            _ => rest.push(arg.clone()),
+        }
+    }
+    let format = match &format[..] {
+        "pretty" => Format::Pretty,
+        "json" => Format::Json,
+        "junit" => Format::Junit,
+        _ => bail!("unsupported --format {}, expected pretty, json or junit", format),
+    };
+    Ok((format, rest))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn os(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(|a| a.into()).collect()
+    }
+
+    fn finished(name: &str, outcome: Outcome, exec_time: f64) -> Option<Event> {
+        Some(Event::TestFinished(Record {
+            name: name.to_string(),
+            outcome,
+            exec_time,
+        }))
+    }
+
+    #[test]
+    fn parses_events() {
+        assert_eq!(
+            parse_event(r#"{ "type": "suite", "event": "started", "test_count": 2 }"#),
+            Some(Event::SuiteStarted)
+        );
+        assert_eq!(
+            parse_event(r#"{ "type": "test", "event": "started", "name": "a::b" }"#),
+            Some(Event::TestStarted("a::b".to_string()))
+        );
+        assert_eq!(
+            parse_event(r#"{ "type": "test", "name": "a::b", "event": "ok", "exec_time": 0.5 }"#),
+            finished("a::b", Outcome::Ok, 0.5)
+        );
+        assert_eq!(
+            parse_event(
+                r#"{ "type": "test", "name": "t", "event": "failed", "exec_time": 1, "stdout": "oh no\n" }"#
+            ),
+            finished("t", Outcome::Failed("oh no\n".to_string()), 1.0)
+        );
+        assert_eq!(
+            parse_event(r#"{ "type": "test", "name": "t", "event": "ignored" }"#),
+            finished("t", Outcome::Ignored(None), 0.0)
+        );
+        assert_eq!(
+            parse_event(r#"{ "type": "test", "name": "t", "event": "ignored", "message": "slow" }"#),
+            finished("t", Outcome::Ignored(Some("slow".to_string())), 0.0)
+        );
+        assert_eq!(
+            parse_event(r#"{ "type": "bench", "name": "b", "median": 2, "deviation": 1, "mean": 3 }"#),
+            finished(
+                "b",
+                Outcome::Bench(Summary {
+                    mean: 3.0,
+                    median: 2.0,
+                    deviation: 1.0,
+                }),
+                0.0
+            )
+        );
+        assert_eq!(
+            parse_event(r#"{ "type": "suite", "event": "failed", "passed": 0, "failed": 1 }"#),
+            Some(Event::SuiteFinished)
+        );
+    }
+
+    #[test]
+    fn other_output_is_not_an_event() {
+        assert_eq!(parse_event("test output"), None);
+        assert_eq!(parse_event("[1, 2]"), None);
+        assert_eq!(parse_event("{}"), None);
+        assert_eq!(parse_event(r#"{ "type": "other" }"#), None);
+    }
+
+    #[test]
+    fn retries_remaining_tests_exactly() {
+        let args = os(&["filter", "--nocapture", "--skip", "slow", "--include-ignored"]);
+        let remaining = ["a::b".to_string(), "c".to_string()];
+        assert_eq!(
+            retry_args(&args, &remaining),
+            os(&["--nocapture", "--include-ignored", "--exact", "a::b", "c"])
+        );
+        assert_eq!(retry_args(&os(&["--ignored"]), &[]), os(&["--ignored", "--exact"]));
+    }
+
+    #[test]
+    fn splits_format() {
+        let (format, rest) = split_format(&os(&["a", "--format", "junit", "b"])).unwrap();
+        assert_eq!(format, Format::Junit);
+        assert_eq!(rest, os(&["a", "b"]));
+
+        let (format, rest) = split_format(&os(&["-Z", "unstable-options", "--format=json"])).unwrap();
+        assert_eq!(format, Format::Json);
+        assert_eq!(rest, os(&[]));
+
+        let (format, rest) = split_format(&os(&["-Zunstable-options", "--exact"])).unwrap();
+        assert_eq!(format, Format::Pretty);
+        assert_eq!(rest, os(&["--exact"]));
+    }
+
+    /// Runs a shard of `script` in node from a fresh temporary directory.
+    fn run_script(name: &str, script: &str) -> Shard {
+        let tmpdir = env::temp_dir().join(format!("wbg-shard-{}-{}", name, process::id()));
+        fs::create_dir_all(&tmpdir).unwrap();
+        let js_path = tmpdir.join("run.js");
+        fs::write(&js_path, script).unwrap();
+        let shard = run_shard(&tmpdir, &js_path, &[]).unwrap();
+        fs::remove_dir_all(&tmpdir).unwrap();
+        shard
+    }
+
+    /// A script which reports that the test `a` passed and then exits with
+    /// `code`, after finishing the suite if `finish` is true.
+    fn passes_then_exits(finish: bool, code: u32) -> String {
+        format!(
+            r#"
+            if (process.argv.includes('--list')) {{
+                console.log('a: test');
+                process.exit(0);
+            }}
+            console.log(JSON.stringify({{ type: 'suite', event: 'started', test_count: 1 }}));
+            console.log(JSON.stringify({{ type: 'test', event: 'started', name: 'a' }}));
+            console.log(JSON.stringify({{ type: 'test', name: 'a', event: 'ok', exec_time: 0 }}));
+            if ({})
+                console.log(JSON.stringify({{ type: 'suite', event: 'ok', passed: 1 }}));
+            if ({1})
+                console.error('teardown hook failed');
+            process.exit({1});
+            "#,
+            finish, code,
+        )
+    }
+
+    #[test]
+    fn passing_shard() {
+        let shard = run_script("pass", &passes_then_exits(true, 0));
+        assert_eq!(shard.records, [Record {
+            name: "a".to_string(),
+            outcome: Outcome::Ok,
+            exec_time: 0.0,
+        }]);
+        assert!(!shard.failed);
+        assert_eq!(shard.output, Vec::<String>::new());
+    }
+
+    #[test]
+    fn fails_after_the_suite() {
+        let shard = run_script("after-suite", &passes_then_exits(true, 1));
+        assert_eq!(shard.records.len(), 1);
+        assert!(shard.failed);
+        assert_eq!(shard.output, ["teardown hook failed\n"]);
+    }
+
+    #[test]
+    fn fails_after_the_last_test() {
+        let shard = run_script("after-last-test", &passes_then_exits(false, 1));
+        assert_eq!(shard.records.len(), 1);
+        assert_eq!(shard.records[0].outcome, Outcome::Ok);
+        assert!(shard.failed);
+        assert_eq!(shard.output, ["teardown hook failed\n"]);
+    }
+
+    #[test]
+    fn rejects_bad_formats() {
+        let err = split_format(&os(&["--format"])).err().unwrap();
+        assert_eq!(err.to_string(), "flag --format requires an argument");
+        let err = split_format(&os(&["--format=terse"])).err().unwrap();
+        assert_eq!(err.to_string(), "unsupported --format terse, expected pretty, json or junit");
+    }
+}
@@ -6,11 +6,12 @@ extern crate wasm_bindgen_shared;
 #[macro_use]
 extern crate failure;
 
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process;
 
 use docopt::Docopt;
-use failure::Error;
+use failure::{Error, ResultExt};
 use wasm_bindgen_cli_support::Bindgen;
 
 const USAGE: &'static str = "
@@ -18,22 +19,41 @@ Generating JS bindings for a wasm file
 
 Usage:
     wasm-bindgen [options] <input>
+    wasm-bindgen api-diff <old> <new>
     wasm-bindgen -h | --help
     wasm-bindgen -V | --version
 
 Options:
     -h --help                Show this screen.
     --out-dir DIR            Output directory
-    --nodejs                 Generate output that only works in node.js
-    --browser                Generate output that only works in a browser
-    --no-modules             Generate output that only works in a browser (without modules)
+    --out-name VAR           Set a custom output filename (Without extension. Defaults to crate name)
+    --target TARGET          What type of output to generate, valid
+                             values are [web, bundler, nodejs, no-modules],
+                             and the default is [bundler]
+    --browser                Hint that JS should only be compatible with a browser
     --no-modules-global VAR  Name of the global variable to initialize
+    --nodejs                 Deprecated, use `--target nodejs`
+    --no-modules             Deprecated, use `--target no-modules`
     --typescript             Output a TypeScript definition file (on by default)
     --no-typescript          Don't emit a *.d.ts file
     --debug                  Include otherwise-extraneous debug checks in output
     --no-demangle            Don't demangle Rust symbol names
     --keep-debug             Keep debug sections in wasm files
+    --source-map             Emit a source map mapping the generated JS to Rust
+                             source, which needs the crate to be compiled on
+                             nightly with `--cfg procmacro2_semver_exempt`
+    --threads                Generate a module which shares its memory with threads
+    --reference-types        Pass JS values to and from wasm as `externref`
+    --multi-value            Return strings and vectors as multiple values
+    --bigint                 Pass 64-bit integers to and from wasm as `i64`
+    --profile-boundary       Count and time calls across the JS/wasm boundary
+    --emit-manifest          Write a JSON manifest of all imports and exports
     -V --version             Print the version number of wasm-bindgen
+
+The `api-diff` command compares the API exported by two builds of the same
+wasm file, printing what changed. It exits with status 1 if any of the changes
+would break existing JS callers, and with status 2 if the files couldn't be
+read or compared.
 ";
 
 #[derive(Debug, Deserialize)]
@@ -44,12 +64,24 @@ struct Args {
     flag_typescript: bool,
     flag_no_typescript: bool,
     flag_out_dir: Option<PathBuf>,
+    flag_out_name: Option<String>,
     flag_debug: bool,
     flag_version: bool,
     flag_no_demangle: bool,
     flag_no_modules_global: Option<String>,
     flag_keep_debug: bool,
+    flag_source_map: bool,
+    flag_threads: bool,
+    flag_reference_types: bool,
+    flag_multi_value: bool,
+    flag_bigint: bool,
+    flag_profile_boundary: bool,
+    flag_emit_manifest: bool,
+    flag_target: Option<String>,
     arg_input: Option<PathBuf>,
+    cmd_api_diff: bool,
+    arg_old: Option<PathBuf>,
+    arg_new: Option<PathBuf>,
 }
 
 fn main() {
@@ -61,7 +93,12 @@ fn main() {
         println!("wasm-bindgen {}", wasm_bindgen_shared::version());
         return;
     }
-    let err = match rmain(&args) {
+    let result = if args.cmd_api_diff {
+        api_diff(&args)
+    } else {
+        rmain(&args)
+    };
+    let err = match result {
         Ok(()) => return,
         Err(e) => e,
     };
@@ -69,7 +106,9 @@ fn main() {
     for cause in err.iter_causes() {
         eprintln!("\tcaused by: {}", cause);
     }
-    process::exit(1);
+    // Like `diff`, `api-diff` exits with 1 when it finds a breaking change, so
+    // being unable to compare the files gets a status of its own.
+    process::exit(if args.cmd_api_diff { 2 } else { 1 });
 }
 
 fn rmain(args: &Args) -> Result<(), Error> {
@@ -83,15 +122,34 @@ fn rmain(args: &Args) -> Result<(), Error> {
     let mut b = Bindgen::new();
     b.input_path(input)
         .nodejs(args.flag_nodejs)
-        .browser(args.flag_browser)
         .no_modules(args.flag_no_modules)
+        .browser(args.flag_browser)
         .debug(args.flag_debug)
         .demangle(!args.flag_no_demangle)
         .keep_debug(args.flag_keep_debug)
+        .source_map(args.flag_source_map)
+        .threads(args.flag_threads)
+        .reference_types(args.flag_reference_types)
+        .multi_value(args.flag_multi_value)
+        .bigint(args.flag_bigint)
+        .profile_boundary(args.flag_profile_boundary)
+        .emit_manifest(args.flag_emit_manifest)
         .typescript(typescript);
+    if let Some(ref target) = args.flag_target {
+        match target.as_str() {
+            "bundler" => b.bundler(true),
+            "web" => b.web(true),
+            "no-modules" => b.no_modules(true),
+            "nodejs" => b.nodejs(true),
+            s => bail!("invalid target: `{}`", s),
+        };
+    }
     if let Some(ref name) = args.flag_no_modules_global {
         b.no_modules_global(name);
     }
+    if let Some(ref name) = args.flag_out_name {
+        b.out_name(name);
+    }
 
     let out_dir = match args.flag_out_dir {
         Some(ref p) => p,
@@ -100,3 +158,21 @@ fn rmain(args: &Args) -> Result<(), Error> {
 
     b.generate(out_dir)
 }
+
+fn api_diff(args: &Args) -> Result<(), Error> {
+    let read = |path: &Path| {
+        fs::read(path).with_context(|_| format!("failed to read `{}`", path.display()))
+    };
+    let old = read(args.arg_old.as_ref().unwrap())?;
+    let new = read(args.arg_new.as_ref().unwrap())?;
+    let changes = wasm_bindgen_cli_support::api_diff::diff(&old, &new)?;
+    for change in changes.iter() {
+        println!("{}", change);
+    }
+    let breaking = changes.iter().filter(|c| c.breaking).count();
+    if breaking > 0 {
+        eprintln!("error: found {} breaking change(s)", breaking);
+        process::exit(1);
+    }
+    Ok(())
+}
@@ -466,6 +466,149 @@ extern "C" {
     pub fn values(this: &Array) -> Iterator;
 }
 
+// BigInt
+#[wasm_bindgen]
+extern "C" {
+    #[derive(Clone, Debug)]
+    pub type BigInt;
+
+    #[wasm_bindgen(catch, js_name = BigInt)]
+    fn new_bigint(value: &JsValue) -> Result<BigInt, JsValue>;
+
+    /// The `BigInt.asIntN()` method wraps a BigInt value to a signed integer
+    /// between -2^(width-1) and 2^(width-1)-1.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/BigInt/asIntN)
+    #[wasm_bindgen(static_method_of = BigInt, js_name = asIntN)]
+    pub fn as_int_n(bits: f64, bigint: &BigInt) -> BigInt;
+
+    /// The `BigInt.asUintN()` method wraps a BigInt value to an unsigned
+    /// integer between 0 and 2^width-1.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/BigInt/asUintN)
+    #[wasm_bindgen(static_method_of = BigInt, js_name = asUintN)]
+    pub fn as_uint_n(bits: f64, bigint: &BigInt) -> BigInt;
+
+    /// The `toLocaleString()` method returns a string with a language-sensitive
+    /// representation of this BigInt.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/BigInt/toLocaleString)
+    #[wasm_bindgen(method, js_name = toLocaleString)]
+    pub fn to_locale_string(this: &BigInt, locales: &str) -> JsString;
+
+    /// The `toString()` method returns a string representing the specified
+    /// BigInt value in the specified radix.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/BigInt/toString)
+    #[wasm_bindgen(catch, method, js_name = toString)]
+    pub fn to_string(this: &BigInt, radix: u8) -> Result<JsString, JsValue>;
+
+    /// The `valueOf()` method returns the wrapped primitive value of a BigInt
+    /// object.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/BigInt/valueOf)
+    #[wasm_bindgen(method, js_name = valueOf)]
+    pub fn value_of(this: &BigInt) -> BigInt;
+}
+
+impl BigInt {
+    /// Converts `value` to a BigInt, as the `BigInt()` function does, parsing
+    /// strings and converting integral numbers.
+    ///
+    /// Returns an error if `value` can't be converted, such as a number with a
+    /// fractional part.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/BigInt)
+    pub fn new(value: &JsValue) -> Result<BigInt, JsValue> {
+        new_bigint(value)
+    }
+
+    /// Returns the `BigInt` value of this JS value if it's a BigInt.
+    ///
+    /// If this JS value is not a BigInt then this returns `None`.
+    pub fn try_from(val: &JsValue) -> Option<&BigInt> {
+        if val.is_bigint() {
+            Some(val.unchecked_ref())
+        } else {
+            None
+        }
+    }
+
+    /// Raises this BigInt to the power of `rhs`, as the `**` operator does.
+    pub fn pow(&self, rhs: &BigInt) -> BigInt {
+        self.as_ref().pow(rhs.as_ref()).unchecked_into()
+    }
+}
+
+macro_rules! bigint_from {
+    ($($t:ident)*) => ($(
+        impl From<$t> for BigInt {
+            fn from(n: $t) -> BigInt {
+                JsValue::from(n).unchecked_into()
+            }
+        }
+    )*)
+}
+
+bigint_from! { i8 u8 i16 u16 i32 u32 i64 u64 i128 u128 }
+
+// Arithmetic on BigInts is implemented by the operators on `&JsValue`, which
+// can't fail for two BigInts other than by dividing by zero.
+macro_rules! bigint_ops {
+    ($($t:ident $f:ident)*) => ($(
+        impl<'a> std::ops::$t<&'a BigInt> for &'a BigInt {
+            type Output = BigInt;
+
+            fn $f(self, rhs: &'a BigInt) -> BigInt {
+                std::ops::$t::$f(self.as_ref(), rhs.as_ref()).unchecked_into()
+            }
+        }
+
+        impl std::ops::$t<BigInt> for BigInt {
+            type Output = BigInt;
+
+            fn $f(self, rhs: BigInt) -> BigInt {
+                std::ops::$t::$f(&self, &rhs)
+            }
+        }
+    )*)
+}
+
+bigint_ops! {
+    Add add
+    Sub sub
+    Mul mul
+    Div div
+    Rem rem
+    BitAnd bitand
+    BitOr bitor
+    BitXor bitxor
+    Shl shl
+    Shr shr
+}
+
+impl<'a> std::ops::Neg for &'a BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        (-self.as_ref()).unchecked_into()
+    }
+}
+
+impl std::ops::Neg for BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        -&self
+    }
+}
+
+impl PartialEq for BigInt {
+    fn eq(&self, other: &BigInt) -> bool {
+        self.as_ref() == other.as_ref()
+    }
+}
+
 // Boolean
 #[wasm_bindgen]
 extern "C" {
@@ -702,6 +845,20 @@ extern "C" {
     #[wasm_bindgen(constructor)]
     pub fn new(constructor_arg: &JsValue) -> Float32Array;
 
+    /// A `Float32Array()` constructor which creates a view of `length` elements of the
+    /// `ArrayBuffer` `buffer`, starting at `byte_offset`.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Float32Array)
+    #[wasm_bindgen(constructor)]
+    pub fn new_with_byte_offset_and_length(buffer: &JsValue, byte_offset: u32, length: u32) -> Float32Array;
+
+    /// The `set()` method stores multiple values in the typed array, reading
+    /// input values from a specified array.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/TypedArray/set)
+    #[wasm_bindgen(method)]
+    pub fn set(this: &Float32Array, src: &JsValue, offset: u32);
+
     /// The fill() method fills all the elements of an array from a start index
     /// to an end index with a static value. The end index is not included.
     ///
@@ -755,6 +912,20 @@ extern "C" {
     #[wasm_bindgen(constructor)]
     pub fn new(constructor_arg: &JsValue) -> Float64Array;
 
+    /// A `Float64Array()` constructor which creates a view of `length` elements of the
+    /// `ArrayBuffer` `buffer`, starting at `byte_offset`.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Float64Array)
+    #[wasm_bindgen(constructor)]
+    pub fn new_with_byte_offset_and_length(buffer: &JsValue, byte_offset: u32, length: u32) -> Float64Array;
+
+    /// The `set()` method stores multiple values in the typed array, reading
+    /// input values from a specified array.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/TypedArray/set)
+    #[wasm_bindgen(method)]
+    pub fn set(this: &Float64Array, src: &JsValue, offset: u32);
+
     /// The fill() method fills all the elements of an array from a start index
     /// to an end index with a static value. The end index is not included.
     ///
@@ -943,6 +1114,20 @@ extern "C" {
     #[wasm_bindgen(constructor)]
     pub fn new(constructor_arg: &JsValue) -> Int8Array;
 
+    /// An `Int8Array()` constructor which creates a view of `length` elements of the
+    /// `ArrayBuffer` `buffer`, starting at `byte_offset`.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Int8Array)
+    #[wasm_bindgen(constructor)]
+    pub fn new_with_byte_offset_and_length(buffer: &JsValue, byte_offset: u32, length: u32) -> Int8Array;
+
+    /// The `set()` method stores multiple values in the typed array, reading
+    /// input values from a specified array.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/TypedArray/set)
+    #[wasm_bindgen(method)]
+    pub fn set(this: &Int8Array, src: &JsValue, offset: u32);
+
     /// The fill() method fills all the elements of an array from a start index
     /// to an end index with a static value. The end index is not included.
     ///
@@ -996,6 +1181,20 @@ extern "C" {
     #[wasm_bindgen(constructor)]
     pub fn new(constructor_arg: &JsValue) -> Int16Array;
 
+    /// An `Int16Array()` constructor which creates a view of `length` elements of the
+    /// `ArrayBuffer` `buffer`, starting at `byte_offset`.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Int16Array)
+    #[wasm_bindgen(constructor)]
+    pub fn new_with_byte_offset_and_length(buffer: &JsValue, byte_offset: u32, length: u32) -> Int16Array;
+
+    /// The `set()` method stores multiple values in the typed array, reading
+    /// input values from a specified array.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/TypedArray/set)
+    #[wasm_bindgen(method)]
+    pub fn set(this: &Int16Array, src: &JsValue, offset: u32);
+
     /// The fill() method fills all the elements of an array from a start index
     /// to an end index with a static value. The end index is not included.
     ///
@@ -1049,6 +1248,20 @@ extern "C" {
     #[wasm_bindgen(constructor)]
     pub fn new(constructor_arg: &JsValue) -> Int32Array;
 
+    /// An `Int32Array()` constructor which creates a view of `length` elements of the
+    /// `ArrayBuffer` `buffer`, starting at `byte_offset`.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Int32Array)
+    #[wasm_bindgen(constructor)]
+    pub fn new_with_byte_offset_and_length(buffer: &JsValue, byte_offset: u32, length: u32) -> Int32Array;
+
+    /// The `set()` method stores multiple values in the typed array, reading
+    /// input values from a specified array.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/TypedArray/set)
+    #[wasm_bindgen(method)]
+    pub fn set(this: &Int32Array, src: &JsValue, offset: u32);
+
     /// The fill() method fills all the elements of an array from a start index
     /// to an end index with a static value. The end index is not included.
     ///
@@ -2786,6 +2999,20 @@ extern "C" {
     #[wasm_bindgen(constructor)]
     pub fn new(constructor_arg: &JsValue) -> Uint8Array;
 
+    /// An `Uint8Array()` constructor which creates a view of `length` elements of the
+    /// `ArrayBuffer` `buffer`, starting at `byte_offset`.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Uint8Array)
+    #[wasm_bindgen(constructor)]
+    pub fn new_with_byte_offset_and_length(buffer: &JsValue, byte_offset: u32, length: u32) -> Uint8Array;
+
+    /// The `set()` method stores multiple values in the typed array, reading
+    /// input values from a specified array.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/TypedArray/set)
+    #[wasm_bindgen(method)]
+    pub fn set(this: &Uint8Array, src: &JsValue, offset: u32);
+
     /// The fill() method fills all the elements of an array from a start index
     /// to an end index with a static value. The end index is not included.
     ///
@@ -2841,6 +3068,20 @@ extern "C" {
     #[wasm_bindgen(constructor)]
     pub fn new(constructor_arg: &JsValue) -> Uint8ClampedArray;
 
+    /// An `Uint8ClampedArray()` constructor which creates a view of `length` elements of the
+    /// `ArrayBuffer` `buffer`, starting at `byte_offset`.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Uint8ClampedArray)
+    #[wasm_bindgen(constructor)]
+    pub fn new_with_byte_offset_and_length(buffer: &JsValue, byte_offset: u32, length: u32) -> Uint8ClampedArray;
+
+    /// The `set()` method stores multiple values in the typed array, reading
+    /// input values from a specified array.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/TypedArray/set)
+    #[wasm_bindgen(method)]
+    pub fn set(this: &Uint8ClampedArray, src: &JsValue, offset: u32);
+
     /// The fill() method fills all the elements of an array from a start index
     /// to an end index with a static value. The end index is not included.
     ///
@@ -2894,6 +3135,20 @@ extern "C" {
     #[wasm_bindgen(constructor)]
     pub fn new(constructor_arg: &JsValue) -> Uint16Array;
 
+    /// An `Uint16Array()` constructor which creates a view of `length` elements of the
+    /// `ArrayBuffer` `buffer`, starting at `byte_offset`.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Uint16Array)
+    #[wasm_bindgen(constructor)]
+    pub fn new_with_byte_offset_and_length(buffer: &JsValue, byte_offset: u32, length: u32) -> Uint16Array;
+
+    /// The `set()` method stores multiple values in the typed array, reading
+    /// input values from a specified array.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/TypedArray/set)
+    #[wasm_bindgen(method)]
+    pub fn set(this: &Uint16Array, src: &JsValue, offset: u32);
+
     /// The fill() method fills all the elements of an array from a start index
     /// to an end index with a static value. The end index is not included.
     ///
@@ -2947,6 +3202,20 @@ extern "C" {
     #[wasm_bindgen(constructor)]
     pub fn new(constructor_arg: &JsValue) -> Uint32Array;
 
+    /// An `Uint32Array()` constructor which creates a view of `length` elements of the
+    /// `ArrayBuffer` `buffer`, starting at `byte_offset`.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Uint32Array)
+    #[wasm_bindgen(constructor)]
+    pub fn new_with_byte_offset_and_length(buffer: &JsValue, byte_offset: u32, length: u32) -> Uint32Array;
+
+    /// The `set()` method stores multiple values in the typed array, reading
+    /// input values from a specified array.
+    ///
+    /// [MDN documentation](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/TypedArray/set)
+    #[wasm_bindgen(method)]
+    pub fn set(this: &Uint32Array, src: &JsValue, offset: u32);
+
     /// The fill() method fills all the elements of an array from a start index
     /// to an end index with a static value. The end index is not included.
     ///
@@ -2987,6 +3256,68 @@ extern "C" {
     pub fn byte_offset(this: &Uint32Array) -> u32;
 }
 
+// Views and copies of Rust slices as typed arrays
+macro_rules! typed_array_slices {
+    ($($ty:ident: $elem:ident,)*) => ($(
+        impl $ty {
+            /// Creates a typed array which is a view of the slice `rust` in
+            /// wasm's linear memory, without copying it.
+            ///
+            /// # Unsafety
+            ///
+            /// The view is only valid until wasm's memory grows, after which
+            /// it's detached from the memory's buffer and reads as empty. Any
+            /// allocation, including the ones done in JS glue to pass
+            /// arguments to Rust, may grow the memory, so the view should be
+            /// used immediately and then dropped.
+            ///
+            /// The view also isn't tied to the lifetime of `rust` and allows
+            /// writes from JS even though `rust` is a shared slice.
+            pub unsafe fn view(rust: &[$elem]) -> $ty {
+                let buf = wasm_bindgen::memory();
+                let mem = buf.unchecked_ref::<WebAssembly::Memory>();
+                $ty::new_with_byte_offset_and_length(
+                    &mem.buffer(),
+                    rust.as_ptr() as u32,
+                    rust.len() as u32,
+                )
+            }
+
+            /// Copies the contents of this typed array into the slice `dst`.
+            ///
+            /// # Panics
+            ///
+            /// Panics if this typed array and `dst` have different lengths.
+            pub fn copy_to(&self, dst: &mut [$elem]) {
+                assert_eq!(self.length() as usize, dst.len());
+                unsafe { $ty::view(dst).set(self.as_ref(), 0); }
+            }
+
+            /// Copies the contents of the slice `src` into this typed array.
+            ///
+            /// # Panics
+            ///
+            /// Panics if this typed array and `src` have different lengths.
+            pub fn copy_from(&self, src: &[$elem]) {
+                assert_eq!(self.length() as usize, src.len());
+                unsafe { self.set($ty::view(src).as_ref(), 0); }
+            }
+        }
+    )*)
+}
+
+typed_array_slices! {
+    Int8Array: i8,
+    Int16Array: i16,
+    Int32Array: i32,
+    Uint8Array: u8,
+    Uint8ClampedArray: u8,
+    Uint16Array: u16,
+    Uint32Array: u32,
+    Float32Array: f32,
+    Float64Array: f64,
+}
+
 // URIError
 #[wasm_bindgen]
 extern "C" {
@@ -3856,6 +4187,23 @@ impl JsString {
             None
         }
     }
+
+    /// Creates a JS string from UTF-16 code units, copying them to the JS
+    /// heap without transcoding.
+    ///
+    /// The code units don't have to be valid UTF-16, which lets unpaired
+    /// surrogates round trip through Rust.
+    pub fn from_utf16(units: &[u16]) -> JsString {
+        JsString {
+            obj: JsValue::from_utf16(units),
+        }
+    }
+
+    /// Copies the UTF-16 code units of this string into a new vector,
+    /// avoiding the UTF-8 transcoding done when converting to a `String`.
+    pub fn to_utf16(&self) -> Vec<u16> {
+        self.obj.as_utf16().unwrap()
+    }
 }
 
 impl PartialEq<str> for JsString {
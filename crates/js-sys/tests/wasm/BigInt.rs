@@ -0,0 +1,45 @@
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::*;
+use js_sys::*;
+
+#[wasm_bindgen_test]
+fn new() {
+    assert_eq!(BigInt::new(&JsValue::from("42")).unwrap(), BigInt::from(42));
+    assert_eq!(BigInt::new(&JsValue::from(7)).unwrap(), BigInt::from(7u8));
+    assert!(BigInt::new(&JsValue::from(1.5)).is_err());
+}
+
+#[wasm_bindgen_test]
+fn try_from() {
+    assert!(BigInt::try_from(&JsValue::from(1u64)).is_some());
+    assert!(BigInt::try_from(&JsValue::from(1)).is_none());
+}
+
+#[wasm_bindgen_test]
+fn as_int_n() {
+    let n = BigInt::from(u64::max_value());
+    assert_eq!(BigInt::as_int_n(64.0, &n), BigInt::from(-1));
+    assert_eq!(BigInt::as_uint_n(8.0, &n), BigInt::from(255));
+}
+
+#[wasm_bindgen_test]
+fn to_string() {
+    let n = BigInt::from(255);
+    assert_eq!(String::from(n.to_string(16).unwrap()), "ff");
+    assert!(n.to_string(100).is_err());
+    assert_eq!(n.value_of(), n);
+}
+
+#[wasm_bindgen_test]
+fn ops() {
+    let a = BigInt::from(6);
+    let b = BigInt::from(4);
+    assert_eq!(&a + &b, BigInt::from(10));
+    assert_eq!(&a - &b, BigInt::from(2));
+    assert_eq!(&a / &b, BigInt::from(1));
+    assert_eq!(&a % &b, BigInt::from(2));
+    assert_eq!(&a & &b, BigInt::from(4));
+    assert_eq!(&a ^ &b, BigInt::from(2));
+    assert_eq!(-&a, BigInt::from(-6));
+    assert_eq!(b.pow(&BigInt::from(40)), BigInt::from(1u128 << 80));
+}
@@ -490,6 +490,16 @@ fn value_of() {
     assert_eq!(greeting.value_of(), "Hello world!");
 }
 
+#[wasm_bindgen_test]
+fn utf16() {
+    let units = [0x48, 0xe9, 0xd83d, 0xde00, 0xdc00];
+    let s = JsString::from_utf16(&units);
+    assert_eq!(s.length(), 5);
+    assert_eq!(s.to_utf16(), units);
+    assert_eq!(JsString::from("Héllo").to_utf16(), "Héllo".encode_utf16().collect::<Vec<_>>());
+    assert_eq!(JsString::from_utf16(&[]), "");
+}
+
 #[wasm_bindgen_test]
 fn raw() {
     let call_site = Object::new();
@@ -88,3 +88,35 @@ macro_rules! test_fill {
 fn new_fill() {
     each!(test_fill);
 }
+
+macro_rules! test_view_and_copy {
+    ($arr:ident) => ({
+        let mut rust = [1 as _, 2 as _, 3 as _];
+        let view = unsafe { $arr::view(&rust) };
+        assert_eq!(view.length(), 3);
+        view.for_each(&mut |x, i, _| {
+            assert_eq!(x as f64, (i + 1) as f64);
+        });
+
+        let arr = $arr::new(&3.into());
+        arr.copy_from(&rust);
+        arr.fill(4 as _, 0, 1);
+        arr.copy_to(&mut rust);
+        assert_eq!(rust, [4 as _, 2 as _, 3 as _]);
+    })
+}
+#[wasm_bindgen_test]
+fn view_and_copy() {
+    each!(test_view_and_copy);
+}
+
+#[wasm_bindgen_test]
+fn copy_to_wrong_length() {
+    // The panic traps, which reaches Rust again as the exception of `call0`.
+    let copy = Closure::wrap(Box::new(|| {
+        let mut rust = [0u8; 2];
+        Uint8Array::new(&3.into()).copy_to(&mut rust);
+    }) as Box<FnMut()>);
+    let f: &Function = copy.as_ref().unchecked_ref();
+    assert!(f.call0(&JsValue::NULL).is_err());
+}
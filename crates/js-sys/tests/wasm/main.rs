@@ -11,6 +11,7 @@ pub mod global_fns;
 pub mod Array;
 pub mod ArrayBuffer;
 pub mod ArrayIterator;
+pub mod BigInt;
 pub mod Boolean;
 pub mod DataView;
 pub mod Date;
@@ -192,6 +192,16 @@ impl BindgenAttrs {
             _ => false,
         })
     }
+
+    /// Get the first slices attribute
+    fn slices(&self) -> Option<&Ident> {
+        self.attrs
+            .iter()
+            .filter_map(|a| match a {
+                BindgenAttr::Slices(s) => Some(s),
+                _ => None,
+            }).next()
+    }
 }
 
 impl Parse for BindgenAttrs {
@@ -225,6 +235,7 @@ pub enum BindgenAttr {
     JsClass(String),
     Extends(Ident),
     Variadic,
+    Slices(Ident),
 }
 
 impl Parse for BindgenAttr {
@@ -284,6 +295,10 @@ impl Parse for BindgenAttr {
             input.parse::<Token![=]>()?;
             return Ok(BindgenAttr::Extends(input.parse::<AnyIdent>()?.0))
         }
+        if attr == "slices" {
+            input.parse::<Token![=]>()?;
+            return Ok(BindgenAttr::Slices(input.parse::<AnyIdent>()?.0))
+        }
         if attr == "module" {
             input.parse::<Token![=]>()?;
             return Ok(BindgenAttr::Module(input.parse::<syn::LitStr>()?.value()))
@@ -401,6 +416,16 @@ impl<'a> ConvertToAst<(BindgenAttrs, &'a Option<String>)> for syn::ForeignItemFn
         )?.0;
         let catch = opts.catch();
         let variadic = opts.variadic();
+        let slices = match opts.slices() {
+            None => ast::SliceMode::View,
+            Some(s) if s == "view" => ast::SliceMode::View,
+            Some(s) if s == "copy" => ast::SliceMode::Copy,
+            Some(s) if s == "copy_in_out" => ast::SliceMode::CopyInOut,
+            Some(s) => bail_span!(
+                s,
+                "the `slices` attribute must be one of `view`, `copy`, or `copy_in_out`"
+            ),
+        };
         let js_ret = if catch {
             // TODO: this assumes a whole bunch:
             //
@@ -530,6 +555,7 @@ impl<'a> ConvertToAst<(BindgenAttrs, &'a Option<String>)> for syn::ForeignItemFn
             js_ret,
             catch,
             variadic,
+            slices,
             structural: opts.structural(),
             rust_name: self.ident.clone(),
             shim: Ident::new(&shim, Span::call_site()),
@@ -605,6 +631,7 @@ impl ConvertToAst<BindgenAttrs> for syn::ItemFn {
             bail_span!(self.unsafety, "can only #[wasm_bindgen] safe functions");
         }
         assert_not_variadic(&attrs, &self)?;
+        assert_no_slices(&attrs)?;
 
         Ok(function_from_decl(&self.ident, &attrs, self.decl, self.attrs, self.vis, false, None)?.0)
     }
@@ -854,6 +881,7 @@ impl<'a, 'b> MacroParse<()> for (&'a Ident, &'b mut syn::ImplItem) {
         }
 
         let opts = BindgenAttrs::find(&mut method.attrs)?;
+        assert_no_slices(&opts)?;
         let comments = extract_doc_comments(&method.attrs);
         let is_constructor = opts.constructor();
         let (function, method_self) = function_from_decl(
@@ -1086,6 +1114,16 @@ fn assert_not_variadic(attrs: &BindgenAttrs, span: &dyn ToTokens) -> Result<(),
     Ok(())
 }
 
+/// Exports always receive slices by copying them into wasm memory, so `slices`
+/// only makes sense on imports.
+fn assert_no_slices(attrs: &BindgenAttrs) -> Result<(), Diagnostic> {
+    if let Some(s) = attrs.slices() {
+        bail_span!(s, "the `slices` attribute can only be applied to imported \
+            (`extern`) functions")
+    }
+    Ok(())
+}
+
 /// If the path is a single ident, return it.
 fn extract_path_ident(path: &syn::Path) -> Result<Ident, Diagnostic> {
     if path.leading_colon.is_some() {
@@ -13,3 +13,6 @@ extern "C" {
     #[wasm_bindgen {  }]
     fn bar();
 }
+
+#[wasm_bindgen(slices = copy)]
+pub fn baz(a: &[u8]) {}
@@ -38,4 +38,7 @@ extern "C" {
     fn f() -> Result<>;
     #[wasm_bindgen(catch)]
     fn f() -> Result<'a>;
+
+    #[wasm_bindgen(slices = borrow)]
+    fn f(a: &[u8]);
 }
@@ -38,4 +38,6 @@ impl A {
 
     pub const fn foo() {}
     pub unsafe fn foo() {}
+    #[wasm_bindgen(slices = copy)]
+    pub fn bar(a: &[u8]) {}
 }
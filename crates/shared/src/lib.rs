@@ -44,11 +44,19 @@ pub struct ImportFunction {
     pub shim: String,
     pub catch: bool,
     pub variadic: bool,
+    pub slices: SliceMode,
     pub method: Option<MethodData>,
     pub structural: bool,
     pub function: Function,
 }
 
+#[derive(Deserialize, Serialize, Copy, Clone, PartialEq)]
+pub enum SliceMode {
+    View,
+    Copy,
+    CopyInOut,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct MethodData {
     pub class: String,
@@ -118,6 +126,16 @@ pub struct EnumVariant {
 #[derive(Deserialize, Serialize)]
 pub struct Function {
     pub name: String,
+    pub location: Option<Location>,
+}
+
+/// Where in the Rust source a function was defined, used to generate source
+/// maps for the JS glue.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Location {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
 }
 
 #[derive(Deserialize, Serialize)]
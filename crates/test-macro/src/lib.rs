@@ -18,9 +18,27 @@ pub fn wasm_bindgen_test(
 ) -> proc_macro::TokenStream {
     let mut attr = attr.into_iter();
     let mut async = false;
+    let mut timeout = None;
     while let Some(token) = attr.next() {
         match &token {
             proc_macro::TokenTree::Ident(i) if i.to_string() == "async" => async = true,
+            proc_macro::TokenTree::Ident(i) if i.to_string() == "timeout" => {
+                match (attr.next(), attr.next()) {
+                    (
+                        Some(proc_macro::TokenTree::Punct(ref eq)),
+                        Some(proc_macro::TokenTree::Literal(millis)),
+                    ) if eq.as_char() == '=' => {
+                        let millis = millis
+                            .to_string()
+                            .replace("_", "")
+                            .trim_end_matches("u32")
+                            .parse::<u32>()
+                            .expect("`timeout` must be a number of milliseconds");
+                        timeout = Some(millis);
+                    }
+                    _ => panic!("malformed `#[wasm_bindgen_test]` attribute"),
+                }
+            }
             _ => panic!("malformed `#[wasm_bindgen_test]` attribute"),
         }
         match &attr.next() {
@@ -30,29 +48,21 @@ pub fn wasm_bindgen_test(
         }
     }
 
-    let mut body = TokenStream::from(body).into_iter();
-
-    // Skip over other attributes to `fn #ident ...`, and extract `#ident`
-    let mut leading_tokens = Vec::new();
-    while let Some(token) = body.next() {
-        leading_tokens.push(token.clone());
-        if let TokenTree::Ident(token) = token {
-            if token == "fn" {
-                break
-            }
-        }
-    }
-    let ident = match body.next() {
-        Some(TokenTree::Ident(token)) => token,
-        _ => panic!("expected a function name"),
-    };
+    let TestFn { leading_tokens, ignore, should_panic, ident, body } = test_fn(body);
+    let should_panic = should_panic.unwrap_or(quote! { None });
 
     let mut tokens = Vec::<TokenTree>::new();
 
     let test_body = if async {
-        quote! { cx.execute_async(test_name, #ident); }
+        let timeout = match timeout {
+            Some(millis) => quote! { Some(#millis) },
+            None => quote! { None },
+        };
+        quote! { cx.execute_async(test_name, #ident, #should_panic, #ignore, #timeout); }
+    } else if timeout.is_some() {
+        panic!("`timeout` is only supported on `async` tests")
     } else {
-        quote! { cx.execute_sync(test_name, #ident); }
+        quote! { cx.execute_sync(test_name, #ident, #should_panic, #ignore); }
     };
 
     // We generate a `#[no_mangle]` with a known prefix so the test harness can
@@ -71,9 +81,243 @@ pub fn wasm_bindgen_test(
         }
     }).into_iter());
 
+    // Timeouts are also recorded in a custom section, so the test runner can
+    // wait long enough for them to fire when it's scraping a browser's output.
+    if let Some(millis) = timeout {
+        let name = format!("__WBG_TEST_TIMEOUT_{}", CNT.fetch_add(1, Ordering::SeqCst));
+        let name = Ident::new(&name, Span::call_site());
+        let bytes = (0..4).map(|i| (millis >> (i * 8)) as u8);
+        tokens.extend((quote! {
+            #[link_section = "__wasm_bindgen_test_timeouts"]
+            #[cfg(target_arch = "wasm32")]
+            #[doc(hidden)]
+            pub static #name: [u8; 4] = [#(#bytes),*];
+        }).into_iter());
+    }
+
     tokens.extend(leading_tokens);
     tokens.push(ident.into());
     tokens.extend(body);
 
     tokens.into_iter().collect::<TokenStream>().into()
 }
+
+#[proc_macro_attribute]
+pub fn wasm_bindgen_bench(
+    attr: proc_macro::TokenStream,
+    body: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    if !attr.is_empty() {
+        panic!("malformed `#[wasm_bindgen_bench]` attribute");
+    }
+    let TestFn { leading_tokens, ignore, should_panic, ident, body } = test_fn(body);
+    if should_panic.is_some() {
+        panic!("`#[should_panic]` isn't supported on benchmarks");
+    }
+
+    // Benchmarks are exported the same way as tests, so the test runner
+    // doesn't need to tell them apart.
+    let name = format!("__wbg_test_{}_{}", ident, CNT.fetch_add(1, Ordering::SeqCst));
+    let name = Ident::new(&name, Span::call_site());
+    let mut tokens = quote! {
+        #[no_mangle]
+        pub extern fn #name(cx: *const ::wasm_bindgen_test::__rt::Context) {
+            unsafe {
+                let cx = &*cx;
+                let test_name = concat!(module_path!(), "::", stringify!(#ident));
+                cx.execute_bench(test_name, #ident, #ignore);
+            }
+        }
+    };
+    tokens.extend(leading_tokens);
+    tokens.extend(Some(TokenTree::from(ident)));
+    tokens.extend(body);
+    tokens.into()
+}
+
+#[proc_macro_attribute]
+pub fn wasm_bindgen_test_setup(
+    attr: proc_macro::TokenStream,
+    body: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    hook("setup", attr, body)
+}
+
+#[proc_macro_attribute]
+pub fn wasm_bindgen_test_teardown(
+    attr: proc_macro::TokenStream,
+    body: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    hook("teardown", attr, body)
+}
+
+/// Expands `#[wasm_bindgen_test_setup]` or `#[wasm_bindgen_test_teardown]`,
+/// which accept the `async` and `once` options.
+fn hook(
+    kind: &str,
+    attr: proc_macro::TokenStream,
+    body: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let mut attr = attr.into_iter();
+    let mut async = false;
+    let mut once = false;
+    while let Some(token) = attr.next() {
+        match &token {
+            proc_macro::TokenTree::Ident(i) if i.to_string() == "async" => async = true,
+            proc_macro::TokenTree::Ident(i) if i.to_string() == "once" => once = true,
+            _ => panic!("malformed `#[wasm_bindgen_test_{}]` attribute", kind),
+        }
+        match &attr.next() {
+            Some(proc_macro::TokenTree::Punct(op)) if op.as_char() == ',' => {}
+            Some(_) => panic!("malformed `#[wasm_bindgen_test_{}]` attribute", kind),
+            None => break,
+        }
+    }
+
+    // Find `#ident` in `fn #ident ...`, past any attributes and visibility.
+    let body = TokenStream::from(body);
+    let mut tokens = body.clone().into_iter();
+    let mut ident = None;
+    while let Some(token) = tokens.next() {
+        if let TokenTree::Ident(token) = token {
+            if token == "fn" {
+                ident = tokens.next();
+                break
+            }
+        }
+    }
+    let ident = match ident {
+        Some(TokenTree::Ident(ident)) => ident,
+        _ => panic!("expected a function name"),
+    };
+
+    // Like tests, hooks are registered through a `#[no_mangle]` function with
+    // a known prefix which the test runner finds and hands to the harness.
+    let name = format!("__wbg_{}_{}_{}", kind, ident, CNT.fetch_add(1, Ordering::SeqCst));
+    let name = Ident::new(&name, Span::call_site());
+    let method = if async {
+        format!("{}_async", kind)
+    } else {
+        format!("{}_sync", kind)
+    };
+    let method = Ident::new(&method, Span::call_site());
+    let mut tokens = quote! {
+        #[no_mangle]
+        pub extern fn #name(cx: *const ::wasm_bindgen_test::__rt::Context) {
+            unsafe {
+                (*cx).#method(#ident, #once);
+            }
+        }
+    };
+    tokens.extend(body);
+    tokens.into()
+}
+
+/// A test or benchmark function, split at its name.
+struct TestFn {
+    /// Everything before the name, up to and including `fn`, less the
+    /// attributes below.
+    leading_tokens: Vec<TokenTree>,
+    /// `None` or `Some(reason)`, from `#[ignore]`.
+    ignore: TokenStream,
+    /// `None` or `Some(expected)` if the function has `#[should_panic]`.
+    should_panic: Option<TokenStream>,
+    ident: Ident,
+    /// Everything after the name.
+    body: token_stream::IntoIter,
+}
+
+fn test_fn(body: proc_macro::TokenStream) -> TestFn {
+    let mut body = TokenStream::from(body).into_iter();
+
+    // Skip over other attributes to `fn #ident ...`, and extract `#ident`.
+    // Along the way `#[ignore]` and `#[should_panic]` are picked out and
+    // removed, since they're implemented by the test harness.
+    let mut leading_tokens = Vec::new();
+    let mut ignore = quote! { None };
+    let mut should_panic = None;
+    while let Some(token) = body.next() {
+        if let TokenTree::Punct(p) = &token {
+            if p.as_char() == '#' {
+                let attr = match body.next() {
+                    Some(TokenTree::Group(g)) => g,
+                    _ => panic!("expected an attribute"),
+                };
+                match test_attribute(&attr) {
+                    Some(TestAttribute::Ignore(reason)) => {
+                        ignore = quote! { Some(#reason) };
+                    }
+                    Some(TestAttribute::ShouldPanic(expected)) => {
+                        should_panic = Some(quote! { Some(#expected) });
+                    }
+                    None => {
+                        leading_tokens.push(token.clone());
+                        leading_tokens.push(attr.into());
+                    }
+                }
+                continue
+            }
+        }
+        leading_tokens.push(token.clone());
+        if let TokenTree::Ident(token) = token {
+            if token == "fn" {
+                break
+            }
+        }
+    }
+    let ident = match body.next() {
+        Some(TokenTree::Ident(token)) => token,
+        _ => panic!("expected a function name"),
+    };
+    TestFn { leading_tokens, ignore, should_panic, ident, body }
+}
+
+enum TestAttribute {
+    /// `#[ignore]` or `#[ignore = "reason"]`, with `None` or `Some(reason)`.
+    Ignore(TokenStream),
+    /// `#[should_panic]` or `#[should_panic(expected = "message")]`, with
+    /// `None` or `Some(message)`.
+    ShouldPanic(TokenStream),
+}
+
+/// Parses the contents of the `[...]` of an attribute on a test, returning
+/// `None` if it's not one the test harness implements.
+fn test_attribute(attr: &Group) -> Option<TestAttribute> {
+    let mut tokens = attr.stream().into_iter();
+    let name = match tokens.next() {
+        Some(TokenTree::Ident(name)) => name.to_string(),
+        _ => return None,
+    };
+    match &name[..] {
+        "ignore" => {
+            let reason = match (tokens.next(), tokens.next()) {
+                (None, _) => quote! { None },
+                (Some(TokenTree::Punct(ref p)), Some(TokenTree::Literal(reason)))
+                    if p.as_char() == '=' =>
+                {
+                    quote! { Some(#reason) }
+                }
+                _ => panic!("malformed `#[ignore]` attribute"),
+            };
+            Some(TestAttribute::Ignore(reason))
+        }
+        "should_panic" => {
+            let args = match tokens.next() {
+                None => return Some(TestAttribute::ShouldPanic(quote! { None })),
+                Some(TokenTree::Group(ref g)) if g.delimiter() == Delimiter::Parenthesis => {
+                    g.stream().into_iter().collect::<Vec<_>>()
+                }
+                _ => panic!("malformed `#[should_panic]` attribute"),
+            };
+            match &args[..] {
+                [TokenTree::Ident(key), TokenTree::Punct(eq), TokenTree::Literal(expected)]
+                    if key == "expected" && eq.as_char() == '=' =>
+                {
+                    Some(TestAttribute::ShouldPanic(quote! { Some(#expected) }))
+                }
+                _ => panic!("malformed `#[should_panic]` attribute"),
+            }
+        }
+        _ => None,
+    }
+}
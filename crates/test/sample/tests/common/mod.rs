@@ -43,3 +43,40 @@ fn fail_after_3s() -> impl Future<Item = (), Error = JsValue> {
         })
 }
 
+
+#[wasm_bindgen_bench]
+fn bench_sum(b: &mut Bencher) {
+    b.iter(|| (0..black_box(1000u32)).sum::<u32>());
+}
+
+#[wasm_bindgen_test(async, timeout = 1000)]
+fn fail_after_timeout() -> impl Future<Item = (), Error = JsValue> {
+    console_log!("log before timing out");
+    Timeout::new(Duration::new(2, 0)).map(|()| {
+        console_log!("DO NOT SEE ME");
+    })
+}
+
+#[wasm_bindgen_test]
+#[should_panic(expected = "a different message")]
+fn fail_with_unexpected_panic() {
+    panic!("this message doesn't contain the expected one");
+}
+
+#[wasm_bindgen_test]
+#[should_panic]
+fn fail_without_panicking() {
+    console_log!("should have panicked");
+}
+
+#[wasm_bindgen_test]
+#[ignore]
+fn ignored() {
+    console_log!("DO NOT SEE ME");
+}
+
+#[wasm_bindgen_test]
+#[ignore = "shows its reason"]
+fn ignored_with_reason() {
+    console_log!("DO NOT SEE ME");
+}
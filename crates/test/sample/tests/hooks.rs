@@ -0,0 +1,35 @@
+extern crate futures;
+extern crate sample;
+extern crate wasm_bindgen;
+extern crate wasm_bindgen_test;
+
+use std::time::Duration;
+
+use futures::prelude::*;
+use sample::Timeout;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen_test_setup(async, once)]
+fn fail_setup_after_1s() -> impl Future<Item = (), Error = JsValue> {
+    console_log!("once setup log");
+    Timeout::new(Duration::new(1, 0)).map(|()| {
+        panic!("this setup hook fails every test");
+    })
+}
+
+#[wasm_bindgen_test_teardown(once)]
+fn teardown() {
+    console_log!("once teardown still runs");
+}
+
+#[wasm_bindgen_test]
+fn fail_because_of_setup() {
+    console_log!("DO NOT SEE ME");
+}
+
+#[wasm_bindgen_test(async)]
+fn fail_async_because_of_setup() -> impl Future<Item = (), Error = JsValue> {
+    console_log!("DO NOT SEE ME");
+    Timeout::new(Duration::new(0, 0))
+}
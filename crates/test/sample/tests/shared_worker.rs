@@ -0,0 +1,10 @@
+extern crate futures;
+extern crate js_sys;
+extern crate sample;
+extern crate wasm_bindgen;
+extern crate wasm_bindgen_test;
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_shared_worker);
+
+pub mod common;
+pub mod worker;
@@ -0,0 +1,8 @@
+use js_sys::eval;
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen_test]
+fn runs_in_a_worker() {
+    let in_worker = eval("self instanceof WorkerGlobalScope").unwrap();
+    assert_eq!(in_worker.as_bool(), Some(true));
+}
@@ -13,7 +13,11 @@ extern crate wasm_bindgen;
 extern crate wasm_bindgen_futures;
 extern crate wasm_bindgen_test_macro;
 
-pub use wasm_bindgen_test_macro::wasm_bindgen_test;
+pub use wasm_bindgen_test_macro::{
+    wasm_bindgen_bench, wasm_bindgen_test, wasm_bindgen_test_setup, wasm_bindgen_test_teardown,
+};
+
+pub use __rt::bench::{black_box, Bencher};
 
 /// Helper macro which acts like `println!` only routes to `console.log`
 /// instead.
@@ -36,6 +40,12 @@ macro_rules! console_log {
 ///
 /// * `run_in_browser` - requires that this test is run in a browser rather than
 ///   node.js, which is the default for executing tests.
+/// * `run_in_dedicated_worker` - runs the tests in a browser inside of a
+///   dedicated web worker rather than on the page itself.
+/// * `run_in_shared_worker` - runs the tests in a browser inside of a shared
+///   web worker.
+/// * `run_in_service_worker` - runs the tests in a browser inside of a service
+///   worker.
 ///
 /// This macro may be invoked at most one time per test suite (an entire binary
 /// like `tests/foo.rs`, not per module)
@@ -47,6 +57,24 @@ macro_rules! wasm_bindgen_test_configure {
         pub static __WBG_TEST_RUN_IN_BROWSER: [u8; 1] = [0x01];
         $crate::wasm_bindgen_test_configure!($($others)*);
     );
+    (run_in_dedicated_worker $($others:tt)*) => (
+        #[link_section = "__wasm_bindgen_test_unstable"]
+        #[cfg(target_arch = "wasm32")]
+        pub static __WBG_TEST_RUN_IN_DEDICATED_WORKER: [u8; 1] = [0x02];
+        $crate::wasm_bindgen_test_configure!($($others)*);
+    );
+    (run_in_shared_worker $($others:tt)*) => (
+        #[link_section = "__wasm_bindgen_test_unstable"]
+        #[cfg(target_arch = "wasm32")]
+        pub static __WBG_TEST_RUN_IN_SHARED_WORKER: [u8; 1] = [0x03];
+        $crate::wasm_bindgen_test_configure!($($others)*);
+    );
+    (run_in_service_worker $($others:tt)*) => (
+        #[link_section = "__wasm_bindgen_test_unstable"]
+        #[cfg(target_arch = "wasm32")]
+        pub static __WBG_TEST_RUN_IN_SERVICE_WORKER: [u8; 1] = [0x04];
+        $crate::wasm_bindgen_test_configure!($($others)*);
+    );
     () => ()
 }
 
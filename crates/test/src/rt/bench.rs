@@ -0,0 +1,177 @@
+//! Support for `#[wasm_bindgen_bench]` benchmarks, timed with
+//! `performance.now()`.
+//!
+//! Like libtest, benchmarks only measure anything when the harness is passed
+//! `--bench`, which `cargo bench` does. Otherwise they're run once as tests to
+//! check that they work.
+
+use std::mem;
+use std::ptr;
+
+use wasm_bindgen::prelude::*;
+
+use super::report::Summary;
+
+/// Each sample runs the benchmark enough times to take at least this many
+/// milliseconds, since timers in browsers can be quite coarse.
+const SAMPLE_MS: f64 = 1.0;
+
+/// How long to run the benchmark before taking samples, in milliseconds.
+const WARMUP_MS: f64 = 100.0;
+
+/// How many samples to take.
+const SAMPLES: usize = 50;
+
+#[wasm_bindgen]
+extern {
+    #[wasm_bindgen(js_namespace = performance)]
+    fn now() -> f64;
+}
+
+/// Manager of the benchmarking runs, passed to each `#[wasm_bindgen_bench]`
+/// function.
+///
+/// This works like libtest's `Bencher`, with the benchmark calling `iter`
+/// with the code to measure.
+pub struct Bencher {
+    measure: bool,
+    summary: Option<Summary>,
+}
+
+impl Bencher {
+    /// Creates a bencher which measures its benchmark if `measure` is true,
+    /// or otherwise runs it just once.
+    pub fn new(measure: bool) -> Bencher {
+        Bencher {
+            measure,
+            summary: None,
+        }
+    }
+
+    /// Times `inner`, running it repeatedly.
+    ///
+    /// The return value of `inner` is passed through `black_box` so the
+    /// computation of it isn't optimized away.
+    pub fn iter<T, F>(&mut self, mut inner: F)
+        where F: FnMut() -> T,
+    {
+        if !self.measure {
+            black_box(inner());
+            return
+        }
+
+        // Figure out how many iterations make up a sample, doubling until a
+        // sample is long enough to time.
+        let mut iters = 1u64;
+        while time(iters, &mut inner) < SAMPLE_MS && iters < 1 << 40 {
+            iters *= 2;
+        }
+
+        let start = now();
+        while now() - start < WARMUP_MS {
+            time(iters, &mut inner);
+        }
+
+        let mut samples = (0..SAMPLES)
+            .map(|_| time(iters, &mut inner) * 1_000_000.0 / iters as f64)
+            .collect::<Vec<_>>();
+        self.summary = Some(summarize(&mut samples));
+    }
+
+    /// The statistics of the benchmark, if it was measured.
+    pub fn summary(&self) -> Option<Summary> {
+        self.summary
+    }
+}
+
+/// Runs `inner` `iters` times, returning how long that took in milliseconds.
+fn time<T>(iters: u64, inner: &mut FnMut() -> T) -> f64 {
+    let start = now();
+    for _ in 0..iters {
+        black_box(inner());
+    }
+    now() - start
+}
+
+/// Summarizes `samples`, sorting them.
+fn summarize(samples: &mut [f64]) -> Summary {
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    // Clamp the slowest and fastest 5% of samples, as libtest does, so a few
+    // outliers don't skew the results.
+    let low = percentile(samples, 5.0);
+    let high = percentile(samples, 95.0);
+    for sample in samples.iter_mut() {
+        *sample = sample.max(low).min(high);
+    }
+
+    Summary {
+        mean: samples.iter().sum::<f64>() / samples.len() as f64,
+        median: percentile(samples, 50.0),
+        deviation: samples[samples.len() - 1] - samples[0],
+    }
+}
+
+/// The `pct` percentile of the sorted `samples`, interpolating between the
+/// two closest samples.
+fn percentile(samples: &[f64], pct: f64) -> f64 {
+    let rank = pct / 100.0 * (samples.len() - 1) as f64;
+    let low = rank.floor() as usize;
+    let high = (low + 1).min(samples.len() - 1);
+    samples[low] + (samples[high] - samples[low]) * (rank - low as f64)
+}
+
+/// A function which the compiler can't see through, to keep benchmarks from
+/// being optimized away.
+pub fn black_box<T>(dummy: T) -> T {
+    unsafe {
+        let ret = ptr::read_volatile(&dummy);
+        mem::forget(dummy);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn percentiles_interpolate() {
+        let samples = [1.0, 2.0, 4.0, 8.0, 16.0];
+        assert_eq!(percentile(&samples, 0.0), 1.0);
+        assert_eq!(percentile(&samples, 50.0), 4.0);
+        assert_eq!(percentile(&samples, 100.0), 16.0);
+        assert_eq!(percentile(&samples, 25.0), 2.0);
+        assert_eq!(percentile(&samples, 62.5), 6.0);
+        assert_eq!(percentile(&[3.0], 95.0), 3.0);
+    }
+
+    #[test]
+    fn summarizes_sorted_samples() {
+        let mut samples = [5.0, 1.0, 3.0, 2.0, 4.0];
+        let summary = summarize(&mut samples);
+        // The slowest and fastest 5% are clamped to 1.2 and 4.8.
+        for (sample, expected) in samples.iter().zip(&[1.2, 2.0, 3.0, 4.0, 4.8]) {
+            assert_close(*sample, *expected);
+        }
+        assert_close(summary.median, 3.0);
+        assert_close(summary.mean, 3.0);
+        assert_close(summary.deviation, 3.6);
+    }
+
+    #[test]
+    fn outliers_are_clamped() {
+        let mut samples = vec![10.0; 19];
+        samples.push(1000.0);
+        let summary = summarize(&mut samples);
+        // Only the last 5% is interpolated towards the outlier.
+        assert_close(samples[19], 59.5);
+        assert_close(summary.median, 10.0);
+        assert_close(summary.mean, 12.475);
+        assert_close(summary.deviation, 49.5);
+    }
+}
@@ -46,40 +46,50 @@ impl Browser {
 
 impl super::Formatter for Browser {
     fn writeln(&self, line: &str) {
+        // Escape the line so it shows up verbatim in the page's text, which
+        // matters for output like `--format junit`'s XML.
         let mut html = self.pre.inner_html();
-        html.push_str(&line);
+        for c in line.chars() {
+            match c {
+                '<' => html.push_str("&lt;"),
+                '>' => html.push_str("&gt;"),
+                '&' => html.push_str("&amp;"),
+                c => html.push(c),
+            }
+        }
         html.push_str("\n");
         self.pre.set_inner_html(&html);
     }
 
-    fn log_test(&self, name: &str, result: &Result<(), JsValue>) {
-        let s = if result.is_ok() { "ok" } else { "FAIL" };
-        self.writeln(&format!("test {} ... {}", name, s));
-    }
-
     fn stringify_error(&self, err: &JsValue) -> String {
-        // TODO: this should be a checked cast to `Error`
-        let err = Error::from(err.clone());
-        let name = String::from(err.name());
-        let message = String::from(err.message());
-        let err = BrowserError::from(JsValue::from(err));
-        let stack = err.stack();
+        stringify_error(err)
+    }
+}
 
-        let header = format!("{}: {}", name, message);
-        let stack = match stack.as_string() {
-            Some(stack) => stack,
-            None => return header,
-        };
+/// Renders a thrown value with the browser's `name`, `message` and `stack`
+/// properties of errors.
+pub fn stringify_error(err: &JsValue) -> String {
+    // TODO: this should be a checked cast to `Error`
+    let err = Error::from(err.clone());
+    let name = String::from(err.name());
+    let message = String::from(err.message());
+    let err = BrowserError::from(JsValue::from(err));
+    let stack = err.stack();
 
-        // If the `stack` variable contains the name/message already, this is
-        // probably a chome-like error which is already rendered well, so just
-        // return this info
-        if stack.contains(&header) {
-            return stack
-        }
+    let header = format!("{}: {}", name, message);
+    let stack = match stack.as_string() {
+        Some(stack) => stack,
+        None => return header,
+    };
 
-        // Fallback to make sure we don't lose any info
-        format!("{}\n{}", header, stack)
+    // If the `stack` variable contains the name/message already, this is
+    // probably a chome-like error which is already rendered well, so just
+    // return this info
+    if stack.contains(&header) {
+        return stack
     }
+
+    // Fallback to make sure we don't lose any info
+    format!("{}\n{}", header, stack)
 }
 
@@ -1,4 +1,4 @@
-//! Runtime detection of whether we're in node.js or a browser.
+//! Runtime detection of whether we're in node.js, a browser or a worker.
 
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
@@ -9,6 +9,8 @@ extern {
     type This;
     #[wasm_bindgen(method, getter, structural, js_name = self)]
     fn self_(me: &This) -> JsValue;
+    #[wasm_bindgen(method, getter, structural)]
+    fn document(me: &This) -> JsValue;
 }
 
 /// Returns whether it's likely we're executing in a browser environment, as
@@ -19,3 +21,10 @@ pub fn is_browser() -> bool {
     // browsers.
     js_sys::global().unchecked_into::<This>().self_() != JsValue::undefined()
 }
+
+/// Returns whether we're executing in a web worker, which is a browser
+/// environment without a `document`.
+pub fn is_worker() -> bool {
+    let global = js_sys::global().unchecked_into::<This>();
+    is_browser() && global.document() == JsValue::undefined()
+}
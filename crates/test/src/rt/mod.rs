@@ -90,14 +90,15 @@
 
 use std::cell::{RefCell, Cell};
 use std::fmt;
+use std::panic;
 use std::rc::Rc;
 
 use console_error_panic_hook;
 use futures::future;
 use futures::prelude::*;
-use js_sys::{Array, Function, Promise};
+use js_sys::{Array, Date, Error, Function, Promise};
 use wasm_bindgen::prelude::*;
-use wasm_bindgen_futures::future_to_promise;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
 
 // Maximum number of tests to execute concurrently. Eventually this should be a
 // configuration option specified at runtime or at compile time rather than
@@ -107,9 +108,19 @@ use wasm_bindgen_futures::future_to_promise;
 // conccurrently doing things by default would likely end up in a bad situation.
 const CONCURRENCY: usize = 1;
 
+// How long an async test may run before it's failed, in milliseconds, unless
+// it specifies its own with `#[wasm_bindgen_test(async, timeout = ...)]`.
+const DEFAULT_TIMEOUT_MS: u32 = 10_000;
+
+pub mod bench;
 pub mod node;
 pub mod browser;
 pub mod detect;
+pub mod worker;
+mod report;
+
+use self::bench::Bencher;
+use self::report::{Outcome, Record, Summary};
 
 /// Runtime test harness support instantiated in JS.
 ///
@@ -121,11 +132,42 @@ pub struct Context {
 }
 
 struct State {
-    /// An optional filter used to restrict which tests are actually executed
-    /// and which are ignored. This is passed via the `args` function which
-    /// comes from the command line of `wasm-bindgen-test-runner`. Currently
-    /// this is the only "CLI option"
-    filter: RefCell<Option<String>>,
+    /// Filters used to restrict which tests are actually executed, a test
+    /// runs if its name contains any of them (or is equal to one of them
+    /// with `--exact`). This is passed via the `args` function which comes
+    /// from the command line of `wasm-bindgen-test-runner`.
+    filters: RefCell<Vec<String>>,
+
+    /// Tests whose names contain any of these, passed with `--skip`, aren't
+    /// executed.
+    skip: RefCell<Vec<String>>,
+
+    /// Whether filters must match test names exactly, with `--exact`.
+    exact: Cell<bool>,
+
+    /// Whether tests marked as ignored are run, with `--ignored` or
+    /// `--include-ignored`.
+    run_ignored: Cell<RunIgnored>,
+
+    /// Whether to list the tests rather than running them, with `--list`.
+    list: Cell<bool>,
+
+    /// Whether to print the output of tests as it happens rather than
+    /// capturing it, with `--nocapture`.
+    nocapture: Cell<bool>,
+
+    /// Whether to measure benchmarks, skipping tests, with `--bench`.
+    bench: Cell<bool>,
+
+    /// How results are reported, with `--format`.
+    format: Cell<Format>,
+
+    /// Every finished test, for `--format junit` which reports them all at
+    /// the end.
+    records: RefCell<Vec<Record>>,
+
+    /// When `run` started executing tests, from `Date.now()`.
+    start: Cell<f64>,
 
     /// Counter of the number of tests that have succeeded.
     succeeded: Cell<usize>,
@@ -133,6 +175,16 @@ struct State {
     /// Counter of the number of tests that have been ignored
     ignored: Cell<usize>,
 
+    /// Counter of the number of benchmarks that have been measured.
+    measured: Cell<usize>,
+
+    /// Tests which are ignored, along with the reason they're ignored for if
+    /// one was given, waiting to be reported as such.
+    ignored_tests: RefCell<Vec<(String, Option<&'static str>)>>,
+
+    /// Counter of the number of tests that were excluded by filters.
+    filtered_out: Cell<usize>,
+
     /// A list of all tests which have failed.
     ///
     /// Each test listed here is paired with a `JsValue` that represents the
@@ -147,11 +199,54 @@ struct State {
     /// of asynchronous work, so they're sitting on the running list.
     running: RefCell<Vec<Test>>,
 
+    /// Setup and teardown hooks registered with
+    /// `#[wasm_bindgen_test_setup]` and `#[wasm_bindgen_test_teardown]`.
+    hooks: Rc<Hooks>,
+
+    /// The error thrown by a `once` setup hook, which fails every test
+    /// rather than running it.
+    setup_error: RefCell<Option<JsValue>>,
+
     /// How to actually format output, either node.js or browser-specific
     /// implementation.
     formatter: Box<Formatter>,
 }
 
+/// A setup or teardown hook, creating a future to run each time it's used.
+type Hook = Rc<Fn() -> Box<Future<Item = (), Error = JsValue>>>;
+
+#[derive(Default)]
+struct Hooks {
+    /// Hooks run before each test, in the order they were registered.
+    setup: RefCell<Vec<Hook>>,
+    /// Hooks run after each test, whether or not it passed.
+    teardown: RefCell<Vec<Hook>>,
+    /// Hooks run once before any test.
+    setup_once: RefCell<Vec<Hook>>,
+    /// Hooks run once after all tests have finished.
+    teardown_once: RefCell<Vec<Hook>>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum RunIgnored {
+    /// Only run tests which aren't ignored, the default.
+    No,
+    /// Run both ignored and non-ignored tests.
+    Yes,
+    /// Only run ignored tests.
+    Only,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Format {
+    /// Human readable output like libtest's, the default.
+    Pretty,
+    /// One JSON object per line like libtest's `--format json`.
+    Json,
+    /// A JUnit XML document once all tests are done.
+    Junit,
+}
+
 /// Representation of one test that needs to be executed.
 ///
 /// Tests are all represented as futures, and tests perform no work until their
@@ -160,6 +255,14 @@ struct Test {
     name: String,
     future: Box<Future<Item = (), Error = JsValue>>,
     output: Rc<RefCell<Output>>,
+    /// Whether this test is `#[should_panic]`, and with which `expected`
+    /// message.
+    should_panic: Option<Option<&'static str>>,
+    /// When this test started executing, from `Date.now()`.
+    start: f64,
+    /// Where a benchmark stores its statistics once measured, or `None` if
+    /// this isn't a benchmark.
+    bench: Option<Rc<Cell<Option<Summary>>>>,
 }
 
 /// Captured output of each test.
@@ -167,15 +270,14 @@ struct Test {
 struct Output {
     log: String,
     error: String,
+    /// The message of the panic which made the test fail, if it panicked.
+    panic: Option<String>,
 }
 
 trait Formatter {
     /// Writes a line of output, typically status information.
     fn writeln(&self, line: &str);
 
-    /// Log the result of a test, either passing or failing.
-    fn log_test(&self, name: &str, result: &Result<(), JsValue>);
-
     /// Convert a thrown value into a string, using platform-specific apis
     /// perhaps to turn the error into a string.
     fn stringify_error(&self, val: &JsValue) -> String;
@@ -206,20 +308,48 @@ impl Context {
     /// tests.
     #[wasm_bindgen(constructor)]
     pub fn new() -> Context {
-        console_error_panic_hook::set_once();
+        // Record panic messages so `#[should_panic(expected = "...")]` can be
+        // checked, before printing them out as usual.
+        panic::set_hook(Box::new(|info| {
+            let payload = info.payload();
+            let message = match payload.downcast_ref::<&str>() {
+                Some(s) => s.to_string(),
+                None => match payload.downcast_ref::<String>() {
+                    Some(s) => s.clone(),
+                    None => String::new(),
+                },
+            };
+            LAST_PANIC.with(|p| *p.borrow_mut() = Some(message));
+            console_error_panic_hook::hook(info);
+        }));
 
-        let formatter = match node::Node::new() {
-            Some(node) => Box::new(node) as Box<Formatter>,
-            None => Box::new(browser::Browser::new()),
+        let formatter = match (node::Node::new(), worker::Worker::new()) {
+            (Some(node), _) => Box::new(node) as Box<Formatter>,
+            (None, Some(worker)) => Box::new(worker),
+            (None, None) => Box::new(browser::Browser::new()),
         };
         Context {
             state: Rc::new(State {
-                filter: Default::default(),
+                filters: Default::default(),
+                skip: Default::default(),
+                exact: Cell::new(false),
+                run_ignored: Cell::new(RunIgnored::No),
+                list: Cell::new(false),
+                nocapture: Cell::new(false),
+                bench: Cell::new(false),
+                format: Cell::new(Format::Pretty),
+                records: Default::default(),
+                start: Cell::new(0.0),
                 failures: Default::default(),
                 ignored: Default::default(),
+                measured: Default::default(),
+                ignored_tests: Default::default(),
+                filtered_out: Default::default(),
                 remaining: Default::default(),
                 running: Default::default(),
                 succeeded: Default::default(),
+                hooks: Default::default(),
+                setup_error: Default::default(),
                 formatter,
             }),
         }
@@ -228,23 +358,44 @@ impl Context {
     /// Inform this context about runtime arguments passed to the test
     /// harness.
     ///
-    /// Eventually this will be used to support flags, but for now it's just
-    /// used to support test filters.
+    /// These follow libtest's command line: any number of test filters along
+    /// with the `--exact`, `--skip`, `--ignored`, `--include-ignored`,
+    /// `--list`, `--nocapture`, `--bench` and `--format` flags. Any other
+    /// flag is rejected.
     pub fn args(&mut self, args: Vec<JsValue>) {
-        // Here we want to reject all flags like `--foo` or `-f` as we don't
-        // support anything, and also we only support at most one non-flag
-        // argument as a test filter.
-        //
-        // Everything else is rejected.
-        let mut filter = self.state.filter.borrow_mut();
-        for arg in args {
-            let arg = arg.as_string().unwrap();
-            if arg.starts_with("-") {
-                panic!("flag {} not supported", arg);
-            } else if filter.is_some() {
-                panic!("more than one filter argument cannot be passed");
+        let mut args = args.into_iter().map(|arg| arg.as_string().unwrap());
+        while let Some(arg) = args.next() {
+            match &arg[..] {
+                "--format" => match args.next() {
+                    Some(format) => self.state.set_format(&format),
+                    None => panic!("flag --format requires an argument"),
+                },
+                s if s.starts_with("--format=") => {
+                    self.state.set_format(&s["--format=".len()..]);
+                }
+                // libtest requires this for `--format json`, so accept it for
+                // compatibility.
+                "-Zunstable-options" => {}
+                "-Z" => match args.next() {
+                    Some(ref s) if s == "unstable-options" => {}
+                    _ => panic!("flag -Z only supports unstable-options"),
+                },
+                "--exact" => self.state.exact.set(true),
+                "--ignored" => self.state.run_ignored.set(RunIgnored::Only),
+                "--include-ignored" => self.state.run_ignored.set(RunIgnored::Yes),
+                "--list" => self.state.list.set(true),
+                "--nocapture" => self.state.nocapture.set(true),
+                "--bench" => self.state.bench.set(true),
+                "--skip" => match args.next() {
+                    Some(skip) => self.state.skip.borrow_mut().push(skip),
+                    None => panic!("flag --skip requires an argument"),
+                },
+                s if s.starts_with("--skip=") => {
+                    self.state.skip.borrow_mut().push(s["--skip=".len()..].to_string());
+                }
+                s if s.starts_with("-") => panic!("flag {} not supported", s),
+                _ => self.state.filters.borrow_mut().push(arg),
             }
-            *filter = Some(arg);
         }
     }
 
@@ -258,10 +409,6 @@ impl Context {
     /// The promise returned resolves to either `true` if all tests passed or
     /// `false` if at least one test failed.
     pub fn run(&self, tests: Vec<JsValue>) -> Promise {
-        let noun = if tests.len() == 1 { "test" } else { "tests" };
-        self.state.formatter.writeln(&format!("running {} {}", tests.len(), noun));
-        self.state.formatter.writeln("");
-
         // Execute all our test functions through their wasm shims (unclear how
         // to pass native function pointers around here). Each test will
         // execute one of the `execute_*` tests below which will push a
@@ -277,10 +424,67 @@ impl Context {
             }
         }
 
+        // With `--list` we've got the names of all the tests to run, so print
+        // them out and we're done.
+        if self.state.list.get() {
+            let remaining = self.state.remaining.borrow();
+            for test in remaining.iter().rev() {
+                let kind = if test.bench.is_some() { "bench" } else { "test" };
+                self.state.formatter.writeln(&format!("{}: {}", test.name, kind));
+            }
+            let benches = remaining.iter().filter(|t| t.bench.is_some()).count();
+            let tests = remaining.len() - benches;
+            let noun = if tests == 1 { "test" } else { "tests" };
+            let bench_noun = if benches == 1 { "benchmark" } else { "benchmarks" };
+            self.state.formatter.writeln("");
+            self.state.formatter.writeln(&format!(
+                "{} {}, {} {}",
+                tests, noun, benches, bench_noun
+            ));
+            return Promise::resolve(&JsValue::from(true));
+        }
+
+        self.state.start.set(Date::now());
+        let ignored_tests = self.state.ignored_tests.borrow();
+        let count = self.state.remaining.borrow().len() + ignored_tests.len();
+        if self.state.format.get() == Format::Pretty {
+            self.state.formatter.writeln(&report::pretty_suite_started(count));
+        } else if self.state.format.get() == Format::Json {
+            self.state.formatter.writeln(&report::json_suite_started(count));
+        }
+
+        for (name, reason) in ignored_tests.iter() {
+            self.state.report(Record {
+                name: name.clone(),
+                outcome: Outcome::Ignored(reason.map(String::from)),
+                exec_time: 0.0,
+            });
+        }
+        self.state.ignored.set(ignored_tests.len());
+
         // Now that we've collected all our tests we wrap everything up in a
         // future to actually do all the processing, and pass it out to JS as a
-        // `Promise`.
-        let future = ExecuteTests(self.state.clone()).map(JsValue::from)
+        // `Promise`. The `once` hooks run before and after all of the tests.
+        let state = self.state.clone();
+        let setup = run_hooks_once(self.state.hooks.setup_once.borrow().clone());
+        let teardown = run_hooks_once(self.state.hooks.teardown_once.borrow().clone());
+        let future = setup
+            .then(move |result| {
+                if let Err(e) = result {
+                    *state.setup_error.borrow_mut() = Some(e);
+                }
+                ExecuteTests(state.clone())
+                    .and_then(|()| teardown.then(|result| Ok((state, result))))
+            })
+            .map(|(state, teardown)| {
+                if let Err(e) = &teardown {
+                    let error = state.formatter.stringify_error(e);
+                    state.formatter.writeln(&format!("teardown hook failed:\n{}", report::tab(&error)));
+                }
+                state.print_results();
+                let all_passed = state.failures.borrow().len() == 0 && teardown.is_ok();
+                JsValue::from(all_passed)
+            })
             .map_err(|e| match e {});
         future_to_promise(future)
     }
@@ -288,6 +492,8 @@ impl Context {
 
 scoped_thread_local!(static CURRENT_OUTPUT: RefCell<Output>);
 
+thread_local!(static LAST_PANIC: RefCell<Option<String>> = RefCell::new(None));
+
 /// Handler for `console.log` invocations.
 ///
 /// If a test is currently running it takes the `args` array and stringifies
@@ -335,46 +541,170 @@ fn record(orig: &Function, args: &Array, dst: impl FnOnce(&mut Output) -> &mut S
 impl Context {
     /// Entry point for a synchronous test in wasm. The `#[wasm_bindgen_test]`
     /// macro generates invocations of this method.
-    pub fn execute_sync(&self, name: &str, f: impl FnOnce() + 'static) {
-        self.execute(name, future::lazy(|| Ok(f())));
+    ///
+    /// `should_panic` and `ignore` come from the `#[should_panic]` and
+    /// `#[ignore]` attributes of the test, holding their `expected` message
+    /// or reason if one was given.
+    pub fn execute_sync(
+        &self,
+        name: &str,
+        f: impl FnOnce() + 'static,
+        should_panic: Option<Option<&'static str>>,
+        ignore: Option<Option<&'static str>>,
+    ) {
+        self.execute(name, future::lazy(|| Ok(f())), should_panic, ignore, None, None);
     }
 
     /// Entry point for an asynchronous in wasm. The
     /// `#[wasm_bindgen_test(async)]` macro generates invocations of this
     /// method.
-    pub fn execute_async<F>(&self, name: &str, f: impl FnOnce() -> F + 'static)
+    ///
+    /// The test fails if it hasn't finished after `timeout` milliseconds, or
+    /// `DEFAULT_TIMEOUT_MS` if it's `None`, including the time taken by its
+    /// setup hooks. Its teardown hooks still run if it times out.
+    pub fn execute_async<F>(
+        &self,
+        name: &str,
+        f: impl FnOnce() -> F + 'static,
+        should_panic: Option<Option<&'static str>>,
+        ignore: Option<Option<&'static str>>,
+        timeout: Option<u32>,
+    )
+        where F: Future<Item = (), Error = JsValue> + 'static,
+    {
+        self.execute(name, future::lazy(f), should_panic, ignore, timeout, None)
+    }
+
+    /// Entry point for a benchmark in wasm. The `#[wasm_bindgen_bench]` macro
+    /// generates invocations of this method.
+    ///
+    /// With `--bench` the benchmark is measured, otherwise it's run once like
+    /// a test.
+    pub fn execute_bench(
+        &self,
+        name: &str,
+        f: impl FnOnce(&mut Bencher) + 'static,
+        ignore: Option<Option<&'static str>>,
+    ) {
+        let summary = Rc::new(Cell::new(None));
+        let measure = self.state.bench.get();
+        let result = summary.clone();
+        let bench = future::lazy(move || {
+            let mut bencher = Bencher::new(measure);
+            f(&mut bencher);
+            result.set(bencher.summary());
+            Ok(())
+        });
+        self.execute(name, bench, None, ignore, None, Some(summary))
+    }
+
+    /// Registers a synchronous setup hook. The `#[wasm_bindgen_test_setup]`
+    /// macro generates invocations of this method.
+    ///
+    /// With `once` the hook runs a single time before any test, otherwise it
+    /// runs before each test.
+    pub fn setup_sync(&self, f: impl Fn() + 'static, once: bool) {
+        let hooks = &self.state.hooks;
+        let list = if once { &hooks.setup_once } else { &hooks.setup };
+        list.borrow_mut().push(sync_hook(f));
+    }
+
+    /// Registers an asynchronous setup hook. The
+    /// `#[wasm_bindgen_test_setup(async)]` macro generates invocations of
+    /// this method.
+    pub fn setup_async<F>(&self, f: impl Fn() -> F + 'static, once: bool)
         where F: Future<Item = (), Error = JsValue> + 'static,
     {
-        self.execute(name, future::lazy(f))
+        let hooks = &self.state.hooks;
+        let list = if once { &hooks.setup_once } else { &hooks.setup };
+        list.borrow_mut().push(async_hook(f));
+    }
+
+    /// Registers a synchronous teardown hook. The
+    /// `#[wasm_bindgen_test_teardown]` macro generates invocations of this
+    /// method.
+    ///
+    /// With `once` the hook runs a single time after all tests have
+    /// finished, otherwise it runs after each test whether or not it passed.
+    pub fn teardown_sync(&self, f: impl Fn() + 'static, once: bool) {
+        let hooks = &self.state.hooks;
+        let list = if once { &hooks.teardown_once } else { &hooks.teardown };
+        list.borrow_mut().push(sync_hook(f));
+    }
+
+    /// Registers an asynchronous teardown hook. The
+    /// `#[wasm_bindgen_test_teardown(async)]` macro generates invocations of
+    /// this method.
+    pub fn teardown_async<F>(&self, f: impl Fn() -> F + 'static, once: bool)
+        where F: Future<Item = (), Error = JsValue> + 'static,
+    {
+        let hooks = &self.state.hooks;
+        let list = if once { &hooks.teardown_once } else { &hooks.teardown };
+        list.borrow_mut().push(async_hook(f));
     }
 
     fn execute(
         &self,
         name: &str,
         test: impl Future<Item = (), Error = JsValue> + 'static,
+        should_panic: Option<Option<&'static str>>,
+        ignore: Option<Option<&'static str>>,
+        timeout: Option<u32>,
+        bench: Option<Rc<Cell<Option<Summary>>>>,
     ) {
         // If our test is filtered out, record that it was filtered and move
-        // on, nothing to do here.
-        let filter = self.state.filter.borrow();
-        if let Some(filter) = &*filter {
-            if !name.contains(filter) {
-                let ignored = self.state.ignored.get();
-                self.state.ignored.set(ignored + 1);
+        // on, nothing to do here. With `--bench` only benchmarks are run.
+        let skipped = self.state.bench.get() && bench.is_none();
+        if skipped || !self.state.matches_filters(name, ignore.is_some()) {
+            let filtered_out = self.state.filtered_out.get();
+            self.state.filtered_out.set(filtered_out + 1);
+            return
+        }
+
+        // Ignored tests are likewise skipped unless they were asked for, but
+        // still show up in the output (and in `--list`).
+        if let Some(reason) = ignore {
+            if self.state.run_ignored.get() == RunIgnored::No && !self.state.list.get() {
+                self.state.ignored_tests.borrow_mut().push((name.to_string(), reason));
                 return
             }
         }
 
+        // Run the test between the setup and teardown hooks. They're looked up
+        // once the test starts so hooks registered after it still apply. The
+        // timeout covers the setup hooks too, which may be async even if the
+        // test isn't. Teardown runs once that's over, even if it timed out,
+        // and gets the default timeout of its own.
+        let hooks = self.state.hooks.clone();
+        let test = future::lazy(move || {
+            let test = Timeout {
+                test: run_hooks(hooks.setup.borrow().clone()).and_then(|()| test),
+                millis: timeout.unwrap_or(DEFAULT_TIMEOUT_MS),
+                timer: None,
+            };
+            let teardown = Timeout {
+                test: run_hooks(hooks.teardown.borrow().clone()),
+                millis: DEFAULT_TIMEOUT_MS,
+                timer: None,
+            };
+            test.then(|result| teardown.then(|teardown| result.and(teardown)))
+        });
+
         // Looks like we've got a test that needs to be executed! Push it onto
         // the list of remaining tests.
         let output = Rc::new(RefCell::new(Output::default()));
         let future = TestFuture {
             output: output.clone(),
+            capture: !self.state.nocapture.get(),
             test,
         };
         self.state.remaining.borrow_mut().push(Test {
             name: name.to_string(),
             future: Box::new(future),
             output,
+            should_panic,
+            start: 0.0,
+            bench,
         });
     }
 }
@@ -384,10 +714,10 @@ struct ExecuteTests(Rc<State>);
 enum Never {}
 
 impl Future for ExecuteTests {
-    type Item = bool;
+    type Item = ();
     type Error = Never;
 
-    fn poll(&mut self) -> Poll<bool, Never> {
+    fn poll(&mut self) -> Poll<(), Never> {
         let mut running = self.0.running.borrow_mut();
         let mut remaining = self.0.remaining.borrow_mut();
 
@@ -412,6 +742,14 @@ impl Future for ExecuteTests {
                 Some(test) => test,
                 None => break,
             };
+            if self.0.format.get() == Format::Json {
+                self.0.formatter.writeln(&report::json_test_started(&test.name));
+            }
+            test.start = Date::now();
+            if let Some(e) = &*self.0.setup_error.borrow() {
+                self.0.log_test_result(test, Err(e.clone()));
+                continue
+            }
             let result = match test.future.poll() {
                 Ok(Async::Ready(())) => Ok(()),
                 Ok(Async::NotReady) => {
@@ -433,69 +771,129 @@ impl Future for ExecuteTests {
         // so we shouldn't have any more remaining tests either.
         assert_eq!(remaining.len(), 0);
 
-        self.0.print_results();
-        let all_passed = self.0.failures.borrow().len() == 0;
-        Ok(Async::Ready(all_passed))
+        Ok(Async::Ready(()))
     }
 }
 
 impl State {
+    /// Whether the test `name` is selected by the filters passed on the
+    /// command line.
+    fn matches_filters(&self, name: &str, ignored: bool) -> bool {
+        if self.run_ignored.get() == RunIgnored::Only && !ignored {
+            return false
+        }
+        let matches = |filter: &String| {
+            if self.exact.get() {
+                name == filter
+            } else {
+                name.contains(&filter[..])
+            }
+        };
+        let filters = self.filters.borrow();
+        if filters.len() > 0 && !filters.iter().any(&matches) {
+            return false
+        }
+        !self.skip.borrow().iter().any(&matches)
+    }
+
     fn log_test_result(&self, test: Test, result: Result<(), JsValue>) {
+        let result = match test.should_panic {
+            Some(expected) => check_panic(&test, result, expected),
+            None => result,
+        };
+
         // Print out information about the test passing or failing
-        self.formatter.log_test(&test.name, &result);
+        let summary = test.bench.as_ref().and_then(|b| b.get());
+        let outcome = match (&result, summary) {
+            (Ok(()), Some(summary)) => Outcome::Bench(summary),
+            (Ok(()), None) => Outcome::Ok,
+            (Err(e), _) => Outcome::Failed(self.failure_output(&test, e)),
+        };
+        self.report(Record {
+            name: test.name.clone(),
+            outcome,
+            exec_time: (Date::now() - test.start) / 1000.0,
+        });
 
         // Save off the test for later processing when we print the final
         // results.
         match result {
+            Ok(()) if summary.is_some() => self.measured.set(self.measured.get() + 1),
             Ok(()) => self.succeeded.set(self.succeeded.get() + 1),
             Err(e) => self.failures.borrow_mut().push((test, e)),
         }
     }
 
+    fn set_format(&self, format: &str) {
+        self.format.set(match format {
+            "pretty" => Format::Pretty,
+            "json" => Format::Json,
+            "junit" => Format::Junit,
+            _ => panic!("unsupported --format {}, expected pretty, json or junit", format),
+        });
+    }
+
+    /// Reports that a test has finished or was ignored.
+    fn report(&self, record: Record) {
+        match self.format.get() {
+            Format::Pretty => self.formatter.writeln(&report::pretty_test_finished(&record)),
+            Format::Json => self.formatter.writeln(&report::json_test_finished(&record)),
+            Format::Junit => self.records.borrow_mut().push(record),
+        }
+    }
+
     fn print_results(&self) {
         let failures = self.failures.borrow();
-        if failures.len() > 0 {
-            self.formatter.writeln("\nfailures:\n");
-            for (test, error) in failures.iter() {
-                self.print_failure(test, error);
+        let exec_time = (Date::now() - self.start.get()) / 1000.0;
+        match self.format.get() {
+            Format::Pretty => {
+                let failures = failures
+                    .iter()
+                    .map(|(test, error)| (test.name.clone(), self.failure_output(test, error)))
+                    .collect::<Vec<_>>();
+                self.formatter.writeln(&report::pretty_suite_finished(
+                    &failures,
+                    self.succeeded.get(),
+                    self.ignored.get(),
+                    self.measured.get(),
+                    self.filtered_out.get(),
+                ));
             }
-            self.formatter.writeln("failures:\n");
-            for (test, _) in failures.iter() {
-                self.formatter.writeln(&format!("    {}", test.name));
+            Format::Json => {
+                self.formatter.writeln(&report::json_suite_finished(
+                    self.succeeded.get(),
+                    failures.len(),
+                    self.ignored.get(),
+                    self.measured.get(),
+                    self.filtered_out.get(),
+                    exec_time,
+                ));
+            }
+            Format::Junit => {
+                self.formatter.writeln(&report::junit(&self.records.borrow(), exec_time));
             }
         }
-        self.formatter.writeln("");
-        self.formatter.writeln(&format!(
-            "test result: {}. \
-             {} passed; \
-             {} failed; \
-             {} ignored\n",
-            if failures.len() == 0 { "ok" } else { "FAILED" },
-            self.succeeded.get(),
-            failures.len(),
-            self.ignored.get(),
-        ));
     }
 
-    fn print_failure(&self, test: &Test, error: &JsValue) {
+    /// Renders the captured output of a failed test along with the exception
+    /// it failed with.
+    fn failure_output(&self, test: &Test, error: &JsValue) -> String {
         let mut logs = String::new();
         let output = test.output.borrow();
         if output.log.len() > 0 {
             logs.push_str("log output:\n");
-            logs.push_str(&tab(&output.log));
+            logs.push_str(&report::tab(&output.log));
             logs.push_str("\n");
         }
         if output.error.len() > 0 {
             logs.push_str("error output:\n");
-            logs.push_str(&tab(&output.error));
+            logs.push_str(&report::tab(&output.error));
             logs.push_str("\n");
         }
         logs.push_str("JS exception that was thrown:\n");
         let error_string = self.formatter.stringify_error(error);
-        logs.push_str(&tab(&error_string));
-
-        let msg = format!("---- {} output ----\n{}", test.name, tab(&logs));
-        self.formatter.writeln(&msg);
+        logs.push_str(&report::tab(&error_string));
+        logs
     }
 }
 
@@ -528,6 +926,7 @@ impl State {
 /// perfect code on the first try, right? *sobs*
 struct TestFuture<F> {
     output: Rc<RefCell<Output>>,
+    capture: bool,
     test: F,
 }
 
@@ -544,19 +943,133 @@ impl<F: Future<Error = JsValue>> Future for TestFuture<F> {
     fn poll(&mut self) -> Poll<F::Item, F::Error> {
         let test = &mut self.test;
         let mut future_output = None;
-        CURRENT_OUTPUT.set(&self.output, || {
-            __wbg_test_invoke(&mut || future_output = Some(test.poll()))
-        })?;
-        future_output.unwrap()
+        LAST_PANIC.with(|p| p.borrow_mut().take());
+        let result = {
+            let mut invoke = || __wbg_test_invoke(&mut || future_output = Some(test.poll()));
+            if self.capture {
+                CURRENT_OUTPUT.set(&self.output, invoke)
+            } else {
+                invoke()
+            }
+        };
+        let result = result.and_then(|()| future_output.unwrap());
+        if result.is_err() {
+            self.output.borrow_mut().panic = LAST_PANIC.with(|p| p.borrow_mut().take());
+        }
+        result
+    }
+}
+
+fn sync_hook(f: impl Fn() + 'static) -> Hook {
+    Rc::new(move || Box::new(future::ok(f())))
+}
+
+fn async_hook<F>(f: impl Fn() -> F + 'static) -> Hook
+    where F: Future<Item = (), Error = JsValue> + 'static,
+{
+    Rc::new(move || Box::new(f()))
+}
+
+/// Runs each of `hooks` in turn, stopping at the first which fails.
+fn run_hooks(hooks: Vec<Hook>) -> Box<Future<Item = (), Error = JsValue>> {
+    let mut future: Box<Future<Item = (), Error = JsValue>> = Box::new(future::ok(()));
+    for hook in hooks {
+        future = Box::new(future.and_then(move |()| hook()));
+    }
+    future
+}
+
+/// Runs `once` hooks outside of any test, catching their panics but leaving
+/// their output uncaptured. Together they get the default timeout of a test.
+fn run_hooks_once(hooks: Vec<Hook>) -> impl Future<Item = (), Error = JsValue> {
+    TestFuture {
+        output: Default::default(),
+        capture: false,
+        test: Timeout {
+            test: future::lazy(|| run_hooks(hooks)),
+            millis: DEFAULT_TIMEOUT_MS,
+            timer: None,
+        },
+    }
+}
+
+/// Fails a test, or hooks, once they've been running for `millis`
+/// milliseconds.
+///
+/// The timer is started with `setTimeout` the first time the test is polled,
+/// and cleared as soon as the test finishes so a pending timer doesn't keep
+/// node alive after all tests are done.
+struct Timeout<F> {
+    test: F,
+    millis: u32,
+    timer: Option<(JsValue, JsFuture)>,
+}
+
+#[wasm_bindgen]
+extern {
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(closure: JsValue, millis: u32) -> JsValue;
+
+    #[wasm_bindgen(js_name = clearTimeout)]
+    fn clear_timeout(id: &JsValue);
+}
+
+impl<F: Future<Item = (), Error = JsValue>> Future for Timeout<F> {
+    type Item = ();
+    type Error = JsValue;
+
+    fn poll(&mut self) -> Poll<(), JsValue> {
+        match self.test.poll() {
+            Ok(Async::NotReady) => {}
+            result => {
+                if let Some((id, _)) = self.timer.take() {
+                    clear_timeout(&id);
+                }
+                return result
+            }
+        }
+
+        if self.timer.is_none() {
+            let millis = self.millis;
+            let mut id = None;
+            let promise = Promise::new(&mut |resolve, _reject| {
+                id = Some(set_timeout(resolve.into(), millis));
+            });
+            self.timer = Some((id.unwrap(), JsFuture::from(promise)));
+        }
+        match self.timer.as_mut().unwrap().1.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            _ => {
+                self.timer = None;
+                let msg = format!("timed out after {}ms", self.millis);
+                Err(Error::new(&msg).into())
+            }
+        }
     }
 }
 
-fn tab(s: &str) -> String {
-    let mut result = String::new();
-    for line in s.lines() {
-        result.push_str("    ");
-        result.push_str(line);
-        result.push_str("\n");
+/// Turns the `result` of a `#[should_panic]` test into whether it passed.
+fn check_panic(
+    test: &Test,
+    result: Result<(), JsValue>,
+    expected: Option<&str>,
+) -> Result<(), JsValue> {
+    if result.is_ok() {
+        return Err(Error::new("test did not panic as expected").into())
+    }
+    let output = test.output.borrow();
+    let message = match &output.panic {
+        Some(message) => message,
+        // The test threw an exception without panicking.
+        None => return result,
+    };
+    match expected {
+        Some(expected) if !message.contains(expected) => Err(Error::new(&format!(
+            "panic did not contain expected string\n      \
+             panic message: `{:?}`,\n \
+             expected substring: `{:?}`",
+            message, expected
+        )).into()),
+        _ => Ok(()),
     }
-    return result;
 }
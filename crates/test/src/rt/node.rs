@@ -34,11 +34,6 @@ impl super::Formatter for Node {
         super::js_console_log(line);
     }
 
-    fn log_test(&self, name: &str, result: &Result<(), JsValue>) {
-        let s = if result.is_ok() { "ok" } else { "FAIL" };
-        self.writeln(&format!("test {} ... {}", name, s));
-    }
-
     fn stringify_error(&self, err: &JsValue) -> String {
         // TODO: should do a checked cast to `NodeError`
         NodeError::from(err.clone()).stack()
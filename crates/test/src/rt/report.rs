@@ -0,0 +1,414 @@
+//! Test reports in the formats selected with `--format`.
+//!
+//! `wasm-bindgen-test-runner` includes this file too, to merge the results
+//! of tests run over several processes, so it only depends on `std`.
+//!
+//! The pretty format is that of libtest. The JSON format follows the events
+//! printed by libtest's unstable `--format json`, one object per line, and
+//! the JUnit format is a single XML document printed once all tests have
+//! finished.
+
+use std::fmt::Write;
+
+/// The outcome of a test which has finished, or was never run.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Ok,
+    /// The test failed, with its captured output and exception.
+    Failed(String),
+    /// The test was ignored, with the reason it was ignored if one was given.
+    Ignored(Option<String>),
+    /// The test was a benchmark which was measured.
+    Bench(Summary),
+}
+
+/// Statistics of the samples of a benchmark, in nanoseconds per iteration.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Summary {
+    /// The mean of the samples.
+    pub mean: f64,
+    /// The median of the samples.
+    pub median: f64,
+    /// The difference between the largest and smallest samples, once
+    /// outliers are clamped.
+    pub deviation: f64,
+}
+
+/// A test which has finished.
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    pub name: String,
+    pub outcome: Outcome,
+    /// How long the test took to run, in seconds.
+    pub exec_time: f64,
+}
+
+pub fn pretty_suite_started(test_count: usize) -> String {
+    let noun = if test_count == 1 { "test" } else { "tests" };
+    format!("running {} {}\n", test_count, noun)
+}
+
+pub fn pretty_test_finished(record: &Record) -> String {
+    match &record.outcome {
+        Outcome::Ok => format!("test {} ... ok", record.name),
+        Outcome::Failed(_) => format!("test {} ... FAIL", record.name),
+        Outcome::Ignored(None) => format!("test {} ... ignored", record.name),
+        Outcome::Ignored(Some(reason)) => format!("test {} ... ignored, {}", record.name, reason),
+        // Like `cargo bench`, with the mean added on.
+        Outcome::Bench(s) => format!(
+            "test {} ... bench: {:>11} ns/iter (+/- {}) mean {}",
+            record.name,
+            format_ns(s.median),
+            format_ns(s.deviation),
+            format_ns(s.mean),
+        ),
+    }
+}
+
+/// Renders the output of each of the `failures`, given as pairs of test name
+/// and output, followed by the final counts.
+pub fn pretty_suite_finished(
+    failures: &[(String, String)],
+    passed: usize,
+    ignored: usize,
+    measured: usize,
+    filtered_out: usize,
+) -> String {
+    let mut ret = String::new();
+    if failures.len() > 0 {
+        ret.push_str("\nfailures:\n\n");
+        for (name, output) in failures {
+            drop(writeln!(ret, "---- {} output ----\n{}", name, tab(output)));
+        }
+        ret.push_str("failures:\n\n");
+        for (name, _) in failures {
+            drop(writeln!(ret, "    {}", name));
+        }
+    }
+    drop(write!(
+        ret,
+        "\ntest result: {}. \
+         {} passed; \
+         {} failed; \
+         {} ignored; \
+         {} measured; \
+         {} filtered out\n",
+        if failures.len() == 0 { "ok" } else { "FAILED" },
+        passed,
+        failures.len(),
+        ignored,
+        measured,
+        filtered_out,
+    ));
+    ret
+}
+
+pub fn json_suite_started(test_count: usize) -> String {
+    format!(
+        r#"{{ "type": "suite", "event": "started", "test_count": {} }}"#,
+        test_count
+    )
+}
+
+pub fn json_test_started(name: &str) -> String {
+    format!(
+        r#"{{ "type": "test", "event": "started", "name": {} }}"#,
+        json_string(name)
+    )
+}
+
+pub fn json_test_finished(record: &Record) -> String {
+    let name = json_string(&record.name);
+    match &record.outcome {
+        Outcome::Ok => format!(
+            r#"{{ "type": "test", "name": {}, "event": "ok", "exec_time": {} }}"#,
+            name, record.exec_time
+        ),
+        Outcome::Failed(output) => format!(
+            r#"{{ "type": "test", "name": {}, "event": "failed", "exec_time": {}, "stdout": {} }}"#,
+            name,
+            record.exec_time,
+            json_string(output)
+        ),
+        Outcome::Ignored(None) => format!(
+            r#"{{ "type": "test", "name": {}, "event": "ignored" }}"#,
+            name
+        ),
+        Outcome::Ignored(Some(reason)) => format!(
+            r#"{{ "type": "test", "name": {}, "event": "ignored", "message": {} }}"#,
+            name,
+            json_string(reason)
+        ),
+        Outcome::Bench(s) => format!(
+            r#"{{ "type": "bench", "name": {}, "median": {}, "deviation": {}, "mean": {} }}"#,
+            name,
+            s.median.round(),
+            s.deviation.round(),
+            s.mean.round(),
+        ),
+    }
+}
+
+pub fn json_suite_finished(
+    passed: usize,
+    failed: usize,
+    ignored: usize,
+    measured: usize,
+    filtered_out: usize,
+    exec_time: f64,
+) -> String {
+    format!(
+        r#"{{ "type": "suite", "event": "{}", "passed": {}, "failed": {}, "ignored": {}, "measured": {}, "filtered_out": {}, "exec_time": {} }}"#,
+        if failed == 0 { "ok" } else { "failed" },
+        passed,
+        failed,
+        ignored,
+        measured,
+        filtered_out,
+        exec_time,
+    )
+}
+
+/// Renders all finished tests as a JUnit XML document.
+pub fn junit(records: &[Record], exec_time: f64) -> String {
+    let failures = records
+        .iter()
+        .filter(|r| match r.outcome {
+            Outcome::Failed(_) => true,
+            _ => false,
+        })
+        .count();
+    let skipped = records
+        .iter()
+        .filter(|r| match r.outcome {
+            Outcome::Ignored(_) => true,
+            _ => false,
+        })
+        .count();
+
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str("\n<testsuites>\n");
+    drop(writeln!(
+        xml,
+        r#"  <testsuite name="wasm-bindgen-test" errors="0" failures="{}" tests="{}" skipped="{}" time="{}">"#,
+        failures,
+        records.len(),
+        skipped,
+        exec_time,
+    ));
+    for record in records {
+        // Like libtest, split `module::path::test` into a class name of
+        // `module::path` and a test name of `test`.
+        let (class, name) = match record.name.rfind("::") {
+            Some(i) => (&record.name[..i], &record.name[i + 2..]),
+            None => ("", &record.name[..]),
+        };
+        drop(write!(
+            xml,
+            r#"    <testcase classname="{}" name="{}" time="{}""#,
+            xml_escape(class),
+            xml_escape(name),
+            record.exec_time,
+        ));
+        match &record.outcome {
+            Outcome::Ok | Outcome::Bench(_) => xml.push_str("/>\n"),
+            Outcome::Failed(output) => {
+                xml.push_str(">\n");
+                drop(writeln!(
+                    xml,
+                    r#"      <failure type="assert">{}</failure>"#,
+                    xml_escape(output)
+                ));
+                xml.push_str("    </testcase>\n");
+            }
+            Outcome::Ignored(_) => xml.push_str(">\n      <skipped/>\n    </testcase>\n"),
+        }
+    }
+    xml.push_str("  </testsuite>\n</testsuites>");
+    xml
+}
+
+/// Renders a number of nanoseconds like libtest does, as a whole number with
+/// thousands separated by commas.
+pub fn format_ns(ns: f64) -> String {
+    let digits = (ns.max(0.0).round() as u64).to_string();
+    let mut ret = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            ret.push(',');
+        }
+        ret.push(c);
+    }
+    ret
+}
+
+/// Indents every line of `s` by four spaces.
+pub fn tab(s: &str) -> String {
+    let mut result = String::new();
+    for line in s.lines() {
+        result.push_str("    ");
+        result.push_str(line);
+        result.push_str("\n");
+    }
+    return result;
+}
+
+fn json_string(s: &str) -> String {
+    let mut ret = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c if (c as u32) < 0x20 => drop(write!(ret, "\\u{:04x}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut ret = String::new();
+    for c in s.chars() {
+        match c {
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '&' => ret.push_str("&amp;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&apos;"),
+            '\n' | '\r' | '\t' => ret.push(c),
+            // XML 1.0 can't contain other control characters at all, not even
+            // as character references, so write them out like Rust would.
+            c if (c as u32) < 0x20 => drop(write!(ret, "\\u{{{:x}}}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, outcome: Outcome) -> Record {
+        Record {
+            name: name.to_string(),
+            outcome,
+            exec_time: 0.5,
+        }
+    }
+
+    #[test]
+    fn json_strings() {
+        assert_eq!(json_string("plain"), r#""plain""#);
+        assert_eq!(json_string(r#"a "quoted" \ path"#), r#""a \"quoted\" \\ path""#);
+        assert_eq!(json_string("a\nb\r\tc"), r#""a\nb\r\tc""#);
+        assert_eq!(json_string("\u{1b}[31mred\u{0}"), r#""\u001b[31mred\u0000""#);
+        assert_eq!(json_string("<&>é"), r#""<&>é""#);
+    }
+
+    #[test]
+    fn xml_escapes() {
+        assert_eq!(xml_escape("plain"), "plain");
+        assert_eq!(xml_escape("a < b && c > d"), "a &lt; b &amp;&amp; c &gt; d");
+        assert_eq!(xml_escape(r#""quoted" 'single'"#), "&quot;quoted&quot; &apos;single&apos;");
+        assert_eq!(xml_escape("a\nb\r\tc"), "a\nb\r\tc");
+        assert_eq!(xml_escape("\u{1b}[31mred\u{0}"), "\\u{1b}[31mred\\u{0}");
+    }
+
+    #[test]
+    fn json_events() {
+        assert_eq!(
+            json_test_finished(&record("a::b", Outcome::Ok)),
+            r#"{ "type": "test", "name": "a::b", "event": "ok", "exec_time": 0.5 }"#
+        );
+        assert_eq!(
+            json_test_finished(&record("t", Outcome::Failed("oh \"no\"\n".to_string()))),
+            r#"{ "type": "test", "name": "t", "event": "failed", "exec_time": 0.5, "stdout": "oh \"no\"\n" }"#
+        );
+        assert_eq!(
+            json_test_finished(&record("t", Outcome::Ignored(Some("slow".to_string())))),
+            r#"{ "type": "test", "name": "t", "event": "ignored", "message": "slow" }"#
+        );
+        assert_eq!(
+            json_suite_finished(1, 2, 3, 4, 5, 1.5),
+            r#"{ "type": "suite", "event": "failed", "passed": 1, "failed": 2, "ignored": 3, "measured": 4, "filtered_out": 5, "exec_time": 1.5 }"#
+        );
+    }
+
+    #[test]
+    fn nanoseconds() {
+        assert_eq!(format_ns(0.0), "0");
+        assert_eq!(format_ns(999.0), "999");
+        assert_eq!(format_ns(1000.0), "1,000");
+        assert_eq!(format_ns(123456.4), "123,456");
+        assert_eq!(format_ns(999999.5), "1,000,000");
+        assert_eq!(format_ns(-3.0), "0");
+    }
+
+    #[test]
+    fn pretty_report() {
+        assert_eq!(pretty_suite_started(1), "running 1 test\n");
+        assert_eq!(pretty_test_finished(&record("a::b", Outcome::Ok)), "test a::b ... ok");
+        assert_eq!(
+            pretty_test_finished(&record("t", Outcome::Ignored(Some("slow".to_string())))),
+            "test t ... ignored, slow"
+        );
+        let bench = Summary {
+            mean: 1500.4,
+            median: 1234567.0,
+            deviation: 12.0,
+        };
+        assert_eq!(
+            pretty_test_finished(&record("b", Outcome::Bench(bench))),
+            "test b ... bench:   1,234,567 ns/iter (+/- 12) mean 1,500"
+        );
+        assert_eq!(
+            pretty_suite_finished(&[], 2, 1, 0, 3),
+            "\ntest result: ok. 2 passed; 0 failed; 1 ignored; 0 measured; 3 filtered out\n"
+        );
+        let failures = [("t".to_string(), "oh no\n".to_string())];
+        assert_eq!(
+            pretty_suite_finished(&failures, 0, 0, 0, 0),
+            "
+failures:
+
+---- t output ----
+    oh no
+
+failures:
+
+    t
+
+test result: FAILED. 0 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out
+"
+        );
+    }
+
+    #[test]
+    fn junit_report() {
+        let records = [
+            record("module::nested::passes", Outcome::Ok),
+            record("top_level", Outcome::Failed("expected <1> & got '2'\u{1b}".to_string())),
+            record("module::skipped", Outcome::Ignored(None)),
+        ];
+        assert_eq!(
+            junit(&records, 2.0),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <testsuite name="wasm-bindgen-test" errors="0" failures="1" tests="3" skipped="1" time="2">
+    <testcase classname="module::nested" name="passes" time="0.5"/>
+    <testcase classname="" name="top_level" time="0.5">
+      <failure type="assert">expected &lt;1&gt; &amp; got &apos;2&apos;\u{1b}</failure>
+    </testcase>
+    <testcase classname="module" name="skipped" time="0.5">
+      <skipped/>
+    </testcase>
+  </testsuite>
+</testsuites>"#
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//! Support for printing status information of a test suite in a web worker.
+//!
+//! Workers have no DOM to render output into, so instead each line is posted
+//! back to the page which started the worker. That's done by a function the
+//! runner's worker bootstrap script defines, and the page then renders the
+//! output just like `Browser` does.
+//!
+//! Deno also looks like a worker, having a global `self` but no `document`,
+//! and the runner's script for Deno defines the same function to print each
+//! line.
+
+use wasm_bindgen::prelude::*;
+
+/// Implementation of `Formatter` for dedicated, shared and service workers.
+pub struct Worker {
+}
+
+#[wasm_bindgen]
+extern {
+    fn __wbg_test_output_writeln(line: &str);
+}
+
+impl Worker {
+    /// Attempts to create a new formatter for a worker, returning `None` if
+    /// this isn't executing in one.
+    pub fn new() -> Option<Worker> {
+        if !super::detect::is_worker() {
+            return None
+        }
+        Some(Worker { })
+    }
+}
+
+impl super::Formatter for Worker {
+    fn writeln(&self, line: &str) {
+        __wbg_test_output_writeln(line);
+    }
+
+    fn stringify_error(&self, err: &JsValue) -> String {
+        super::browser::stringify_error(err)
+    }
+}
@@ -0,0 +1,96 @@
+#![cfg(target_arch = "wasm32")]
+
+extern crate futures;
+extern crate js_sys;
+extern crate wasm_bindgen;
+extern crate wasm_bindgen_futures;
+extern crate wasm_bindgen_test;
+
+use std::cell::Cell;
+
+use futures::{future, Future};
+use js_sys::Promise;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::*;
+
+thread_local! {
+    static ONCE_SETUPS: Cell<u32> = Cell::new(0);
+    static SETUPS: Cell<u32> = Cell::new(0);
+    static ASYNC_SETUPS: Cell<u32> = Cell::new(0);
+    static TEARDOWNS: Cell<u32> = Cell::new(0);
+    static TIMING_OUT: Cell<bool> = Cell::new(false);
+}
+
+fn get(counter: &'static std::thread::LocalKey<Cell<u32>>) -> u32 {
+    counter.with(|c| c.get())
+}
+
+fn bump(counter: &'static std::thread::LocalKey<Cell<u32>>) {
+    counter.with(|c| c.set(c.get() + 1));
+}
+
+#[wasm_bindgen_test_setup(once)]
+fn once_setup() {
+    assert_eq!(get(&SETUPS), 0);
+    bump(&ONCE_SETUPS);
+}
+
+#[wasm_bindgen_test_setup]
+fn setup() {
+    // The previous test's teardown has run.
+    assert_eq!(get(&SETUPS), get(&TEARDOWNS));
+    bump(&SETUPS);
+}
+
+#[wasm_bindgen_test_setup(async)]
+fn async_setup() -> impl Future<Item = (), Error = JsValue> {
+    JsFuture::from(Promise::resolve(&JsValue::NULL)).map(|_| bump(&ASYNC_SETUPS))
+}
+
+#[wasm_bindgen_test_teardown]
+fn teardown() {
+    bump(&TEARDOWNS);
+    if TIMING_OUT.with(|t| t.replace(false)) {
+        panic!("teardown runs after a timeout");
+    }
+}
+
+// A failure here fails the whole suite.
+#[wasm_bindgen_test_teardown(once)]
+fn once_teardown() {
+    assert_eq!(get(&ONCE_SETUPS), 1);
+    assert_eq!(get(&SETUPS), get(&TEARDOWNS));
+}
+
+#[wasm_bindgen_test]
+fn once_setup_runs_once() {
+    assert_eq!(get(&ONCE_SETUPS), 1);
+}
+
+#[wasm_bindgen_test]
+fn setup_runs_before_each_test() {
+    assert_eq!(get(&SETUPS), get(&TEARDOWNS) + 1);
+    assert_eq!(get(&ASYNC_SETUPS), get(&SETUPS));
+}
+
+#[wasm_bindgen_test(async)]
+fn async_tests_run_between_hooks() -> impl Future<Item = (), Error = JsValue> {
+    JsFuture::from(Promise::resolve(&JsValue::NULL)).map(|_| {
+        assert_eq!(get(&SETUPS), get(&TEARDOWNS) + 1);
+    })
+}
+
+#[wasm_bindgen_test]
+#[should_panic(expected = "teardown still runs")]
+fn teardown_runs_after_failed_tests() {
+    panic!("teardown still runs");
+}
+
+// The test itself never finishes, so this only passes if its teardown panics.
+#[wasm_bindgen_test(async, timeout = 10)]
+#[should_panic(expected = "teardown runs after a timeout")]
+fn teardown_runs_after_timeouts() -> impl Future<Item = (), Error = JsValue> {
+    TIMING_OUT.with(|t| t.set(true));
+    future::empty()
+}
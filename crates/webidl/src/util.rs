@@ -314,6 +314,7 @@ impl<'src> FirstPassRecord<'src> {
             rust_name: rust_ident(rust_name),
             js_ret: js_ret.clone(),
             variadic,
+            slices: backend::ast::SliceMode::View,
             catch,
             structural,
             shim: {
@@ -1,4 +1,33 @@
 // For more comments about what's going on here, check out the `hello_world`
 // example
 const rust = import('./performance');
-rust.then(m => m.run());
+rust.then(m => {
+  m.run();
+  benchmark(m);
+});
+
+// Time how long it takes to pass a few kinds of strings to Rust with each of
+// the string representations
+function benchmark(m) {
+  const inputs = {
+    'short ASCII': 'hello',
+    'long ASCII': 'hello, world! '.repeat(100),
+    'long non-ASCII': 'héllo, wörld! '.repeat(100),
+  };
+  const funcs = {
+    '&str': m.utf8_len,
+    '&JsString (UTF-16)': m.utf16_len,
+  };
+  const iterations = 10000;
+
+  for (const input in inputs) {
+    for (const func in funcs) {
+      const start = performance.now();
+      for (let i = 0; i < iterations; i++) {
+        funcs[func](inputs[input]);
+      }
+      const elapsed = performance.now() - start;
+      console.log(`${input} as ${func}: ${elapsed.toFixed(2)}ms for ${iterations} calls`);
+    }
+  }
+}
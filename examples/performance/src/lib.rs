@@ -1,9 +1,11 @@
 extern crate humantime;
+extern crate js_sys;
 extern crate wasm_bindgen;
 extern crate web_sys;
 
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use js_sys::JsString;
 use wasm_bindgen::prelude::*;
 
 // lifted from the `console_log` example
@@ -32,6 +34,18 @@ pub fn run() {
     console_log!("request ended at {}", humantime::format_rfc3339(end));
 }
 
+// Called by our JS entry point to benchmark passing strings to Rust, either
+// transcoded to UTF-8 or as a handle whose UTF-16 code units are copied
+#[wasm_bindgen]
+pub fn utf8_len(s: &str) -> usize {
+    s.len()
+}
+
+#[wasm_bindgen]
+pub fn utf16_len(s: &JsString) -> usize {
+    s.to_utf16().len()
+}
+
 fn perf_to_system(amt: f64) -> SystemTime {
     let secs = (amt as u64) / 1_000;
     let nanos = ((amt as u32) % 1_000) * 1_000_000;
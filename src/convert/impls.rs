@@ -48,6 +48,16 @@ pub struct Wasm64 {
 
 unsafe impl WasmAbi for Wasm64 {}
 
+#[repr(C)]
+pub struct Wasm128 {
+    pub w0: u32,
+    pub w1: u32,
+    pub w2: u32,
+    pub w3: u32,
+}
+
+unsafe impl WasmAbi for Wasm128 {}
+
 #[repr(C)]
 pub struct WasmOptional64 {
     pub present: u32,
@@ -204,6 +214,38 @@ macro_rules! type_64 {
 
 type_64!(i64 u64);
 
+macro_rules! type_128 {
+    ($($t:tt)*) => ($(
+        impl IntoWasmAbi for $t {
+            type Abi = Wasm128;
+
+            #[inline]
+            fn into_abi(self, _extra: &mut Stack) -> Wasm128 {
+                Wasm128 {
+                    w0: self as u32,
+                    w1: (self >> 32) as u32,
+                    w2: (self >> 64) as u32,
+                    w3: (self >> 96) as u32,
+                }
+            }
+        }
+
+        impl FromWasmAbi for $t {
+            type Abi = Wasm128;
+
+            #[inline]
+            unsafe fn from_abi(js: Wasm128, _extra: &mut Stack) -> $t {
+                (js.w0 as $t)
+                    | ((js.w1 as $t) << 32)
+                    | ((js.w2 as $t) << 64)
+                    | ((js.w3 as $t) << 96)
+            }
+        }
+    )*)
+}
+
+type_128!(i128 u128);
+
 impl IntoWasmAbi for bool {
     type Abi = u32;
 
@@ -11,25 +11,26 @@ pub use self::traits::*;
 
 pub struct GlobalStack {
     next: usize,
+    // Looked up once, as with threads finding the current thread's buffer
+    // calls out to JS.
+    ptr: *mut u32,
 }
 
 impl GlobalStack {
     #[inline]
     pub unsafe fn new() -> GlobalStack {
-        GlobalStack { next: 0 }
+        use __rt::__wbindgen_global_argument_ptr as global_ptr;
+        GlobalStack { next: 0, ptr: global_ptr() }
     }
 }
 
 impl Stack for GlobalStack {
     #[inline]
     fn push(&mut self, val: u32) {
-        use __rt::{
-            __wbindgen_global_argument_ptr as global_ptr,
-            GLOBAL_STACK_CAP,
-        };
+        use __rt::GLOBAL_STACK_CAP;
         unsafe {
             assert!(self.next < GLOBAL_STACK_CAP);
-            *global_ptr().offset(self.next as isize) = val;
+            *self.ptr.offset(self.next as isize) = val;
             self.next += 1;
         }
     }
@@ -41,6 +41,8 @@ tys! {
     OPTIONAL
     UNIT
     CLAMPED
+    I128
+    U128
 }
 
 #[inline(always)] // see `interpret.rs` in the the cli-support crate
@@ -69,6 +71,8 @@ simple! {
     u32 => U32
     i64 => I64
     u64 => U64
+    i128 => I128
+    u128 => U128
     isize => I32
     usize => U32
     f32 => F32
@@ -19,7 +19,7 @@ extern crate wasm_bindgen_macro;
 use core::cell::UnsafeCell;
 use core::fmt;
 use core::mem;
-use core::ops::{Deref, DerefMut};
+use core::ops::{self, Deref, DerefMut};
 use core::ptr;
 
 use convert::FromWasmAbi;
@@ -99,6 +99,18 @@ impl JsValue {
         }
     }
 
+    /// Creates a new JS value which is a string from UTF-16 code units.
+    ///
+    /// Unlike `from_str` the code units are copied to the JS heap without
+    /// transcoding, and they don't have to be valid UTF-16.
+    pub fn from_utf16(s: &[u16]) -> JsValue {
+        unsafe {
+            JsValue {
+                idx: __wbindgen_string_new_utf16(s.as_ptr(), s.len()),
+            }
+        }
+    }
+
     /// Creates a new JS value which is a number.
     ///
     /// This function creates a JS value representing a number (a heap
@@ -247,6 +259,27 @@ impl JsValue {
         }
     }
 
+    /// Returns the UTF-16 code units of this JS value if it's an instance of
+    /// a string.
+    ///
+    /// Unlike `as_string` this doesn't transcode the string, and works for
+    /// strings which aren't valid UTF-16.
+    ///
+    /// If this JS value is not an instance of a string then this returns
+    /// `None`.
+    #[cfg(feature = "std")]
+    pub fn as_utf16(&self) -> Option<Vec<u16>> {
+        unsafe {
+            let mut len = 0;
+            let ptr = __wbindgen_string_get_utf16(self.idx, &mut len);
+            if ptr.is_null() {
+                None
+            } else {
+                Some(Vec::from_raw_parts(ptr, len, len))
+            }
+        }
+    }
+
     /// Returns the `bool` value of this JS value if it's an instance of a
     /// boolean.
     ///
@@ -286,6 +319,101 @@ impl JsValue {
     pub fn is_function(&self) -> bool {
         unsafe { __wbindgen_is_function(self.idx) == 1 }
     }
+
+    /// Tests whether the type of this JS value is `bigint`.
+    pub fn is_bigint(&self) -> bool {
+        unsafe { __wbindgen_is_bigint(self.idx) == 1 }
+    }
+
+    /// Returns the value of this JS value as an `i64` if it's a BigInt which
+    /// fits in an `i64`.
+    ///
+    /// If this JS value is not a BigInt, or it's out of range, then this
+    /// returns `None`.
+    pub fn as_i64(&self) -> Option<i64> {
+        let n = self.as_i128()?;
+        if n < i64::min_value() as i128 || n > i64::max_value() as i128 {
+            return None;
+        }
+        Some(n as i64)
+    }
+
+    /// Returns the value of this JS value as a `u64` if it's a BigInt which
+    /// fits in a `u64`.
+    ///
+    /// If this JS value is not a BigInt, or it's out of range, then this
+    /// returns `None`.
+    pub fn as_u64(&self) -> Option<u64> {
+        let n = self.as_u128()?;
+        if n > u64::max_value() as u128 {
+            return None;
+        }
+        Some(n as u64)
+    }
+
+    /// Returns the value of this JS value as an `i128` if it's a BigInt which
+    /// fits in an `i128`.
+    ///
+    /// If this JS value is not a BigInt, or it's out of range, then this
+    /// returns `None`.
+    pub fn as_i128(&self) -> Option<i128> {
+        self.bigint_get(true).map(|n| n as i128)
+    }
+
+    /// Returns the value of this JS value as a `u128` if it's a BigInt which
+    /// fits in a `u128`.
+    ///
+    /// If this JS value is not a BigInt, or it's out of range, then this
+    /// returns `None`.
+    pub fn as_u128(&self) -> Option<u128> {
+        self.bigint_get(false)
+    }
+
+    /// Returns the two's complement bits of this BigInt if it fits in 128
+    /// signed or unsigned bits.
+    fn bigint_get(&self, signed: bool) -> Option<u128> {
+        let mut ret = [0u64; 2];
+        unsafe {
+            if __wbindgen_bigint_get(self.idx, signed as u32, ret.as_mut_ptr()) == 0 {
+                return None;
+            }
+        }
+        Some(ret[0] as u128 | (ret[1] as u128) << 64)
+    }
+
+    /// Applies the JS `**` operator to this value and `rhs`.
+    ///
+    /// If the operator throws, the exception is rethrown with `throw_val`.
+    pub fn pow(&self, rhs: &JsValue) -> JsValue {
+        self.try_pow(rhs).unwrap_or_else(|e| throw_val(e))
+    }
+
+    /// Applies the JS `**` operator to this value and `rhs`, returning the
+    /// exception if it throws, such as for a negative BigInt exponent.
+    pub fn try_pow(&self, rhs: &JsValue) -> Result<JsValue, JsValue> {
+        unsafe { catch_op(|exn| __wbindgen_pow(self.idx, rhs.idx, exn)) }
+    }
+
+    /// Applies the JS unary `-` operator to this value, returning the
+    /// exception if it throws.
+    pub fn try_neg(&self) -> Result<JsValue, JsValue> {
+        unsafe { catch_op(|exn| __wbindgen_neg(self.idx, exn)) }
+    }
+}
+
+/// Calls one of the operator intrinsics, which write `1` and the exception to
+/// the two words at their last argument if the operator throws.
+unsafe fn catch_op<F>(f: F) -> Result<JsValue, JsValue>
+where
+    F: FnOnce(*mut u32) -> u32,
+{
+    let mut exn = [0u32; 2];
+    let idx = f(exn.as_mut_ptr());
+    if exn[0] == 1 {
+        Err(JsValue { idx: exn[1] })
+    } else {
+        Ok(JsValue { idx })
+    }
 }
 
 impl PartialEq for JsValue {
@@ -395,6 +523,86 @@ macro_rules! numbers {
 
 numbers! { i8 u8 i16 u16 i32 u32 f32 f64 }
 
+// Unlike the types above these convert to a BigInt rather than a number, as
+// not every value of them can be represented exactly by a JS number.
+macro_rules! bigints {
+    ($($n:ident => $signed:expr)*) => ($(
+        impl PartialEq<$n> for JsValue {
+            fn eq(&self, other: &$n) -> bool {
+                self.bigint_get($signed) == Some(*other as u128)
+            }
+        }
+
+        impl From<$n> for JsValue {
+            fn from(n: $n) -> JsValue {
+                let n = n as u128;
+                unsafe {
+                    JsValue {
+                        idx: __wbindgen_bigint_new(
+                            n as u32,
+                            (n >> 32) as u32,
+                            (n >> 64) as u32,
+                            (n >> 96) as u32,
+                            $signed as u32,
+                        ),
+                    }
+                }
+            }
+        }
+    )*)
+}
+
+bigints! { i64 => true u64 => false i128 => true u128 => false }
+
+// Operators on `&JsValue` apply the same operator in JS, so they work on
+// BigInts and numbers alike. JS throws if the types of the operands can't be
+// mixed, or when dividing a BigInt by zero, in which case the operators
+// rethrow the exception with `throw_val` and the `try_*` methods return it.
+macro_rules! operators {
+    ($($t:ident $f:ident $try_f:ident $intrinsic:ident)*) => (
+        impl JsValue {
+            $(
+                /// Applies the same JS operator as the matching operator on
+                /// `&JsValue`, returning the exception if it throws.
+                pub fn $try_f(&self, rhs: &JsValue) -> Result<JsValue, JsValue> {
+                    unsafe { catch_op(|exn| $intrinsic(self.idx, rhs.idx, exn)) }
+                }
+            )*
+        }
+
+        $(
+            impl<'a> ops::$t<&'a JsValue> for &'a JsValue {
+                type Output = JsValue;
+
+                fn $f(self, rhs: &'a JsValue) -> JsValue {
+                    self.$try_f(rhs).unwrap_or_else(|e| throw_val(e))
+                }
+            }
+        )*
+    )
+}
+
+operators! {
+    Add add try_add __wbindgen_add
+    Sub sub try_sub __wbindgen_sub
+    Mul mul try_mul __wbindgen_mul
+    Div div try_div __wbindgen_div
+    Rem rem try_rem __wbindgen_rem
+    BitAnd bitand try_bit_and __wbindgen_bit_and
+    BitOr bitor try_bit_or __wbindgen_bit_or
+    BitXor bitxor try_bit_xor __wbindgen_bit_xor
+    Shl shl try_shl __wbindgen_shl
+    Shr shr try_shr __wbindgen_shr
+}
+
+impl<'a> ops::Neg for &'a JsValue {
+    type Output = JsValue;
+
+    fn neg(self) -> JsValue {
+        self.try_neg().unwrap_or_else(|e| throw_val(e))
+    }
+}
+
 macro_rules! externs {
     ($(fn $name:ident($($args:tt)*) -> $ret:ty;)*) => (
         #[cfg(all(target_arch = "wasm32", not(target_os = "emscripten")))]
@@ -417,6 +625,7 @@ externs! {
     fn __wbindgen_object_clone_ref(idx: u32) -> u32;
     fn __wbindgen_object_drop_ref(idx: u32) -> ();
     fn __wbindgen_string_new(ptr: *const u8, len: usize) -> u32;
+    fn __wbindgen_string_new_utf16(ptr: *const u16, len: usize) -> u32;
     fn __wbindgen_number_new(f: f64) -> u32;
     fn __wbindgen_number_get(idx: u32, invalid: *mut u8) -> f64;
     fn __wbindgen_is_null(idx: u32) -> u32;
@@ -427,9 +636,21 @@ externs! {
     fn __wbindgen_is_object(idx: u32) -> u32;
     fn __wbindgen_is_function(idx: u32) -> u32;
     fn __wbindgen_is_string(idx: u32) -> u32;
+    fn __wbindgen_is_bigint(idx: u32) -> u32;
+    fn __wbindgen_bigint_new(w0: u32, w1: u32, w2: u32, w3: u32, signed: u32) -> u32;
+    fn __wbindgen_bigint_get(idx: u32, signed: u32, ret: *mut u64) -> u32;
     fn __wbindgen_string_get(idx: u32, len: *mut usize) -> *mut u8;
+    fn __wbindgen_string_get_utf16(idx: u32, len: *mut usize) -> *mut u16;
     fn __wbindgen_throw(a: *const u8, b: usize) -> !;
     fn __wbindgen_rethrow(a: u32) -> !;
+    fn __wbindgen_panic(
+        msg: *const u8,
+        msg_len: usize,
+        file: *const u8,
+        file_len: usize,
+        line: u32,
+        column: u32,
+    ) -> !;
 
     fn __wbindgen_cb_drop(idx: u32) -> ();
     fn __wbindgen_cb_forget(idx: u32) -> ();
@@ -441,7 +662,27 @@ externs! {
     fn __wbindgen_json_serialize(idx: u32, ptr: *mut *mut u8) -> usize;
     fn __wbindgen_jsval_eq(a: u32, b: u32) -> u32;
 
+    fn __wbindgen_add(a: u32, b: u32, exn: *mut u32) -> u32;
+    fn __wbindgen_sub(a: u32, b: u32, exn: *mut u32) -> u32;
+    fn __wbindgen_mul(a: u32, b: u32, exn: *mut u32) -> u32;
+    fn __wbindgen_div(a: u32, b: u32, exn: *mut u32) -> u32;
+    fn __wbindgen_rem(a: u32, b: u32, exn: *mut u32) -> u32;
+    fn __wbindgen_pow(a: u32, b: u32, exn: *mut u32) -> u32;
+    fn __wbindgen_bit_and(a: u32, b: u32, exn: *mut u32) -> u32;
+    fn __wbindgen_bit_or(a: u32, b: u32, exn: *mut u32) -> u32;
+    fn __wbindgen_bit_xor(a: u32, b: u32, exn: *mut u32) -> u32;
+    fn __wbindgen_shl(a: u32, b: u32, exn: *mut u32) -> u32;
+    fn __wbindgen_shr(a: u32, b: u32, exn: *mut u32) -> u32;
+    fn __wbindgen_neg(a: u32, exn: *mut u32) -> u32;
+
     fn __wbindgen_memory() -> u32;
+
+    fn __wbindgen_current_id() -> u32;
+    fn __wbindgen_tcb_get() -> u32;
+    fn __wbindgen_tcb_set(ptr: u32) -> ();
+
+    fn __wbindgen_anyref_table_grow(delta: u32) -> i32;
+    fn __wbindgen_anyref_table_set_null(idx: u32) -> ();
 }
 
 impl Clone for JsValue {
@@ -467,6 +708,12 @@ impl fmt::Debug for JsValue {
         if let Some(n) = self.as_bool() {
             return n.fmt(f);
         }
+        if let Some(n) = self.as_i128() {
+            return n.fmt(f);
+        }
+        if let Some(n) = self.as_u128() {
+            return n.fmt(f);
+        }
         if self.is_null() {
             return fmt::Display::fmt("null", f);
         }
@@ -601,6 +848,48 @@ pub fn throw_val(s: JsValue) -> ! {
     }
 }
 
+if_std! {
+    /// Installs a panic hook which turns Rust panics into JS exceptions.
+    ///
+    /// By default a panic in wasm traps with an `unreachable` instruction and
+    /// JS only sees an opaque `RuntimeError`. Once this is called a panic
+    /// instead throws a JS `Error` whose message contains the panic's message
+    /// and location. If the bindings were generated with `--debug` the message
+    /// also includes the Rust call stack at the point of the panic.
+    ///
+    /// This is typically called once at the start of a program, although
+    /// calling it multiple times is harmless. As with `throw_str`, destructors
+    /// are not run when the exception is thrown.
+    pub fn set_panic_hook() {
+        std::panic::set_hook(Box::new(panic_hook));
+    }
+
+    fn panic_hook(info: &std::panic::PanicInfo) {
+        let payload = info.payload();
+        let message = match payload.downcast_ref::<&'static str>() {
+            Some(s) => *s,
+            None => match payload.downcast_ref::<String>() {
+                Some(s) => &s[..],
+                None => "Box<Any>",
+            },
+        };
+        let (file, line, column) = match info.location() {
+            Some(l) => (l.file(), l.line(), l.column()),
+            None => ("<unknown>", 0, 0),
+        };
+        unsafe {
+            __wbindgen_panic(
+                message.as_ptr(),
+                message.len(),
+                file.as_ptr(),
+                file.len(),
+                line,
+                column,
+            );
+        }
+    }
+}
+
 /// Returns a handle to this wasm instance's `WebAssembly.Memory`
 pub fn memory() -> JsValue {
     unsafe {
@@ -608,6 +897,16 @@ pub fn memory() -> JsValue {
     }
 }
 
+/// Returns an identifier for the thread this is called on.
+///
+/// This is always `0` unless the bindings were generated with `--threads`, in
+/// which case the main thread is `0` and every other thread running an
+/// instance of the module has its own non-zero id. The id of a thread which
+/// was released with `releaseThread` is given to the next new thread.
+pub fn thread_id() -> u32 {
+    unsafe { __wbindgen_current_id() }
+}
+
 #[doc(hidden)]
 pub mod __rt {
     use core::cell::{Cell, UnsafeCell};
@@ -771,6 +1070,7 @@ pub mod __rt {
     if_std! {
         use std::alloc::{System, GlobalAlloc, Layout};
         use std::mem;
+        use std::prelude::v1::*;
 
         #[no_mangle]
         pub extern fn __wbindgen_malloc(size: usize) -> *mut u8 {
@@ -787,6 +1087,21 @@ pub mod __rt {
             super::throw_str("invalid malloc request");
         }
 
+        #[no_mangle]
+        pub unsafe extern fn __wbindgen_realloc(ptr: *mut u8, old_size: usize, new_size: usize) -> *mut u8 {
+            let align = mem::align_of::<usize>();
+            debug_assert!(old_size > 0);
+            debug_assert!(new_size > 0);
+            if let Ok(layout) = Layout::from_size_align(old_size, align) {
+                let ptr = System.realloc(ptr, layout, new_size);
+                if !ptr.is_null() {
+                    return ptr
+                }
+            }
+
+            super::throw_str("invalid realloc request");
+        }
+
         #[no_mangle]
         pub unsafe extern fn __wbindgen_free(ptr: *mut u8, size: usize) {
             // This happens for zero-length slices, and in that case `ptr` is
@@ -798,6 +1113,43 @@ pub mod __rt {
             let layout = Layout::from_size_align_unchecked(size, align);
             System.dealloc(ptr, layout);
         }
+
+        /// Free slots in the table of JS values used by `wasm-bindgen
+        /// --reference-types`, where `JsValue` indices refer to a table owned
+        /// by the wasm module rather than to a slab in the JS glue.
+        ///
+        /// The first `JSIDX_RESERVED` slots hold the special values like
+        /// `undefined` and are never handed out.
+        static mut ANYREF_FREE: Option<Vec<u32>> = None;
+
+        /// Number of slots the table grows by once all slots are in use.
+        const ANYREF_TABLE_GROWTH: u32 = 128;
+
+        #[no_mangle]
+        pub extern fn __wbindgen_anyref_table_alloc() -> u32 {
+            unsafe {
+                let free = ANYREF_FREE.get_or_insert_with(Vec::new);
+                if let Some(idx) = free.pop() {
+                    return idx
+                }
+                let start = super::__wbindgen_anyref_table_grow(ANYREF_TABLE_GROWTH);
+                if start == -1 {
+                    super::throw_str("failed to grow the table of JS values");
+                }
+                let start = start as u32;
+                free.extend((start + 1..start + ANYREF_TABLE_GROWTH).rev());
+                start
+            }
+        }
+
+        #[no_mangle]
+        pub unsafe extern fn __wbindgen_anyref_table_dealloc(idx: u32) {
+            if idx < super::JSIDX_RESERVED {
+                return
+            }
+            super::__wbindgen_anyref_table_set_null(idx);
+            ANYREF_FREE.get_or_insert_with(Vec::new).push(idx);
+        }
     }
 
     pub const GLOBAL_STACK_CAP: usize = 16;
@@ -807,13 +1159,52 @@ pub mod __rt {
     #[repr(align(8))]
     struct GlobalData([u32; GLOBAL_STACK_CAP]);
 
+    #[cfg(not(all(feature = "std", target_feature = "atomics")))]
     static mut GLOBAL_STACK: GlobalData = GlobalData([0; GLOBAL_STACK_CAP]);
 
+    #[cfg(not(all(feature = "std", target_feature = "atomics")))]
     #[no_mangle]
     pub unsafe extern "C" fn __wbindgen_global_argument_ptr() -> *mut u32 {
         GLOBAL_STACK.0.as_mut_ptr()
     }
 
+    // With threads each thread passes arguments through its own buffer, so
+    // calls on different threads don't clobber each other's arguments.
+    #[cfg(all(feature = "std", target_feature = "atomics"))]
+    #[no_mangle]
+    pub unsafe extern "C" fn __wbindgen_global_argument_ptr() -> *mut u32 {
+        (*thread_data()).global_stack.0.as_mut_ptr()
+    }
+
+    /// State the runtime keeps for each thread when the module's memory is
+    /// shared with other threads.
+    #[cfg(all(feature = "std", target_feature = "atomics"))]
+    struct ThreadData {
+        global_stack: GlobalData,
+    }
+
+    /// Returns the current thread's `ThreadData`, allocating it on first use.
+    ///
+    /// A pointer to it is kept in the thread control block, which the JS glue
+    /// keeps separately for each thread. When the stack of a released thread
+    /// is given to a new thread its control block is too, so the allocation
+    /// is reused rather than leaked.
+    #[cfg(all(feature = "std", target_feature = "atomics"))]
+    fn thread_data() -> *mut ThreadData {
+        unsafe {
+            let ptr = super::__wbindgen_tcb_get() as *mut ThreadData;
+            if !ptr.is_null() {
+                return ptr
+            }
+            let data = ThreadData {
+                global_stack: GlobalData([0; GLOBAL_STACK_CAP]),
+            };
+            let ptr = std::boxed::Box::into_raw(std::boxed::Box::new(data));
+            super::__wbindgen_tcb_set(ptr as u32);
+            ptr
+        }
+    }
+
     /// This is a curious function necessary to get wasm-bindgen working today,
     /// and it's a bit of an unfortunate hack.
     ///
@@ -29,6 +29,12 @@ exports.js_works = () => {
     wasm.api_acquire_string('foo', null);
     assert.strictEqual(wasm.api_acquire_string2(''), '');
     assert.strictEqual(wasm.api_acquire_string2('a'), 'a');
+    assert.strictEqual(wasm.api_acquire_string2('héllo'), 'héllo');
+    assert.strictEqual(wasm.api_acquire_string2('a😀b'), 'a😀b');
+    const long = 'x'.repeat(1000) + '日本語';
+    assert.strictEqual(wasm.api_acquire_string2(long), long);
+    assert.deepStrictEqual(wasm.api_utf16('a\ud800€'), new Uint16Array([0x61, 0xd800, 0x20ac]));
+    assert.strictEqual(wasm.api_utf16_string(new Uint16Array([0x61, 0xd800, 0x20ac])), 'a\ud800€');
 };
 
 exports.js_eq_works = () => {
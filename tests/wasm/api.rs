@@ -117,6 +117,17 @@ pub fn api_acquire_string2(a: &JsValue) -> String {
     a.as_string().unwrap_or("wrong".to_string())
 }
 
+#[wasm_bindgen]
+pub fn api_utf16(a: &JsValue) -> Vec<u16> {
+    assert_eq!(JsValue::from(1).as_utf16(), None);
+    a.as_utf16().unwrap()
+}
+
+#[wasm_bindgen]
+pub fn api_utf16_string(a: &[u16]) -> JsValue {
+    JsValue::from_utf16(a)
+}
+
 #[wasm_bindgen_test]
 fn eq_works() {
     js_eq_works();
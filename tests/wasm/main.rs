@@ -26,10 +26,12 @@ pub mod math;
 pub mod node;
 pub mod option;
 pub mod optional_primitives;
+pub mod panic_hook;
 pub mod rethrow;
 pub mod simple;
 pub mod slice;
 pub mod structural;
+pub mod test_attributes;
 pub mod u64;
 pub mod validate_prt;
 pub mod variadic;
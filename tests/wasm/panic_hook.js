@@ -0,0 +1,18 @@
+const wasm = require('wasm-bindgen-test.js');
+const assert = require('assert');
+
+exports.call_panicking = function() {
+  let error = null;
+  try {
+    wasm.panic_with_message();
+  } catch (e) {
+    error = e;
+  }
+  assert.ok(error instanceof Error);
+  assert.ok(
+    error.message.startsWith("panicked at 'oh no 1', tests/wasm/panic_hook.rs:13:5"),
+    error.message
+  );
+  // Tests are run with `--debug`, which appends the Rust stack.
+  assert.ok(error.message.includes('\n\nRust stack:\n    at '), error.message);
+};
@@ -0,0 +1,24 @@
+use std::panic;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen(module = "tests/wasm/panic_hook.js")]
+extern {
+    fn call_panicking();
+}
+
+#[wasm_bindgen]
+pub fn panic_with_message() {
+    panic!("oh no {}", 1);
+}
+
+#[wasm_bindgen_test]
+fn panics_throw_errors() {
+    // The test harness relies on its own hook to report panics, so put it
+    // back afterwards.
+    let hook = panic::take_hook();
+    wasm_bindgen::set_panic_hook();
+    call_panicking();
+    panic::set_hook(hook);
+}
@@ -212,3 +212,12 @@ exports.js_clamped = (a, offset) => {
   assert.equal(a[1], offset + 1);
   assert.equal(a[2], offset + 2);
 };
+
+exports.js_grow_and_fill = (a, memory) => {
+  memory.grow(1);
+  assert.equal(a.length, 3);
+  for (let i = 0; i < a.length; i++) {
+    assert.equal(a[i], i + 1);
+    a[i] += 1;
+  }
+};
@@ -19,6 +19,13 @@ extern {
     fn js_clamped2(val: Clamped<Vec<u8>>, offset: u8);
     #[wasm_bindgen(js_name = js_clamped)]
     fn js_clamped3(val: Clamped<&mut [u8]>, offset: u8);
+
+    #[wasm_bindgen(slices = copy)]
+    fn js_grow_and_fill(a: &mut [u8], memory: &JsValue);
+    #[wasm_bindgen(slices = copy_in_out, js_name = js_grow_and_fill)]
+    fn js_grow_and_fill2(a: &mut [u8], memory: &JsValue);
+    #[wasm_bindgen(slices = copy_in_out, js_name = js_grow_and_fill)]
+    fn js_grow_and_fill3(a: &mut [f32], memory: &JsValue);
 }
 
 macro_rules! export_macro {
@@ -222,3 +229,15 @@ fn take_clamped() {
     js_clamped2(Clamped(vec![4, 5, 6]), 4);
     js_clamped3(Clamped(&mut [7, 8, 9]), 7);
 }
+
+#[wasm_bindgen_test]
+fn import_slices_with_memory_growth() {
+    let mut a = [1, 2, 3];
+    js_grow_and_fill(&mut a, &wasm_bindgen::memory());
+    assert_eq!(a, [1, 2, 3]);
+    js_grow_and_fill2(&mut a, &wasm_bindgen::memory());
+    assert_eq!(a, [2, 3, 4]);
+    let mut b = [1.0, 2.0, 3.0];
+    js_grow_and_fill3(&mut b, &wasm_bindgen::memory());
+    assert_eq!(b, [2.0, 3.0, 4.0]);
+}
@@ -0,0 +1,27 @@
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen_test]
+#[should_panic]
+fn should_panic() {
+    panic!("any message");
+}
+
+#[wasm_bindgen_test]
+#[should_panic(expected = "out of bounds")]
+fn should_panic_with_expected_message() {
+    let v: Vec<u32> = Vec::new();
+    let i = v.len();
+    let _x = v[i];
+}
+
+#[wasm_bindgen_test]
+#[ignore]
+fn ignored() {
+    panic!("ignored tests aren't run");
+}
+
+#[wasm_bindgen_test]
+#[ignore = "checks ignored tests with a reason aren't run"]
+fn ignored_with_reason() {
+    panic!("ignored tests aren't run");
+}
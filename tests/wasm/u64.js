@@ -3,6 +3,8 @@ const assert = require('assert');
 
 exports.i64_js_identity = a => a;
 exports.u64_js_identity = a => a;
+exports.i128_js_identity = a => a;
+exports.u128_js_identity = a => a;
 
 exports.js_works = () => {
     assert.strictEqual(wasm.zero(), BigInt('0'));
@@ -25,6 +27,15 @@ exports.js_works = () => {
     assert.strictEqual(wasm.i64_rust_identity(i64_min), i64_min);
     assert.strictEqual(wasm.u64_rust_identity(u64_max), u64_max);
 
+    const u128_max = (BigInt('1') << BigInt('128')) - BigInt('1');
+    const i128_min = -(BigInt('1') << BigInt('127'));
+    assert.strictEqual(wasm.u128_max(), u128_max);
+    assert.strictEqual(wasm.i128_min(), i128_min);
+    assert.strictEqual(wasm.i128_rust_identity(BigInt('-1')), BigInt('-1'));
+    assert.strictEqual(wasm.i128_rust_identity(i128_min), i128_min);
+    assert.strictEqual(wasm.u128_rust_identity(u128_max), u128_max);
+    assert.strictEqual(wasm.u128_rust_identity(u64_max + BigInt('1')), u64_max + BigInt('1'));
+
     assert.deepStrictEqual(wasm.u64_slice([]), new BigUint64Array());
     assert.deepStrictEqual(wasm.i64_slice([]), new BigInt64Array());
     const arr1 = new BigUint64Array([BigInt('1'), BigInt('2')]);
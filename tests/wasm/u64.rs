@@ -5,6 +5,8 @@ use wasm_bindgen::prelude::*;
 extern {
     fn i64_js_identity(a: i64) -> i64;
     fn u64_js_identity(a: u64) -> u64;
+    fn i128_js_identity(a: i128) -> i128;
+    fn u128_js_identity(a: u128) -> u128;
     fn js_works();
 }
 
@@ -35,6 +37,18 @@ pub fn i64_rust_identity(a: i64) -> i64 { i64_js_identity(a) }
 #[wasm_bindgen]
 pub fn u64_rust_identity(a: u64) -> u64 { u64_js_identity(a) }
 
+#[wasm_bindgen]
+pub fn i128_min() -> i128 { i128::min_value() }
+
+#[wasm_bindgen]
+pub fn u128_max() -> u128 { u128::max_value() }
+
+#[wasm_bindgen]
+pub fn i128_rust_identity(a: i128) -> i128 { i128_js_identity(a) }
+
+#[wasm_bindgen]
+pub fn u128_rust_identity(a: u128) -> u128 { u128_js_identity(a) }
+
 #[wasm_bindgen]
 pub fn i64_slice(a: &[i64]) -> Vec<i64> { a.to_vec() }
 
@@ -45,3 +59,36 @@ pub fn u64_slice(a: &[u64]) -> Vec<u64> { a.to_vec() }
 fn works() {
     js_works();
 }
+
+#[wasm_bindgen_test]
+fn bigint_values() {
+    let a = JsValue::from(-3i64);
+    assert!(a.is_bigint());
+    assert!(!JsValue::from(3).is_bigint());
+    assert_eq!(a.as_i64(), Some(-3));
+    assert_eq!(a.as_u64(), None);
+    assert_eq!(a, -3i64);
+
+    let max = JsValue::from(u128::max_value());
+    assert_eq!(max.as_u128(), Some(u128::max_value()));
+    assert_eq!(max.as_i128(), None);
+    assert_eq!(max.as_u64(), None);
+    assert_eq!(JsValue::from(i128::min_value()).as_i128(), Some(i128::min_value()));
+
+    let b = JsValue::from(4u64);
+    assert_eq!(&a + &b, 1i64);
+    assert_eq!(&a * &b, -12i64);
+    assert_eq!(&b << &JsValue::from(64u64), 1u128 << 66);
+    assert_eq!(-&a, 3i64);
+    assert_eq!(b.pow(&JsValue::from(3u64)), 64u64);
+}
+
+#[wasm_bindgen_test]
+fn bigint_operator_exceptions() {
+    let a = JsValue::from(3u64);
+    assert_eq!(a.try_add(&JsValue::from(1u64)).unwrap(), 4u64);
+    assert!(a.try_add(&JsValue::from(1)).unwrap_err().is_object());
+    assert!(a.try_div(&JsValue::from(0u64)).is_err());
+    assert!(a.try_pow(&JsValue::from(-1i64)).is_err());
+    assert_eq!(a.try_neg().unwrap(), -3i64);
+}